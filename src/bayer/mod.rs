@@ -0,0 +1,95 @@
+//!
+//! Colour filter array (Bayer) layouts of colour sensors.
+//!
+//! The pattern names the colours of the top-left 2x2 block of the sensor, read
+//! left-to-right then top-to-bottom; e.g. `RGGB` has red at (0, 0) and blue at (1, 1).
+//! [`demosaic`] interpolates full-colour frames from the raw mosaic.
+//!
+
+use crate::svbony::SVBBayerPattern;
+
+mod demosaic;
+
+pub use demosaic::{demosaic, DemosaicMethod};
+
+/// Errors processing Bayer frames
+#[derive(Debug, thiserror::Error)]
+pub enum BayerError {
+    #[error("Frame size {width}x{height} is smaller than one Bayer block")]
+    TooSmall { width: u32, height: u32 },
+}
+
+/// Bayer colour filter array pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BayerPattern {
+    RGGB,
+    BGGR,
+    GRBG,
+    GBRG,
+}
+
+/// Colour of a single photosite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfaColor {
+    Red,
+    Green,
+    Blue,
+}
+
+impl BayerPattern {
+    /// Colour of the photosite at the given pixel coordinates
+    pub fn color_at(&self, x: u32, y: u32) -> CfaColor {
+        let block = match self {
+            BayerPattern::RGGB => [
+                CfaColor::Red,
+                CfaColor::Green,
+                CfaColor::Green,
+                CfaColor::Blue,
+            ],
+            BayerPattern::BGGR => [
+                CfaColor::Blue,
+                CfaColor::Green,
+                CfaColor::Green,
+                CfaColor::Red,
+            ],
+            BayerPattern::GRBG => [
+                CfaColor::Green,
+                CfaColor::Red,
+                CfaColor::Blue,
+                CfaColor::Green,
+            ],
+            BayerPattern::GBRG => [
+                CfaColor::Green,
+                CfaColor::Blue,
+                CfaColor::Red,
+                CfaColor::Green,
+            ],
+        };
+        block[((y % 2) * 2 + x % 2) as usize]
+    }
+}
+
+impl From<SVBBayerPattern> for BayerPattern {
+    fn from(pattern: SVBBayerPattern) -> Self {
+        match pattern {
+            SVBBayerPattern::RG => BayerPattern::RGGB,
+            SVBBayerPattern::BG => BayerPattern::BGGR,
+            SVBBayerPattern::GR => BayerPattern::GRBG,
+            SVBBayerPattern::GB => BayerPattern::GBRG,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_at() {
+        assert_eq!(BayerPattern::RGGB.color_at(0, 0), CfaColor::Red);
+        assert_eq!(BayerPattern::RGGB.color_at(3, 3), CfaColor::Blue);
+        assert_eq!(BayerPattern::GBRG.color_at(1, 0), CfaColor::Blue);
+        assert_eq!(BayerPattern::GRBG.color_at(0, 1), CfaColor::Blue);
+        assert_eq!(BayerPattern::BGGR.color_at(2, 1), CfaColor::Green);
+    }
+}
@@ -0,0 +1,453 @@
+//!
+//! Detection and correction of defective (hot and dead) pixels.
+//!
+//! Each pixel is compared with the median of its neighbours of the same colour;
+//! a pixel whose residual exceeds a multiple of the robust standard deviation of
+//! all residuals (1.4826 times their median absolute deviation) is defective.
+//! Hot pixels are found in darks, where they stand above their neighbours, and dead
+//! pixels in flats, where they fall below.
+//!
+//! On a Bayer sensor the neighbours of the same colour are two pixels away, plus the
+//! diagonal neighbours of green pixels; on a monochrome sensor they are the eight
+//! adjacent pixels.  Defects are corrected with the mean of their non-defective
+//! neighbours.
+//!
+
+use super::CalibrationError;
+use crate::bayer::{BayerPattern, CfaColor};
+use crate::stacking::{stack, StackMethod};
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+use rgb::Gray;
+use std::io::{BufRead, BufReader, Write};
+
+/// Offsets of the neighbours of the same colour as the pixel at (x, y)
+fn neighbor_offsets(cfa: Option<BayerPattern>, x: u32, y: u32) -> &'static [(i32, i32)] {
+    const MONO: [(i32, i32); 8] = [
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+        (-1, 0),
+        (1, 0),
+        (-1, 1),
+        (0, 1),
+        (1, 1),
+    ];
+    const RED_BLUE: [(i32, i32); 8] = [
+        (-2, -2),
+        (0, -2),
+        (2, -2),
+        (-2, 0),
+        (2, 0),
+        (-2, 2),
+        (0, 2),
+        (2, 2),
+    ];
+    const GREEN: [(i32, i32); 8] = [
+        (-1, -1),
+        (1, -1),
+        (-1, 1),
+        (1, 1),
+        (0, -2),
+        (-2, 0),
+        (2, 0),
+        (0, 2),
+    ];
+    match cfa.map(|p| p.color_at(x, y)) {
+        None => &MONO,
+        Some(CfaColor::Green) => &GREEN,
+        Some(_) => &RED_BLUE,
+    }
+}
+
+/// Values of the in-bounds neighbours of (x, y) for which `keep` is true
+fn neighbors<'a>(
+    width: u32,
+    height: u32,
+    cfa: Option<BayerPattern>,
+    x: u32,
+    y: u32,
+    keep: impl Fn(usize) -> bool + 'a,
+) -> impl Iterator<Item = usize> + 'a {
+    neighbor_offsets(cfa, x, y)
+        .iter()
+        .filter_map(move |(dx, dy)| {
+            let (nx, ny) = (x as i64 + *dx as i64, y as i64 + *dy as i64);
+            if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                return None;
+            }
+            let idx = ny as usize * width as usize + nx as usize;
+            keep(idx).then_some(idx)
+        })
+}
+
+/// Median of a non-empty slice
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_unstable_by(f64::total_cmp);
+    let n = values.len();
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        (values[n / 2 - 1] + values[n / 2]) / 2.0
+    }
+}
+
+/// Map of defective pixels of a sensor
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefectMap {
+    pub width: u32,
+    pub height: u32,
+    /// Whether each pixel, in row-major order, is defective
+    mask: Vec<bool>,
+}
+
+impl DefectMap {
+    /// An empty map, with no defective pixels
+    pub fn new(width: u32, height: u32) -> Self {
+        DefectMap {
+            width,
+            height,
+            mask: vec![false; width as usize * height as usize],
+        }
+    }
+
+    /// Index of the pixel at (x, y) in row-major order
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    /// Whether the pixel at (x, y) is defective
+    pub fn is_defective(&self, x: u32, y: u32) -> bool {
+        self.mask[self.index(x, y)]
+    }
+
+    /// Mark the pixel at (x, y) as defective
+    pub fn mark(&mut self, x: u32, y: u32) {
+        let i = self.index(x, y);
+        self.mask[i] = true;
+    }
+
+    /// Coordinates of the defective pixels
+    pub fn defects(&self) -> Vec<(u32, u32)> {
+        self.mask
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| **d)
+            .map(|(i, _)| {
+                let width = self.width as usize;
+                ((i % width) as u32, (i / width) as u32)
+            })
+            .collect()
+    }
+
+    /// Number of defective pixels
+    pub fn len(&self) -> usize {
+        self.mask.iter().filter(|d| **d).count()
+    }
+
+    /// Whether the map has no defective pixels
+    pub fn is_empty(&self) -> bool {
+        !self.mask.iter().any(|d| *d)
+    }
+
+    /// Add the defects of another map of the same size.
+    ///
+    /// # Arguments
+    /// `other` - The map to merge.
+    ///
+    /// # Returns
+    /// An empty Result, or an error if the map sizes differ.
+    ///
+    pub fn merge(&mut self, other: &DefectMap) -> Result<(), CalibrationError> {
+        self.check_size(other.width, other.height)?;
+        self.mask
+            .iter_mut()
+            .zip(other.mask.iter())
+            .for_each(|(a, b)| *a |= b);
+        Ok(())
+    }
+
+    fn check_size(&self, width: u32, height: u32) -> Result<(), CalibrationError> {
+        if (width, height) != (self.width, self.height) {
+            return Err(CalibrationError::SizeMismatch {
+                expected: (self.width, self.height),
+                found: (width, height),
+            });
+        }
+        Ok(())
+    }
+
+    /// Flag pixels of a frame whose residual from the local median exceeds `sigma`
+    /// robust standard deviations, above (`hot`) or below (`!hot`)
+    fn detect<T: MonoPixel>(
+        frames: &[MonoFrameData<T>],
+        sigma: f64,
+        cfa: Option<BayerPattern>,
+        hot: bool,
+    ) -> Result<Self, CalibrationError> {
+        let frame = stack(frames, StackMethod::Median).map_err(|e| match e {
+            crate::stacking::StackError::NoFrames => CalibrationError::NoFrames,
+            crate::stacking::StackError::SizeMismatch { expected, found } => {
+                CalibrationError::SizeMismatch { expected, found }
+            }
+        })?;
+        let (width, height) = (frame.width, frame.height);
+        if frame.data.is_empty() {
+            return Err(CalibrationError::EmptyFrame);
+        }
+        let values = frame.to_f64_values();
+
+        let mut local = Vec::with_capacity(8);
+        let residuals: Vec<f64> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                local.clear();
+                local.extend(neighbors(width, height, cfa, x, y, |_| true).map(|i| values[i]));
+                // A pixel with no neighbours of its colour cannot be judged
+                if local.is_empty() {
+                    return 0.0;
+                }
+                values[y as usize * width as usize + x as usize] - median(&mut local)
+            })
+            .collect();
+
+        // The MAD of quantized data is often zero, so the robust standard deviation
+        // is kept to at least one data unit
+        let mut abs: Vec<f64> = residuals.iter().map(|r| r.abs()).collect();
+        let threshold = sigma * (1.4826 * median(&mut abs)).max(1.0);
+
+        Ok(DefectMap {
+            width,
+            height,
+            mask: residuals
+                .iter()
+                .map(|r| if hot { *r > threshold } else { -*r > threshold })
+                .collect(),
+        })
+    }
+
+    /// Detect hot pixels from a set of dark frames.
+    ///
+    /// The darks are median-combined, and pixels more than `sigma` robust standard
+    /// deviations above the median of their neighbours are marked.
+    ///
+    /// # Arguments
+    /// `darks` - Dark frames, all of the same size.
+    /// `sigma` - Detection threshold in standard deviations, e.g. 5.
+    /// `cfa` - Bayer pattern of a colour sensor, or `None` for monochrome.
+    ///
+    /// # Returns
+    /// The map of hot pixels, or an error if no frames were given or their sizes differ.
+    ///
+    pub fn detect_hot<T: MonoPixel>(
+        darks: &[MonoFrameData<T>],
+        sigma: f64,
+        cfa: Option<BayerPattern>,
+    ) -> Result<Self, CalibrationError> {
+        Self::detect(darks, sigma, cfa, true)
+    }
+
+    /// Detect dead (low response) pixels from a set of flat frames.
+    ///
+    /// The flats are median-combined, and pixels more than `sigma` robust standard
+    /// deviations below the median of their neighbours are marked.
+    ///
+    /// # Arguments
+    /// `flats` - Flat frames, all of the same size.
+    /// `sigma` - Detection threshold in standard deviations, e.g. 5.
+    /// `cfa` - Bayer pattern of a colour sensor, or `None` for monochrome.
+    ///
+    /// # Returns
+    /// The map of dead pixels, or an error if no frames were given or their sizes differ.
+    ///
+    pub fn detect_dead<T: MonoPixel>(
+        flats: &[MonoFrameData<T>],
+        sigma: f64,
+        cfa: Option<BayerPattern>,
+    ) -> Result<Self, CalibrationError> {
+        Self::detect(flats, sigma, cfa, false)
+    }
+
+    /// Replace defective pixels with the mean of their non-defective neighbours of
+    /// the same colour.
+    ///
+    /// # Arguments
+    /// `frame` - The frame to correct.
+    /// `cfa` - Bayer pattern of a colour sensor, or `None` for monochrome.
+    ///
+    /// # Returns
+    /// The corrected frame, or an error if the frame size does not match the map.
+    ///
+    pub fn correct<T: MonoPixel>(
+        &self,
+        frame: &MonoFrameData<T>,
+        cfa: Option<BayerPattern>,
+    ) -> Result<MonoFrameData<T>, CalibrationError> {
+        self.check_size(frame.width, frame.height)?;
+        let mut out = frame.clone();
+        for (x, y) in self.defects() {
+            let (sum, n) = neighbors(self.width, self.height, cfa, x, y, |i| !self.mask[i])
+                .fold((0.0, 0), |(s, n), i| {
+                    (s + frame.data[i].value().to_f64().unwrap_or(0.0), n + 1)
+                });
+            if n > 0 {
+                let v = (sum / n as f64).round();
+                out.data[self.index(x, y)] = Gray::new(T::from(v).unwrap_or(T::zero()));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Save the map as a text file: a `width,height` line followed by an `x,y` line
+    /// for each defective pixel.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the map to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save(&self, filename: &str) -> Result<(), CalibrationError> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        writeln!(file, "{},{}", self.width, self.height)?;
+        for (x, y) in self.defects() {
+            writeln!(file, "{},{}", x, y)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Load a map saved by [`DefectMap::save`].
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to load.
+    ///
+    /// # Returns
+    /// The map, or an error if the file could not be read or is invalid.
+    ///
+    pub fn load(filename: &str) -> Result<Self, CalibrationError> {
+        let bad = |line: &str| {
+            CalibrationError::File(crate::FrameFileError::Format(format!(
+                "invalid defect map line: {}",
+                line
+            )))
+        };
+        let parse = |line: &str| -> Result<(u32, u32), CalibrationError> {
+            let (a, b) = line.trim().split_once(',').ok_or_else(|| bad(line))?;
+            Ok((
+                a.parse().map_err(|_| bad(line))?,
+                b.parse().map_err(|_| bad(line))?,
+            ))
+        };
+        let mut lines = BufReader::new(std::fs::File::open(filename)?).lines();
+        let first = lines.next().ok_or_else(|| bad(""))??;
+        let (width, height) = parse(&first)?;
+        let mut map = DefectMap::new(width, height);
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (x, y) = parse(&line)?;
+            if x >= width || y >= height {
+                return Err(bad(&line));
+            }
+            map.mark(x, y);
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defects() {
+        let mut next = crate::test_util::lcg(12345);
+        let mut noise = move || ((next() >> 16) % 7) as u16;
+        let darks: Vec<MonoFrameData<u16>> = (0..3)
+            .map(|_| {
+                let mut f = MonoFrameData::<u16> {
+                    width: 16,
+                    height: 12,
+                    data: (0..192).map(|_| Gray::new(100 + noise())).collect(),
+                };
+                f.data[5 * 16 + 7] = Gray::new(4000);
+                f
+            })
+            .collect();
+        let cfa = Some(BayerPattern::RGGB);
+        let map = DefectMap::detect_hot(&darks, 6.0, cfa).unwrap();
+        assert_eq!(map.defects(), vec![(7, 5)]);
+
+        let fixed = map.correct(&darks[0], cfa).unwrap();
+        let v = fixed.data[5 * 16 + 7].value();
+        assert!((100..107).contains(&v));
+
+        let mut flat = MonoFrameData::<u16> {
+            width: 16,
+            height: 12,
+            data: vec![Gray::new(30000); 192],
+        };
+        flat.data[2 * 16 + 3] = Gray::new(50);
+        let mut dead = DefectMap::detect_dead(&[flat], 6.0, None).unwrap();
+        assert_eq!(dead.defects(), vec![(3, 2)]);
+        dead.merge(&map).unwrap();
+        assert_eq!(dead.len(), 2);
+
+        let filename = std::env::temp_dir().join("camera_test_defects.csv");
+        let filename = filename.to_str().unwrap();
+        dead.save(filename).unwrap();
+        assert_eq!(DefectMap::load(filename).unwrap(), dead);
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_defects_quantized() {
+        // A dark with only two levels has a MAD of zero
+        let mut next = crate::test_util::lcg(7);
+        let mut dark = MonoFrameData::<u16> {
+            width: 8,
+            height: 8,
+            data: (0..64)
+                .map(|_| Gray::new(100 + (next() >> 16).is_multiple_of(3) as u16))
+                .collect(),
+        };
+        let map = DefectMap::detect_hot(std::slice::from_ref(&dark), 5.0, None).unwrap();
+        assert!(map.is_empty(), "{:?}", map.defects());
+        dark.data[27] = Gray::new(140);
+        let map = DefectMap::detect_hot(&[dark], 5.0, None).unwrap();
+        assert_eq!(map.defects(), vec![(3, 3)]);
+    }
+
+    #[test]
+    fn test_defects_small_frames() {
+        // Pixels without neighbours of their colour are never flagged
+        for (width, height) in [(1, 1), (2, 1)] {
+            let frame = MonoFrameData::<u16> {
+                width,
+                height,
+                data: vec![Gray::new(1000); (width * height) as usize],
+            };
+            let cfa = Some(BayerPattern::RGGB);
+            assert!(DefectMap::detect_hot(&[frame], 5.0, cfa)
+                .unwrap()
+                .is_empty());
+        }
+        let empty = MonoFrameData::<u16> {
+            width: 0,
+            height: 0,
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            DefectMap::detect_hot(&[empty], 5.0, None),
+            Err(CalibrationError::EmptyFrame)
+        ));
+        assert!(matches!(
+            DefectMap::detect_hot::<u16>(&[], 5.0, None),
+            Err(CalibrationError::NoFrames)
+        ));
+    }
+}
@@ -0,0 +1,406 @@
+//!
+//! Persistent library of master darks.
+//!
+//! The library is a directory holding each master dark as a `float32` NumPy
+//! `.npy` file, with an `index.csv` recording the size, exposure, gain, binning and
+//! sensor temperature of each one.  For an incoming frame the library selects the
+//! darks of matching size, binning and gain whose temperature is within tolerance,
+//! then prefers:
+//! 1. a dark of the same exposure, used as is;
+//! 2. two bias-subtracted darks bracketing the exposure, linearly interpolated;
+//! 3. the bias-subtracted dark of nearest exposure, scaled to the exposure.
+//!
+
+use super::CalibrationError;
+use super::MasterFrame;
+use crate::CameraFrame;
+use crate::Pixel;
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+const INDEX_FILE: &str = "index.csv";
+const INDEX_HEADER: &str = "file,width,height,exposure,gain,binning,temperature,bias_subtracted";
+
+/// Relative difference below which two exposures are considered equal
+const EXPOSURE_EPS: f64 = 1.0e-3;
+
+/// Tolerances used when matching darks to frames
+#[derive(Debug, Clone, Copy)]
+pub struct MatchTolerance {
+    /// Maximum sensor temperature difference, in degrees Celsius
+    pub temperature: f64,
+    /// Maximum gain difference
+    pub gain: f64,
+}
+
+impl Default for MatchTolerance {
+    fn default() -> Self {
+        MatchTolerance {
+            temperature: 2.0,
+            gain: 1.0e-6,
+        }
+    }
+}
+
+/// Index entry of a master dark in the library
+#[derive(Debug, Clone, PartialEq)]
+pub struct DarkEntry {
+    /// Name of the `.npy` file within the library directory
+    pub file: String,
+    pub width: u32,
+    pub height: u32,
+    pub exposure: f64,
+    pub gain: Option<f64>,
+    pub binning: Option<u32>,
+    pub temperature: Option<f64>,
+    pub bias_subtracted: bool,
+}
+
+impl DarkEntry {
+    fn to_csv(&self) -> String {
+        let opt = |v: Option<String>| v.unwrap_or_default();
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            self.file,
+            self.width,
+            self.height,
+            self.exposure,
+            opt(self.gain.map(|v| v.to_string())),
+            opt(self.binning.map(|v| v.to_string())),
+            opt(self.temperature.map(|v| v.to_string())),
+            self.bias_subtracted
+        )
+    }
+
+    fn from_csv(line: &str) -> Result<Self, CalibrationError> {
+        let bad = || {
+            CalibrationError::File(crate::FrameFileError::Format(format!(
+                "invalid dark library index line: {}",
+                line
+            )))
+        };
+        let fields: Vec<&str> = line.trim_end().split(',').collect();
+        if fields.len() != 8 {
+            return Err(bad());
+        }
+        fn opt<V: std::str::FromStr>(s: &str) -> Result<Option<V>, ()> {
+            match s {
+                "" => Ok(None),
+                s => s.parse().map(Some).map_err(|_| ()),
+            }
+        }
+        Ok(DarkEntry {
+            file: fields[0].to_string(),
+            width: fields[1].parse().map_err(|_| bad())?,
+            height: fields[2].parse().map_err(|_| bad())?,
+            exposure: fields[3].parse().map_err(|_| bad())?,
+            gain: opt(fields[4]).map_err(|_| bad())?,
+            binning: opt(fields[5]).map_err(|_| bad())?,
+            temperature: opt(fields[6]).map_err(|_| bad())?,
+            bias_subtracted: fields[7].parse().map_err(|_| bad())?,
+        })
+    }
+
+    /// Whether the entry describes the same camera settings as a master dark
+    fn same_settings(&self, dark: &MasterFrame) -> bool {
+        let close = |a: Option<f64>, b: Option<f64>, eps: f64| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() <= eps,
+            (None, None) => true,
+            _ => false,
+        };
+        (self.width, self.height) == dark.size()
+            && same_exposure(self.exposure, dark.exposure)
+            && self.binning == dark.binning
+            && self.bias_subtracted == dark.bias_subtracted
+            && close(self.gain, dark.gain, 1.0e-6)
+            && close(self.temperature, dark.temperature, 0.05)
+    }
+
+    /// Whether the dark can be used for a frame, ignoring exposure
+    fn matches<T: Pixel>(&self, frame: &CameraFrame<T>, tolerance: &MatchTolerance) -> bool {
+        // Settings unknown on either side are not held against a match
+        let within = |a: Option<f64>, b: Option<f64>, eps: f64| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() <= eps,
+            _ => true,
+        };
+        (self.width, self.height) == (frame.data.width, frame.data.height)
+            && (self.binning.is_none() || frame.binning.is_none() || self.binning == frame.binning)
+            && within(self.gain, frame.gain, tolerance.gain)
+            && within(self.temperature, frame.temperature, tolerance.temperature)
+    }
+
+    /// Temperature difference to a frame, zero if either is unknown
+    fn temperature_difference<T: Pixel>(&self, frame: &CameraFrame<T>) -> f64 {
+        match (self.temperature, frame.temperature) {
+            (Some(a), Some(b)) => (a - b).abs(),
+            _ => 0.0,
+        }
+    }
+}
+
+fn same_exposure(a: f64, b: f64) -> bool {
+    (a - b).abs() <= EXPOSURE_EPS * a.abs().max(b.abs())
+}
+
+/// A persistent library of master darks
+pub struct DarkLibrary {
+    dir: PathBuf,
+    entries: Vec<DarkEntry>,
+}
+
+impl DarkLibrary {
+    /// Open a dark library, creating the directory if it does not exist.
+    ///
+    /// # Arguments
+    /// `dir` - Directory of the library.
+    ///
+    /// # Returns
+    /// The library, or an error if the directory or index could not be read.
+    ///
+    pub fn open(dir: &str) -> Result<Self, CalibrationError> {
+        let dir = PathBuf::from(dir);
+        std::fs::create_dir_all(&dir)?;
+        let mut entries = Vec::new();
+        let index = dir.join(INDEX_FILE);
+        if index.exists() {
+            for line in BufReader::new(std::fs::File::open(index)?).lines().skip(1) {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    entries.push(DarkEntry::from_csv(&line)?);
+                }
+            }
+        }
+        Ok(DarkLibrary { dir, entries })
+    }
+
+    /// Index entries of all darks in the library
+    pub fn entries(&self) -> &[DarkEntry] {
+        &self.entries
+    }
+
+    fn write_index(&self) -> Result<(), CalibrationError> {
+        let tmp = self.dir.join(format!("{}.tmp", INDEX_FILE));
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+        writeln!(file, "{}", INDEX_HEADER)?;
+        for entry in self.entries.iter() {
+            writeln!(file, "{}", entry.to_csv())?;
+        }
+        file.flush()?;
+        drop(file);
+        std::fs::rename(tmp, self.dir.join(INDEX_FILE))?;
+        Ok(())
+    }
+
+    fn path(&self, entry: &DarkEntry) -> String {
+        self.dir.join(&entry.file).to_string_lossy().into_owned()
+    }
+
+    /// Add a master dark to the library, replacing any dark with the same settings.
+    ///
+    /// # Arguments
+    /// `dark` - The master dark to store.
+    ///
+    /// # Returns
+    /// An empty Result if the dark was stored, or an error if the write failed.
+    ///
+    pub fn add(&mut self, dark: &MasterFrame) -> Result<(), CalibrationError> {
+        let file = match self.entries.iter().position(|e| e.same_settings(dark)) {
+            Some(idx) => self.entries.remove(idx).file,
+            None => (0..)
+                .map(|n| format!("dark_{:04}.npy", n))
+                .find(|f| !self.entries.iter().any(|e| &e.file == f))
+                .unwrap(),
+        };
+        let entry = DarkEntry {
+            file,
+            width: dark.data.width,
+            height: dark.data.height,
+            exposure: dark.exposure,
+            gain: dark.gain,
+            binning: dark.binning,
+            temperature: dark.temperature,
+            bias_subtracted: dark.bias_subtracted,
+        };
+        crate::cameraframe::save_f32_to_npy(&self.path(&entry), &dark.data)?;
+        self.entries.push(entry);
+        self.write_index()
+    }
+
+    /// Load a master dark from the library.
+    ///
+    /// # Arguments
+    /// `entry` - Index entry of the dark, from [`DarkLibrary::entries`].
+    ///
+    /// # Returns
+    /// The master dark, or an error if its file could not be read.
+    ///
+    pub fn load(&self, entry: &DarkEntry) -> Result<MasterFrame, CalibrationError> {
+        let data = crate::cameraframe::load_f32_from_npy(&self.path(entry))?;
+        if (data.width, data.height) != (entry.width, entry.height) {
+            return Err(CalibrationError::SizeMismatch {
+                expected: (entry.width, entry.height),
+                found: (data.width, data.height),
+            });
+        }
+        Ok(MasterFrame {
+            exposure: entry.exposure,
+            bias_subtracted: entry.bias_subtracted,
+            gain: entry.gain,
+            binning: entry.binning,
+            temperature: entry.temperature,
+            data,
+        })
+    }
+
+    /// Select the best dark for a frame, interpolating or scaling to its exposure.
+    ///
+    /// # Arguments
+    /// `frame` - The frame to be calibrated.
+    /// `tolerance` - Tolerances on gain and temperature.
+    ///
+    /// # Returns
+    /// The dark at the exposure of the frame, `None` if no dark in the library can be
+    /// used, or an error if a dark could not be read.
+    ///
+    pub fn best_match<T: Pixel>(
+        &self,
+        frame: &CameraFrame<T>,
+        tolerance: &MatchTolerance,
+    ) -> Result<Option<MasterFrame>, CalibrationError> {
+        let candidates: Vec<&DarkEntry> = self
+            .entries
+            .iter()
+            .filter(|e| e.matches(frame, tolerance))
+            .collect();
+        let closest = |entries: &mut dyn Iterator<Item = &&DarkEntry>,
+                       key: &dyn Fn(&DarkEntry) -> f64|
+         -> Option<DarkEntry> {
+            entries
+                .min_by(|a, b| key(a).total_cmp(&key(b)))
+                .map(|e| (*e).clone())
+        };
+        let by_temperature = |e: &DarkEntry| e.temperature_difference(frame);
+
+        if let Some(entry) = closest(
+            &mut candidates
+                .iter()
+                .filter(|e| same_exposure(e.exposure, frame.exposure)),
+            &by_temperature,
+        ) {
+            return Ok(Some(self.load(&entry)?));
+        }
+
+        // Pair each exposure with its temperature difference, so nearer exposures
+        // win and temperature breaks ties
+        let scalable: Vec<&&DarkEntry> = candidates
+            .iter()
+            .filter(|e| e.bias_subtracted && e.exposure > 0.0)
+            .collect();
+        let below = closest(
+            &mut scalable
+                .iter()
+                .copied()
+                .filter(|e| e.exposure < frame.exposure),
+            &|e| (frame.exposure - e.exposure) + 1.0e-9 * by_temperature(e),
+        );
+        let above = closest(
+            &mut scalable
+                .iter()
+                .copied()
+                .filter(|e| e.exposure > frame.exposure),
+            &|e| (e.exposure - frame.exposure) + 1.0e-9 * by_temperature(e),
+        );
+
+        let mut dark = match (below, above) {
+            (Some(lo), Some(hi)) => {
+                let (lo, hi) = (self.load(&lo)?, self.load(&hi)?);
+                let w = ((frame.exposure - lo.exposure) / (hi.exposure - lo.exposure)) as f32;
+                MasterFrame {
+                    data: &lo.data + &(&(&hi.data - &lo.data) * w),
+                    ..lo
+                }
+            }
+            (Some(entry), None) | (None, Some(entry)) => {
+                let mut dark = self.load(&entry)?;
+                let scale = (frame.exposure / dark.exposure) as f32;
+                dark.data = &dark.data * scale;
+                dark
+            }
+            (None, None) => return Ok(None),
+        };
+        dark.exposure = frame.exposure;
+        Ok(Some(dark))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::CombineMethod;
+    use crate::MonoCameraFrame;
+    use crate::MonoFrameData;
+
+    fn dark(exposure: f64, value: u16, temperature: f64) -> MasterFrame {
+        let mut frame = MonoCameraFrame::create(
+            exposure,
+            chrono::Utc::now(),
+            16,
+            MonoFrameData::<u16> {
+                width: 3,
+                height: 2,
+                data: vec![rgb::Gray::new(value); 6],
+            },
+        );
+        frame.temperature = Some(temperature);
+        frame.gain = Some(10.0);
+        let bias = MasterFrame {
+            data: &MonoFrameData::<f32>::ones(3, 2) * 100.0,
+            ..MasterFrame::bias(&[frame.clone()], CombineMethod::Mean).unwrap()
+        };
+        MasterFrame::dark(&[frame], Some(&bias), CombineMethod::Mean).unwrap()
+    }
+
+    #[test]
+    fn test_dark_library() {
+        let dir = std::env::temp_dir().join("camera_test_darks");
+        let _ = std::fs::remove_dir_all(&dir);
+        let dirname = dir.to_str().unwrap();
+
+        let mut library = DarkLibrary::open(dirname).unwrap();
+        library.add(&dark(1.0, 110, -10.0)).unwrap();
+        library.add(&dark(4.0, 150, -10.0)).unwrap();
+        library.add(&dark(4.0, 140, -10.0)).unwrap();
+        library.add(&dark(10.0, 300, 5.0)).unwrap();
+        assert_eq!(library.entries().len(), 3);
+
+        let library = DarkLibrary::open(dirname).unwrap();
+        assert_eq!(library.entries().len(), 3);
+
+        let mut light =
+            MonoCameraFrame::<u16>::create(4.0, chrono::Utc::now(), 16, MonoFrameData::zeros(3, 2));
+        light.temperature = Some(-9.0);
+        light.gain = Some(10.0);
+        let tolerance = MatchTolerance::default();
+
+        // Exact exposure, replaced entry
+        let d = library.best_match(&light, &tolerance).unwrap().unwrap();
+        assert_eq!(d.data.data[0].value(), 40.0);
+
+        // Interpolated between 1 s and 4 s
+        light.exposure = 2.0;
+        let d = library.best_match(&light, &tolerance).unwrap().unwrap();
+        assert!((d.data.data[0].value() - 20.0).abs() < 1e-4);
+        assert_eq!(d.exposure, 2.0);
+
+        // Scaled from 4 s; the 10 s dark is too warm
+        light.exposure = 8.0;
+        let d = library.best_match(&light, &tolerance).unwrap().unwrap();
+        assert!((d.data.data[0].value() - 80.0).abs() < 1e-4);
+
+        light.gain = Some(20.0);
+        assert!(library.best_match(&light, &tolerance).unwrap().is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
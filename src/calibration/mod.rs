@@ -0,0 +1,434 @@
+//!
+//! Dark, bias and flat-field calibration of monochrome frames.
+//!
+//! Master frames are combined pixel-by-pixel from sets of calibration frames and
+//! held in single-precision floating point.  A light frame is calibrated as
+//!
+//! `(light - bias - dark * light_exposure / dark_exposure) / normalized_flat`
+//!
+//! in floating point, then rounded and clamped back to the original pixel type.
+//! Darks are only scaled by exposure when they were built with a master bias
+//! subtracted; a dark that still contains the bias is subtracted as is, and the
+//! master bias is not applied separately.
+//!
+
+use crate::MonoCameraFrame;
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+use rgb::Gray;
+
+mod defects;
+mod library;
+
+pub use defects::DefectMap;
+pub use library::{DarkEntry, DarkLibrary, MatchTolerance};
+
+/// Errors building or applying calibration frames
+#[derive(Debug, thiserror::Error)]
+pub enum CalibrationError {
+    #[error("No calibration frames provided")]
+    NoFrames,
+    #[error("Frame size {found:?} does not match calibration size {expected:?}")]
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    #[error("Calibration frames have no pixels")]
+    EmptyFrame,
+    #[error("Flat field has no positive signal")]
+    InvalidFlat,
+    #[error("Calibration file error: {0}")]
+    File(#[from] crate::FrameFileError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// How a set of calibration frames is combined into a master frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CombineMethod {
+    /// Per-pixel mean
+    Mean,
+    /// Per-pixel median, rejecting outliers such as cosmic rays
+    #[default]
+    Median,
+}
+
+/// A master calibration frame, in floating point
+#[derive(Debug, Clone)]
+pub struct MasterFrame {
+    /// Mean exposure of the frames combined, in seconds
+    pub exposure: f64,
+    /// Whether a master bias was subtracted when building the frame
+    pub bias_subtracted: bool,
+    /// Gain of the first frame combined
+    pub gain: Option<f64>,
+    /// Binning of the first frame combined
+    pub binning: Option<u32>,
+    /// Mean sensor temperature of the frames combined, where known
+    pub temperature: Option<f64>,
+    pub data: MonoFrameData<f32>,
+}
+
+/// Check that all frames have the same size, returning it
+fn common_size<T: MonoPixel>(
+    frames: &[MonoCameraFrame<T>],
+) -> Result<(u32, u32), CalibrationError> {
+    let first = frames.first().ok_or(CalibrationError::NoFrames)?;
+    let size = (first.data.width, first.data.height);
+    for f in frames.iter() {
+        check_size(size, (f.data.width, f.data.height))?;
+    }
+    Ok(size)
+}
+
+fn check_size(expected: (u32, u32), found: (u32, u32)) -> Result<(), CalibrationError> {
+    if expected != found {
+        return Err(CalibrationError::SizeMismatch { expected, found });
+    }
+    Ok(())
+}
+
+/// Combine per-pixel values of several images of the same size
+fn combine(images: &[MonoFrameData<f32>], method: CombineMethod) -> MonoFrameData<f32> {
+    let npix = images[0].data.len();
+    let n = images.len();
+    let data = match method {
+        CombineMethod::Mean => (0..npix)
+            .map(|i| {
+                let sum = images
+                    .iter()
+                    .map(|im| im.data[i].value() as f64)
+                    .sum::<f64>();
+                Gray::new(sum as f32 / n as f32)
+            })
+            .collect(),
+        CombineMethod::Median => {
+            let mut values = vec![0f32; n];
+            (0..npix)
+                .map(|i| {
+                    values
+                        .iter_mut()
+                        .zip(images.iter())
+                        .for_each(|(v, im)| *v = im.data[i].value());
+                    Gray::new(median(&mut values))
+                })
+                .collect()
+        }
+    };
+    MonoFrameData {
+        width: images[0].width,
+        height: images[0].height,
+        data,
+    }
+}
+
+/// Median of a set of values, reordering them
+fn median(values: &mut [f32]) -> f32 {
+    let n = values.len();
+    let mid = *values
+        .select_nth_unstable_by(n / 2, |a, b| a.total_cmp(b))
+        .1;
+    if n % 2 == 1 {
+        mid
+    } else {
+        // The lower middle value is the largest of the lower half
+        let below = values[..n / 2]
+            .iter()
+            .fold(f32::NEG_INFINITY, |a, b| a.max(*b));
+        (below + mid) / 2.0
+    }
+}
+
+impl MasterFrame {
+    /// A master frame of the given values with the exposure and camera settings of
+    /// a set of frames
+    fn describe<T: MonoPixel>(frames: &[MonoCameraFrame<T>], data: MonoFrameData<f32>) -> Self {
+        let temperatures: Vec<f64> = frames.iter().filter_map(|f| f.temperature).collect();
+        MasterFrame {
+            exposure: frames.iter().map(|f| f.exposure).sum::<f64>() / frames.len() as f64,
+            bias_subtracted: false,
+            gain: frames[0].gain,
+            binning: frames[0].binning,
+            temperature: match temperatures.len() {
+                0 => None,
+                n => Some(temperatures.iter().sum::<f64>() / n as f64),
+            },
+            data,
+        }
+    }
+
+    /// Width and height of the frame
+    pub fn size(&self) -> (u32, u32) {
+        (self.data.width, self.data.height)
+    }
+
+    fn mean(&self) -> f64 {
+        let sum = self.data.data.iter().map(|x| x.value() as f64).sum::<f64>();
+        sum / self.data.data.len().max(1) as f64
+    }
+
+    /// Build a master bias from zero-length exposures.
+    ///
+    /// # Arguments
+    /// `frames` - The bias frames, all of the same size.
+    /// `method` - How the frames are combined.
+    ///
+    /// # Returns
+    /// The master bias, or an error if no frames were given or their sizes differ.
+    ///
+    pub fn bias<T: MonoPixel>(
+        frames: &[MonoCameraFrame<T>],
+        method: CombineMethod,
+    ) -> Result<Self, CalibrationError> {
+        common_size(frames)?;
+        let images: Vec<MonoFrameData<f32>> =
+            frames.iter().map(|f| f.data.to_float(1.0, 0.0)).collect();
+        Ok(MasterFrame::describe(frames, combine(&images, method)))
+    }
+
+    /// Build a master dark from frames taken with the shutter closed.
+    ///
+    /// If a master bias is given it is subtracted, leaving only the thermal signal so
+    /// the dark can be scaled to other exposure times.
+    ///
+    /// # Arguments
+    /// `frames` - The dark frames, all of the same size and exposure.
+    /// `bias` - Optional master bias.
+    /// `method` - How the frames are combined.
+    ///
+    /// # Returns
+    /// The master dark, or an error if no frames were given or their sizes differ.
+    ///
+    pub fn dark<T: MonoPixel>(
+        frames: &[MonoCameraFrame<T>],
+        bias: Option<&MasterFrame>,
+        method: CombineMethod,
+    ) -> Result<Self, CalibrationError> {
+        let mut master = Self::bias(frames, method)?;
+        if let Some(bias) = bias {
+            check_size(bias.size(), master.size())?;
+            master.data -= &bias.data;
+            master.bias_subtracted = true;
+        }
+        Ok(master)
+    }
+
+    /// Build a normalized master flat, with a mean of one.
+    ///
+    /// Each flat has the bias and the dark, scaled to its exposure, removed before
+    /// combining.
+    ///
+    /// # Arguments
+    /// `frames` - The flat frames, all of the same size.
+    /// `bias` - Optional master bias.
+    /// `dark` - Optional master dark.
+    /// `method` - How the frames are combined.
+    ///
+    /// # Returns
+    /// The master flat, or an error if no frames were given, their sizes differ, or
+    /// the flat has no positive signal.
+    ///
+    pub fn flat<T: MonoPixel>(
+        frames: &[MonoCameraFrame<T>],
+        bias: Option<&MasterFrame>,
+        dark: Option<&MasterFrame>,
+        method: CombineMethod,
+    ) -> Result<Self, CalibrationError> {
+        common_size(frames)?;
+        let offsets = Calibration {
+            bias: bias.cloned(),
+            dark: dark.cloned(),
+            flat: None,
+        };
+        let images = frames
+            .iter()
+            .map(|f| offsets.calibrate_f32(f))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut master = MasterFrame {
+            bias_subtracted: bias.is_some(),
+            ..MasterFrame::describe(frames, combine(&images, method))
+        };
+        let mean = master.mean();
+        if mean <= 0.0 || !mean.is_finite() {
+            return Err(CalibrationError::InvalidFlat);
+        }
+        master.data = &master.data / mean as f32;
+        Ok(master)
+    }
+}
+
+/// A set of master frames applied to light frames
+#[derive(Debug, Clone, Default)]
+pub struct Calibration {
+    pub bias: Option<MasterFrame>,
+    pub dark: Option<MasterFrame>,
+    /// Normalized flat, as built by [`MasterFrame::flat`]
+    pub flat: Option<MasterFrame>,
+}
+
+impl Calibration {
+    /// Calibrate a frame, returning the result in floating point.
+    ///
+    /// Flat pixels with no positive response produce zero.
+    ///
+    /// # Arguments
+    /// `light` - The frame to calibrate.
+    ///
+    /// # Returns
+    /// The calibrated frame, or an error if a master frame does not match the
+    /// frame size.
+    ///
+    pub fn calibrate_f32<T: MonoPixel>(
+        &self,
+        light: &MonoCameraFrame<T>,
+    ) -> Result<MonoFrameData<f32>, CalibrationError> {
+        let size = (light.data.width, light.data.height);
+        let mut values = light.data.to_float(1.0, 0.0);
+
+        let subtract = |values: &mut MonoFrameData<f32>, master: &MasterFrame, scale: f32| {
+            *values -= &(&master.data * scale);
+        };
+
+        match &self.dark {
+            Some(dark) if dark.bias_subtracted => {
+                check_size(dark.size(), size)?;
+                if let Some(bias) = &self.bias {
+                    check_size(bias.size(), size)?;
+                    subtract(&mut values, bias, 1.0);
+                }
+                let scale = if dark.exposure > 0.0 {
+                    (light.exposure / dark.exposure) as f32
+                } else {
+                    1.0
+                };
+                subtract(&mut values, dark, scale);
+            }
+            Some(dark) => {
+                check_size(dark.size(), size)?;
+                subtract(&mut values, dark, 1.0);
+            }
+            None => {
+                if let Some(bias) = &self.bias {
+                    check_size(bias.size(), size)?;
+                    subtract(&mut values, bias, 1.0);
+                }
+            }
+        }
+
+        if let Some(flat) = &self.flat {
+            check_size(flat.size(), size)?;
+            values
+                .data
+                .iter_mut()
+                .zip(flat.data.data.iter())
+                .for_each(|(v, f)| {
+                    *v = Gray::new(if f.value() > 0.0 {
+                        v.value() / f.value()
+                    } else {
+                        0.0
+                    })
+                });
+        }
+        Ok(values)
+    }
+
+    /// Calibrate a frame, rounding and clamping the result to the pixel type.
+    ///
+    /// # Arguments
+    /// `light` - The frame to calibrate.
+    ///
+    /// # Returns
+    /// The calibrated frame, with the metadata of `light`, or an error if a master
+    /// frame does not match the frame size.
+    ///
+    pub fn apply<T: MonoPixel>(
+        &self,
+        light: &MonoCameraFrame<T>,
+    ) -> Result<MonoCameraFrame<T>, CalibrationError> {
+        let values = self.calibrate_f32(light)?;
+        let mut frame = light.clone();
+        frame.data = values.to_mono(1.0, 0.0);
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(exposure: f64, values: &[u16]) -> MonoCameraFrame<u16> {
+        MonoCameraFrame::create(
+            exposure,
+            chrono::Utc::now(),
+            16,
+            MonoFrameData {
+                width: 2,
+                height: 2,
+                data: values.iter().map(|v| Gray::new(*v)).collect(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 2.5);
+    }
+
+    #[test]
+    fn test_calibration() {
+        let bias = MasterFrame::bias(
+            &[
+                frame(0.0, &[100; 4]),
+                frame(0.0, &[102; 4]),
+                frame(0.0, &[5000; 4]),
+            ],
+            CombineMethod::Median,
+        )
+        .unwrap();
+        assert!(bias.data.data.iter().all(|p| p.value() == 102.0));
+        assert_eq!(bias.size(), (2, 2));
+
+        // 10 counts/s of dark current in one pixel
+        let dark = MasterFrame::dark(
+            &[frame(2.0, &[102, 122, 102, 102])],
+            Some(&bias),
+            CombineMethod::Mean,
+        )
+        .unwrap();
+        assert!(dark.bias_subtracted);
+
+        // Vignetted flat: corner at half response
+        let flat = MasterFrame::flat(
+            &[frame(1.0, &[1502, 1512, 1502, 702])],
+            Some(&bias),
+            Some(&dark),
+            CombineMethod::Mean,
+        )
+        .unwrap();
+        assert!((flat.data.at(1, 1).value() - 0.5).abs() < 1e-6);
+
+        let cal = Calibration {
+            bias: Some(bias),
+            dark: Some(dark),
+            flat: Some(flat),
+        };
+        // 4 s light: bias 102, dark current 40 counts in pixel 1
+        let light = frame(4.0, &[1102, 1142, 50, 602]);
+        let out = cal.apply(&light).unwrap();
+        let values: Vec<u16> = out.data.data.iter().map(|x| x.value()).collect();
+        assert_eq!(values, vec![857, 857, 0, 1000]);
+        assert_eq!(out.exposure, 4.0);
+
+        assert!(matches!(
+            cal.apply(&MonoCameraFrame::<u16>::create(
+                1.0,
+                chrono::Utc::now(),
+                16,
+                MonoFrameData::zeros(3, 3)
+            )),
+            Err(CalibrationError::SizeMismatch { .. })
+        ));
+    }
+}
@@ -10,6 +10,14 @@ where
     pub exposure: f64,
     pub center_of_integration: chrono::DateTime<chrono::Utc>,
     pub bit_depth: u8,
+    /// Camera gain setting at the time of capture, if known.
+    pub gain: Option<f64>,
+    /// Hardware binning factor (applied equally in x and y), if known.
+    pub binning: Option<u32>,
+    /// Sensor temperature in degrees Celsius, if known.
+    pub temperature: Option<f64>,
+    /// Name of the camera that produced the frame, if known.
+    pub camera_name: Option<String>,
     pub data: FrameData<T>,
 }
 
@@ -27,6 +35,7 @@ where
     ///
     /// # Returns
     /// A new camera frame with the given exposure, center of integration, bit depth, and raw data.
+    /// Optional metadata (gain, binning, temperature, camera name) is left unset.
     ///
     pub fn create(
         exposure: f64,
@@ -38,6 +47,10 @@ where
             exposure,
             center_of_integration,
             bit_depth,
+            gain: None,
+            binning: None,
+            temperature: None,
+            camera_name: None,
             data: raw,
         }
     }
@@ -52,6 +65,10 @@ where
             exposure: 0.0,
             center_of_integration: chrono::Utc::now(),
             bit_depth: 12,
+            gain: None,
+            binning: None,
+            temperature: None,
+            camera_name: None,
             data: FrameData::<T>::default(),
         }
     }
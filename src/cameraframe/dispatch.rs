@@ -0,0 +1,297 @@
+//!
+//! This module contains generic saving and loading of CameraFrameType, choosing the
+//! file format from the filename extension.
+//!
+//! Supported extensions are `png`, `tif`/`tiff`, `fits`/`fit`/`fts`, `npy` and
+//! `pgm`/`ppm`/`pnm`.  PNG, FITS and Netpbm files carry the frame metadata; TIFF and
+//! NumPy files hold only the pixels.  Not every format supports every pixel type:
+//! RGBA frames can only be written to PNG and NumPy files.
+//!
+
+use super::fits::read_fits_image_type;
+use super::from_file::read_png_type;
+use super::netpbm::PnmHeader;
+use super::npy::NpyHeader;
+use super::tiff_file::read_tiff_color_type;
+use super::CameraFrame;
+use super::CameraFrameType;
+use super::FrameData;
+use super::FrameFileError;
+use super::MonoFrameData;
+use super::PnmFormat;
+
+/// File formats that CameraFrameType can be saved to and loaded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    Png,
+    Tiff,
+    Fits,
+    Npy,
+    Pnm,
+}
+
+impl FileFormat {
+    fn from_filename(filename: &str) -> Result<Self, FrameFileError> {
+        let ext = std::path::Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "png" => Ok(FileFormat::Png),
+            "tif" | "tiff" => Ok(FileFormat::Tiff),
+            "fits" | "fit" | "fts" => Ok(FileFormat::Fits),
+            "npy" => Ok(FileFormat::Npy),
+            "pgm" | "ppm" | "pnm" => Ok(FileFormat::Pnm),
+            _ => Err(FrameFileError::Unsupported(format!(
+                "unrecognized file extension for {}",
+                filename
+            ))),
+        }
+    }
+}
+
+/// Wrap FrameData loaded from a format without metadata in a CameraFrame
+fn bare_frame<T: crate::Pixel>(data: FrameData<T>, bit_depth: u8) -> CameraFrame<T> {
+    CameraFrame::create(0.0, chrono::Utc::now(), bit_depth, data)
+}
+
+/// Error for a file whose header describes a pixel type CameraFrameType cannot hold
+fn unsupported(filename: &str) -> FrameFileError {
+    FrameFileError::Unsupported(format!(
+        "{} does not hold a Mono8, Mono16, RGB8 or RGBA8 image",
+        filename
+    ))
+}
+
+impl CameraFrameType {
+    /// Save the frame to a file, choosing the format from the filename extension.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the frame to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the extension is
+    /// not recognized, the format cannot hold the pixel type, or the save failed.
+    ///
+    pub fn save(&self, filename: &str) -> Result<(), FrameFileError> {
+        let format = FileFormat::from_filename(filename)?;
+        match (self, format) {
+            (CameraFrameType::Mono8(f), FileFormat::Png) => f.save_to_png(filename),
+            (CameraFrameType::Mono16(f), FileFormat::Png) => f.save_to_png(filename),
+            (CameraFrameType::RGB8(f), FileFormat::Png) => f.save_to_png(filename),
+            (CameraFrameType::RGBA8(f), FileFormat::Png) => f.save_to_png(filename),
+            (CameraFrameType::Mono8(f), FileFormat::Tiff) => f.data.save_to_tiff(filename),
+            (CameraFrameType::Mono16(f), FileFormat::Tiff) => f.data.save_to_tiff(filename),
+            (CameraFrameType::RGB8(f), FileFormat::Tiff) => f.data.save_to_tiff(filename),
+            (CameraFrameType::Mono8(f), FileFormat::Fits) => f.save_to_fits(filename),
+            (CameraFrameType::Mono16(f), FileFormat::Fits) => f.save_to_fits(filename),
+            (CameraFrameType::RGB8(f), FileFormat::Fits) => f.save_to_fits(filename),
+            (CameraFrameType::Mono8(f), FileFormat::Npy) => f.data.save_to_npy(filename),
+            (CameraFrameType::Mono16(f), FileFormat::Npy) => f.data.save_to_npy(filename),
+            (CameraFrameType::RGB8(f), FileFormat::Npy) => f.data.save_to_npy(filename),
+            (CameraFrameType::RGBA8(f), FileFormat::Npy) => f.data.save_to_npy(filename),
+            (CameraFrameType::Mono8(f), FileFormat::Pnm) => {
+                f.save_to_pnm(filename, PnmFormat::Binary)
+            }
+            (CameraFrameType::Mono16(f), FileFormat::Pnm) => {
+                f.save_to_pnm(filename, PnmFormat::Binary)
+            }
+            (CameraFrameType::RGB8(f), FileFormat::Pnm) => {
+                f.save_to_pnm(filename, PnmFormat::Binary)
+            }
+            (CameraFrameType::RGBA8(_), format) => Err(FrameFileError::Unsupported(format!(
+                "RGBA8 frames cannot be saved as {:?}",
+                format
+            ))),
+        }
+    }
+
+    /// Load a frame from a file, choosing the format from the filename extension.
+    ///
+    /// The pixel type is taken from the file: 8-bit images load as Mono8, RGB8 or
+    /// RGBA8, and 16-bit monochrome images as Mono16.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to load.
+    ///
+    /// # Returns
+    /// The frame, or an error if the extension is not recognized, the file could not
+    /// be read, or it holds a pixel type that CameraFrameType cannot represent.
+    ///
+    pub fn load(filename: &str) -> Result<Self, FrameFileError> {
+        use png::{BitDepth, ColorType};
+        use CameraFrameType::*;
+        // Only the header is read to choose the pixel type, then the file is loaded once
+        match FileFormat::from_filename(filename)? {
+            FileFormat::Png => match read_png_type(filename)? {
+                (ColorType::Grayscale, BitDepth::Eight) => {
+                    CameraFrame::load_from_png(filename).map(Mono8)
+                }
+                (ColorType::Grayscale, BitDepth::Sixteen) => {
+                    CameraFrame::load_from_png(filename).map(Mono16)
+                }
+                (ColorType::Rgb, BitDepth::Eight) => CameraFrame::load_from_png(filename).map(RGB8),
+                (ColorType::Rgba, BitDepth::Eight) => {
+                    CameraFrame::load_from_png(filename).map(RGBA8)
+                }
+                _ => Err(unsupported(filename)),
+            },
+            FileFormat::Tiff => {
+                match read_tiff_color_type(filename)? {
+                    tiff::ColorType::Gray(8) => MonoFrameData::<u8>::load_from_tiff(filename)
+                        .map(|d| Mono8(bare_frame(d, 8))),
+                    tiff::ColorType::Gray(16) => MonoFrameData::<u16>::load_from_tiff(filename)
+                        .map(|d| Mono16(bare_frame(d, 16))),
+                    tiff::ColorType::RGB(8) => FrameData::<rgb::RGB8>::load_from_tiff(filename)
+                        .map(|d| RGB8(bare_frame(d, 8))),
+                    _ => Err(unsupported(filename)),
+                }
+            }
+            FileFormat::Fits => match read_fits_image_type(filename)? {
+                (8, 1) => CameraFrame::load_from_fits(filename).map(Mono8),
+                (16, 1) => CameraFrame::load_from_fits(filename).map(Mono16),
+                (8, 3) => CameraFrame::load_from_fits(filename).map(RGB8),
+                _ => Err(unsupported(filename)),
+            },
+            FileFormat::Npy => {
+                let header = NpyHeader::read_file(filename)?;
+                match (header.descr.as_str(), header.shape.as_slice()) {
+                    ("|u1" | "<u1" | ">u1" | "u1", [_, _]) => {
+                        FrameData::load_from_npy(filename).map(|d| Mono8(bare_frame(d, 8)))
+                    }
+                    ("<u2" | ">u2", [_, _]) => {
+                        FrameData::load_from_npy(filename).map(|d| Mono16(bare_frame(d, 16)))
+                    }
+                    ("|u1" | "<u1" | ">u1" | "u1", [_, _, 3]) => {
+                        FrameData::load_from_npy(filename).map(|d| RGB8(bare_frame(d, 8)))
+                    }
+                    ("|u1" | "<u1" | ">u1" | "u1", [_, _, 4]) => {
+                        FrameData::load_from_npy(filename).map(|d| RGBA8(bare_frame(d, 8)))
+                    }
+                    _ => Err(unsupported(filename)),
+                }
+            }
+            FileFormat::Pnm => {
+                let header = PnmHeader::read_file(filename)?;
+                match (header.channels, header.maxval) {
+                    (1, 0..=255) => CameraFrame::load_from_pnm(filename).map(Mono8),
+                    (1, _) => CameraFrame::load_from_pnm(filename).map(Mono16),
+                    (3, 0..=255) => CameraFrame::load_from_pnm(filename).map(RGB8),
+                    _ => Err(unsupported(filename)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoCameraFrame;
+
+    #[test]
+    fn test_save_load_dispatch() {
+        let data = MonoFrameData::<u16> {
+            width: 9,
+            height: 4,
+            data: (0..36).map(|x| rgb::Gray::new(x * 1000)).collect(),
+        };
+        let frame = CameraFrameType::Mono16(MonoCameraFrame::create(
+            0.25,
+            chrono::Utc::now(),
+            16,
+            data.clone(),
+        ));
+        for ext in ["png", "TIF", "fits", "npy", "pgm"] {
+            let filename = std::env::temp_dir().join(format!("camera_test_dispatch.{}", ext));
+            let filename = filename.to_str().unwrap();
+            frame.save(filename).unwrap();
+            match CameraFrameType::load(filename).unwrap() {
+                CameraFrameType::Mono16(f) => assert_eq!(f.data.data, data.data),
+                _ => panic!("expected Mono16 frame from {}", ext),
+            }
+            let _ = std::fs::remove_file(filename);
+        }
+
+        let rgba = CameraFrameType::RGBA8(CameraFrame::create(
+            0.1,
+            chrono::Utc::now(),
+            8,
+            FrameData {
+                width: 2,
+                height: 2,
+                data: vec![rgb::RGBA8::new(1, 2, 3, 4); 4],
+            },
+        ));
+        let filename = std::env::temp_dir().join("camera_test_dispatch_rgba.png");
+        let filename = filename.to_str().unwrap();
+        rgba.save(filename).unwrap();
+        assert!(matches!(
+            CameraFrameType::load(filename).unwrap(),
+            CameraFrameType::RGBA8(_)
+        ));
+        let _ = std::fs::remove_file(filename);
+
+        // The header alone rules out pixel types CameraFrameType cannot hold
+        let filename = std::env::temp_dir().join("camera_test_dispatch_rgb16.png");
+        let filename = filename.to_str().unwrap();
+        FrameData {
+            width: 2,
+            height: 2,
+            data: vec![rgb::RGB16::new(1, 2, 3); 4],
+        }
+        .save_to_png(filename)
+        .unwrap();
+        assert!(matches!(
+            CameraFrameType::load(filename),
+            Err(FrameFileError::Unsupported(_))
+        ));
+        let _ = std::fs::remove_file(filename);
+
+        assert!(matches!(
+            rgba.save("frame.tiff"),
+            Err(FrameFileError::Unsupported(_))
+        ));
+        assert!(matches!(
+            rgba.save("frame.jpg"),
+            Err(FrameFileError::Unsupported(_))
+        ));
+        assert!(matches!(
+            CameraFrameType::load("/nonexistent/frame.png"),
+            Err(FrameFileError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_truncated() {
+        let frame = CameraFrameType::Mono8(MonoCameraFrame::create(
+            0.25,
+            chrono::Utc::now(),
+            8,
+            MonoFrameData::<u8> {
+                width: 64,
+                height: 64,
+                data: (0..64 * 64).map(|x| rgb::Gray::new(x as u8)).collect(),
+            },
+        ));
+        for ext in ["png", "fits"] {
+            let filename = std::env::temp_dir().join(format!("camera_test_truncated.{}", ext));
+            let filename = filename.to_str().unwrap();
+            frame.save(filename).unwrap();
+            let bytes = std::fs::read(filename).unwrap();
+            std::fs::write(filename, &bytes[..bytes.len() / 2]).unwrap();
+            // The decoding error is reported, not a pixel type mismatch
+            let err = CameraFrameType::load(filename).err().unwrap();
+            assert!(
+                matches!(
+                    err,
+                    FrameFileError::PngDecode(_) | FrameFileError::Format(_)
+                ),
+                "{:?}",
+                err
+            );
+            let _ = std::fs::remove_file(filename);
+        }
+    }
+}
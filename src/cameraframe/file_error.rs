@@ -0,0 +1,23 @@
+//!
+//! Error type returned when reading or writing frames to files.
+//!
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameFileError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("PNG encoding error: {0}")]
+    PngEncode(#[from] png::EncodingError),
+    #[error("PNG decoding error: {0}")]
+    PngDecode(#[from] png::DecodingError),
+    #[error("TIFF error: {0}")]
+    Tiff(#[from] tiff::TiffError),
+    #[error("GIF encoding error: {0}")]
+    GifEncode(#[from] gif::EncodingError),
+    #[error("ZIP archive error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Invalid file format: {0}")]
+    Format(String),
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+}
@@ -0,0 +1,692 @@
+//!
+//! This module contains functions for reading and writing FrameData and CameraFrame
+//! to FITS (Flexible Image Transport System) files.
+//!
+//! Monochrome frames are stored as 2-dimensional images; colour frames are stored as
+//! 3-dimensional cubes with one plane each for red, green and blue.
+//! Unsigned 16-bit data is stored as signed 16-bit with `BZERO = 32768`, per the FITS
+//! standard.  Rows are written in memory order (top row first), which is recorded
+//! with the `ROWORDER` keyword.
+//!
+
+use super::CameraFrame;
+use super::FrameData;
+use super::FrameFileError;
+use crate::Pixel;
+use rgb::{Gray, RGB16, RGB8};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+const BLOCK_SIZE: usize = 2880;
+const CARD_SIZE: usize = 80;
+
+/// Pixel types that can be stored in a FITS file
+pub trait FitsPixel: Pixel {
+    /// Number of bits per stored value (the FITS `BITPIX` keyword)
+    const BITPIX: i32;
+
+    /// Number of planes, 1 for monochrome and 3 for colour
+    const PLANES: usize;
+
+    /// Value of the given plane (colour channel) of the pixel
+    fn channel(&self, plane: usize) -> u16;
+
+    /// Construct a pixel from physical channel values, rounding and clamping to the pixel range
+    fn from_channels(channels: &[f64]) -> Self;
+}
+
+fn clamp_u8(v: f64) -> u8 {
+    v.round().clamp(0.0, u8::MAX as f64) as u8
+}
+
+fn clamp_u16(v: f64) -> u16 {
+    v.round().clamp(0.0, u16::MAX as f64) as u16
+}
+
+impl FitsPixel for Gray<u8> {
+    const BITPIX: i32 = 8;
+    const PLANES: usize = 1;
+
+    fn channel(&self, _plane: usize) -> u16 {
+        self.value() as u16
+    }
+
+    fn from_channels(channels: &[f64]) -> Self {
+        Gray::<u8>::new(clamp_u8(channels[0]))
+    }
+}
+
+impl FitsPixel for Gray<u16> {
+    const BITPIX: i32 = 16;
+    const PLANES: usize = 1;
+
+    fn channel(&self, _plane: usize) -> u16 {
+        self.value()
+    }
+
+    fn from_channels(channels: &[f64]) -> Self {
+        Gray::<u16>::new(clamp_u16(channels[0]))
+    }
+}
+
+impl FitsPixel for RGB8 {
+    const BITPIX: i32 = 8;
+    const PLANES: usize = 3;
+
+    fn channel(&self, plane: usize) -> u16 {
+        match plane {
+            0 => self.r as u16,
+            1 => self.g as u16,
+            _ => self.b as u16,
+        }
+    }
+
+    fn from_channels(channels: &[f64]) -> Self {
+        RGB8::new(
+            clamp_u8(channels[0]),
+            clamp_u8(channels[1]),
+            clamp_u8(channels[2]),
+        )
+    }
+}
+
+impl FitsPixel for RGB16 {
+    const BITPIX: i32 = 16;
+    const PLANES: usize = 3;
+
+    fn channel(&self, plane: usize) -> u16 {
+        match plane {
+            0 => self.r,
+            1 => self.g,
+            _ => self.b,
+        }
+    }
+
+    fn from_channels(channels: &[f64]) -> Self {
+        RGB16::new(
+            clamp_u16(channels[0]),
+            clamp_u16(channels[1]),
+            clamp_u16(channels[2]),
+        )
+    }
+}
+
+/// A FITS header, built up from 80-character cards
+struct FitsHeader {
+    cards: Vec<String>,
+}
+
+impl FitsHeader {
+    fn new() -> Self {
+        FitsHeader { cards: Vec::new() }
+    }
+
+    /// Add a card with a fixed-format value (right-justified in columns 11-30)
+    fn push_raw(&mut self, key: &str, value: &str, comment: &str) {
+        let mut card = format!("{:<8}= {:>20}", key, value);
+        if !comment.is_empty() {
+            card.push_str(" / ");
+            card.push_str(comment);
+        }
+        card.truncate(CARD_SIZE);
+        self.cards.push(card);
+    }
+
+    fn push_logical(&mut self, key: &str, value: bool, comment: &str) {
+        self.push_raw(key, if value { "T" } else { "F" }, comment);
+    }
+
+    fn push_int(&mut self, key: &str, value: i64, comment: &str) {
+        self.push_raw(key, &value.to_string(), comment);
+    }
+
+    fn push_float(&mut self, key: &str, value: f64, comment: &str) {
+        self.push_raw(key, &format!("{:.10E}", value), comment);
+    }
+
+    fn push_string(&mut self, key: &str, value: &str, comment: &str) {
+        // Strings are quoted, with embedded quotes doubled, and padded to at least 8 characters
+        let value = format!("'{:<8}'", value.replace('\'', "''"));
+        let mut card = format!("{:<8}= {:<20}", key, value);
+        if !comment.is_empty() {
+            card.push_str(" / ");
+            card.push_str(comment);
+        }
+        card.truncate(CARD_SIZE);
+        self.cards.push(card);
+    }
+
+    /// Write the header, terminated by END and padded with spaces to a full block
+    fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut bytes = Vec::with_capacity((self.cards.len() + 1) * CARD_SIZE);
+        for card in self.cards.iter().map(|s| s.as_str()).chain(["END"]) {
+            bytes.extend_from_slice(format!("{:<80}", card).as_bytes());
+        }
+        let padding = (BLOCK_SIZE - bytes.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        bytes.resize(bytes.len() + padding, b' ');
+        writer.write_all(&bytes)
+    }
+
+    /// Read a header, returning a map of keyword to (unparsed) value
+    fn read<R: Read>(reader: &mut R) -> Result<HashMap<String, String>, FrameFileError> {
+        let mut keys = HashMap::new();
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut first = true;
+        loop {
+            reader.read_exact(&mut block)?;
+            for card in block.chunks(CARD_SIZE) {
+                let key = String::from_utf8_lossy(&card[..8]).trim_end().to_string();
+                if first {
+                    if key != "SIMPLE" {
+                        return Err(FrameFileError::Format("missing SIMPLE keyword".to_string()));
+                    }
+                    first = false;
+                }
+                if key == "END" {
+                    return Ok(keys);
+                }
+                if &card[8..10] != b"= " {
+                    // Commentary card (COMMENT, HISTORY, blank)
+                    continue;
+                }
+                keys.insert(key, parse_value(&String::from_utf8_lossy(&card[10..])));
+            }
+        }
+    }
+}
+
+/// Extract the value from the value/comment field of a header card
+fn parse_value(field: &str) -> String {
+    let field = field.trim_start();
+    if let Some(rest) = field.strip_prefix('\'') {
+        // Quoted string; a doubled quote is an escaped quote
+        let mut value = String::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            value.push(c);
+        }
+        value.trim_end().to_string()
+    } else {
+        field.split('/').next().unwrap_or("").trim().to_string()
+    }
+}
+
+fn get_int(keys: &HashMap<String, String>, key: &str) -> Result<i64, FrameFileError> {
+    keys.get(key)
+        .ok_or_else(|| FrameFileError::Format(format!("missing {} keyword", key)))?
+        .parse::<i64>()
+        .map_err(|_| FrameFileError::Format(format!("invalid {} keyword", key)))
+}
+
+/// Number of colour planes in the primary image: 1 for a 2-D image, `NAXIS3` for 3-D
+fn image_planes(keys: &HashMap<String, String>) -> Result<i64, FrameFileError> {
+    match get_int(keys, "NAXIS")? {
+        2 => Ok(1),
+        3 => get_int(keys, "NAXIS3"),
+        naxis => Err(FrameFileError::Unsupported(format!(
+            "FITS image with NAXIS = {}",
+            naxis
+        ))),
+    }
+}
+
+/// Read only the header of a FITS file, returning `BITPIX` and the number of colour
+/// planes of the primary image
+pub(super) fn read_fits_image_type(filename: &str) -> Result<(i64, i64), FrameFileError> {
+    let keys = FitsHeader::read(&mut BufReader::new(File::open(filename)?))?;
+    Ok((get_int(&keys, "BITPIX")?, image_planes(&keys)?))
+}
+
+fn get_float(keys: &HashMap<String, String>, key: &str) -> Option<f64> {
+    // FORTRAN-style exponents ("1.0D+01") are permitted by the standard
+    keys.get(key).and_then(|v| v.replace('D', "E").parse().ok())
+}
+
+fn parse_date(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+fn format_date(time: &chrono::DateTime<chrono::Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.6f").to_string()
+}
+
+impl<T> FrameData<T>
+where
+    T: FitsPixel,
+{
+    /// Append the structural keywords describing this data to a header
+    fn fits_header(&self) -> FitsHeader {
+        let mut header = FitsHeader::new();
+        header.push_logical("SIMPLE", true, "conforms to FITS standard");
+        header.push_int("BITPIX", T::BITPIX as i64, "bits per data value");
+        header.push_int(
+            "NAXIS",
+            if T::PLANES > 1 { 3 } else { 2 },
+            "number of data axes",
+        );
+        header.push_int("NAXIS1", self.width as i64, "image width");
+        header.push_int("NAXIS2", self.height as i64, "image height");
+        if T::PLANES > 1 {
+            header.push_int("NAXIS3", T::PLANES as i64, "colour planes (R, G, B)");
+        }
+        if T::BITPIX == 16 {
+            header.push_int("BZERO", 32768, "offset for unsigned 16-bit data");
+            header.push_int("BSCALE", 1, "data scaling");
+        }
+        header.push_string("ROWORDER", "TOP-DOWN", "order of image rows");
+        header
+    }
+
+    /// Write the data unit, padded with zeros to a full block
+    fn write_fits_data<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let bytes_per_value = (T::BITPIX / 8) as usize;
+        let mut bytes = Vec::with_capacity(self.data.len() * T::PLANES * bytes_per_value);
+        for plane in 0..T::PLANES {
+            for pixel in self.data.iter() {
+                let v = pixel.channel(plane);
+                match T::BITPIX {
+                    8 => bytes.push(v as u8),
+                    // Subtracting BZERO from an unsigned value is a flip of the sign bit
+                    _ => bytes.extend_from_slice(&(v ^ 0x8000).to_be_bytes()),
+                }
+            }
+        }
+        let padding = (BLOCK_SIZE - bytes.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        bytes.resize(bytes.len() + padding, 0);
+        writer.write_all(&bytes)
+    }
+
+    /// Read the data unit described by the given header keywords.  Floating-point
+    /// images are only accepted when a scale factor to the pixel range is given.
+    fn read_fits_data<R: Read + Seek>(
+        keys: &HashMap<String, String>,
+        reader: &mut R,
+        scale: Option<f64>,
+    ) -> Result<Self, FrameFileError> {
+        let bitpix = get_int(keys, "BITPIX")?;
+        let planes = image_planes(keys)?;
+        let width = get_int(keys, "NAXIS1")?;
+        let height = get_int(keys, "NAXIS2")?;
+        if planes as usize != T::PLANES {
+            return Err(FrameFileError::Unsupported(format!(
+                "FITS image has {} planes, expected {}",
+                planes,
+                T::PLANES
+            )));
+        }
+        if bitpix > 0 && bitpix > T::BITPIX as i64 {
+            return Err(FrameFileError::Unsupported(format!(
+                "FITS image with BITPIX = {} does not fit in a {}-bit pixel",
+                bitpix,
+                T::BITPIX
+            )));
+        }
+        if bitpix < 0 && scale.is_none() {
+            return Err(FrameFileError::Unsupported(format!(
+                "FITS image with BITPIX = {} is floating point; load it with a scale",
+                bitpix
+            )));
+        }
+        if width <= 0 || height <= 0 {
+            return Err(FrameFileError::Format("empty FITS image".to_string()));
+        }
+        let scale = scale.unwrap_or(1.0);
+        let bzero = get_float(keys, "BZERO").unwrap_or(0.0) * scale;
+        let bscale = get_float(keys, "BSCALE").unwrap_or(1.0) * scale;
+
+        // Check the size given by the header against the file before allocating
+        let bytes_per_value = bitpix.unsigned_abs() / 8;
+        let nbytes = (width as u64)
+            .checked_mul(height as u64)
+            .and_then(|n| n.checked_mul(T::PLANES as u64 * bytes_per_value));
+        let position = reader.stream_position()?;
+        let remaining = reader.seek(SeekFrom::End(0))? - position;
+        reader.seek(SeekFrom::Start(position))?;
+        let nbytes = match nbytes {
+            Some(n) if n <= remaining => n as usize,
+            _ => {
+                return Err(FrameFileError::Format(
+                    "truncated FITS data unit".to_string(),
+                ))
+            }
+        };
+        let npixels = width as usize * height as usize;
+        let mut bytes = vec![0u8; nbytes];
+        reader.read_exact(&mut bytes).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => {
+                FrameFileError::Format("truncated FITS data unit".to_string())
+            }
+            _ => e.into(),
+        })?;
+
+        let values: Vec<f64> = match bitpix {
+            8 => bytes.iter().map(|b| *b as f64).collect(),
+            16 => bytes
+                .chunks_exact(2)
+                .map(|b| i16::from_be_bytes([b[0], b[1]]) as f64)
+                .collect(),
+            32 => bytes
+                .chunks_exact(4)
+                .map(|b| i32::from_be_bytes(b.try_into().unwrap()) as f64)
+                .collect(),
+            -32 => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_be_bytes(b.try_into().unwrap()) as f64)
+                .collect(),
+            -64 => bytes
+                .chunks_exact(8)
+                .map(|b| f64::from_be_bytes(b.try_into().unwrap()))
+                .collect(),
+            _ => {
+                return Err(FrameFileError::Format(format!(
+                    "invalid BITPIX = {}",
+                    bitpix
+                )))
+            }
+        };
+
+        let mut channels = vec![0.0; T::PLANES];
+        Ok(FrameData::<T> {
+            width: width as u32,
+            height: height as u32,
+            data: (0..npixels)
+                .map(|idx| {
+                    for (plane, c) in channels.iter_mut().enumerate() {
+                        *c = values[plane * npixels + idx] * bscale + bzero;
+                    }
+                    T::from_channels(&channels)
+                })
+                .collect(),
+        })
+    }
+
+    /// Save the FrameData to a FITS file.
+    ///
+    /// Only the keywords describing the image structure are written;
+    /// use `CameraFrame::save_to_fits` to include exposure and camera metadata.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the FITS image to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_fits(&self, filename: &str) -> Result<(), FrameFileError> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        self.fits_header().write(&mut writer)?;
+        self.write_fits_data(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load FrameData from a FITS file.
+    ///
+    /// The primary image is read and `BSCALE` / `BZERO` are applied.
+    /// Floating-point images are rejected; use `FrameData::load_from_fits_scaled`.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the FITS file to load.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or does not
+    /// match the pixel type.
+    ///
+    pub fn load_from_fits(filename: &str) -> Result<Self, FrameFileError> {
+        let mut reader = BufReader::new(File::open(filename)?);
+        let keys = FitsHeader::read(&mut reader)?;
+        Self::read_fits_data(&keys, &mut reader, None)
+    }
+
+    /// Load FrameData from a FITS file, multiplying each value by a scale factor.
+    ///
+    /// Unlike `FrameData::load_from_fits`, floating-point images are accepted, e.g.
+    /// normalized data loaded with a scale of 65535.  Scaled values are rounded and
+    /// clamped to the range of the pixel type.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the FITS file to load.
+    /// `scale` - Factor applied to each value after `BSCALE` / `BZERO`.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or does not
+    /// match the pixel type.
+    ///
+    pub fn load_from_fits_scaled(filename: &str, scale: f64) -> Result<Self, FrameFileError> {
+        let mut reader = BufReader::new(File::open(filename)?);
+        let keys = FitsHeader::read(&mut reader)?;
+        Self::read_fits_data(&keys, &mut reader, Some(scale))
+    }
+}
+
+impl<T> CameraFrame<T>
+where
+    T: FitsPixel,
+{
+    /// Save the CameraFrame to a FITS file, including exposure and camera metadata.
+    ///
+    /// The following keywords are written in addition to the image structure:
+    /// `EXPTIME`, `DATE-OBS` (start of exposure), `DATE-AVG` (center of integration),
+    /// `BITDEPTH` (significant bits per value) and, where known, `GAIN`, `XBINNING`, `YBINNING`, `CCD-TEMP` and `INSTRUME`.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the FITS image to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_fits(&self, filename: &str) -> Result<(), FrameFileError> {
+        let mut header = self.data.fits_header();
+        let start = self.center_of_integration
+            - chrono::Duration::microseconds((self.exposure * 0.5e6) as i64);
+        header.push_float("EXPTIME", self.exposure, "exposure time (s)");
+        header.push_string("DATE-OBS", &format_date(&start), "UTC start of exposure");
+        header.push_string(
+            "DATE-AVG",
+            &format_date(&self.center_of_integration),
+            "UTC center of integration",
+        );
+        header.push_int(
+            "BITDEPTH",
+            self.bit_depth as i64,
+            "significant bits per data value",
+        );
+        if let Some(gain) = self.gain {
+            header.push_float("GAIN", gain, "camera gain setting");
+        }
+        if let Some(binning) = self.binning {
+            header.push_int("XBINNING", binning as i64, "binning factor in x");
+            header.push_int("YBINNING", binning as i64, "binning factor in y");
+        }
+        if let Some(temperature) = self.temperature {
+            header.push_float("CCD-TEMP", temperature, "sensor temperature (C)");
+        }
+        if let Some(name) = &self.camera_name {
+            header.push_string("INSTRUME", name, "camera name");
+        }
+
+        let mut writer = BufWriter::new(File::create(filename)?);
+        header.write(&mut writer)?;
+        self.data.write_fits_data(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load a CameraFrame from a FITS file.
+    ///
+    /// The center of integration is taken from `DATE-AVG` if present, otherwise it is
+    /// derived from `DATE-OBS` and `EXPTIME`.  The bit depth is taken from `BITDEPTH` if
+    /// present, otherwise from `BITPIX`.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the FITS file to load.
+    ///
+    /// # Returns
+    /// The CameraFrame, or an error if the file could not be read or does not
+    /// match the pixel type.
+    ///
+    pub fn load_from_fits(filename: &str) -> Result<Self, FrameFileError> {
+        let mut reader = BufReader::new(File::open(filename)?);
+        let keys = FitsHeader::read(&mut reader)?;
+        let data = FrameData::<T>::read_fits_data(&keys, &mut reader, None)?;
+        let bit_depth = match get_int(&keys, "BITDEPTH") {
+            Ok(bits) if bits > 0 && bits <= T::BITPIX as i64 => bits as u8,
+            _ => get_int(&keys, "BITPIX")?.min(T::BITPIX as i64) as u8,
+        };
+
+        let exposure = get_float(&keys, "EXPTIME")
+            .or_else(|| get_float(&keys, "EXPOSURE"))
+            .unwrap_or(0.0);
+        let center_of_integration = match keys.get("DATE-AVG").and_then(|v| parse_date(v)) {
+            Some(t) => t,
+            None => keys
+                .get("DATE-OBS")
+                .and_then(|v| parse_date(v))
+                .map(|t| t + chrono::Duration::microseconds((exposure * 0.5e6) as i64))
+                .unwrap_or_else(chrono::Utc::now),
+        };
+
+        Ok(CameraFrame {
+            gain: get_float(&keys, "GAIN"),
+            binning: get_float(&keys, "XBINNING").map(|b| b as u32),
+            temperature: get_float(&keys, "CCD-TEMP"),
+            camera_name: keys.get("INSTRUME").cloned(),
+            ..CameraFrame::create(exposure, center_of_integration, bit_depth, data)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoFrameData;
+
+    #[test]
+    fn test_fits_mono16_roundtrip() {
+        let data = MonoFrameData::<u16> {
+            width: 37,
+            height: 11,
+            data: (0..37 * 11)
+                .map(|x| Gray::<u16>::new((x * 173) as u16))
+                .collect(),
+        };
+        let mut frame = CameraFrame::create(0.25, chrono::Utc::now(), 12, data);
+        frame.gain = Some(120.0);
+        frame.binning = Some(2);
+        frame.temperature = Some(-10.5);
+        frame.camera_name = Some("Test 'Camera'".to_string());
+
+        let filename = std::env::temp_dir().join("camera_test_mono16.fits");
+        let filename = filename.to_str().unwrap();
+        frame.save_to_fits(filename).unwrap();
+        assert_eq!(std::fs::metadata(filename).unwrap().len() % 2880, 0);
+
+        let frame2 = MonoFrameData::<u16>::load_from_fits(filename).unwrap();
+        assert_eq!(frame2.width, 37);
+        assert_eq!(frame2.height, 11);
+        assert_eq!(frame2.data, frame.data.data);
+
+        let frame3 = CameraFrame::<Gray<u16>>::load_from_fits(filename).unwrap();
+        assert_eq!(frame3.exposure, 0.25);
+        assert_eq!(frame3.gain, Some(120.0));
+        assert_eq!(frame3.binning, Some(2));
+        assert_eq!(frame3.temperature, Some(-10.5));
+        assert_eq!(frame3.camera_name.as_deref(), Some("Test 'Camera'"));
+        assert_eq!(frame3.bit_depth, 12);
+        assert!(
+            (frame3.center_of_integration - frame.center_of_integration)
+                .num_microseconds()
+                .unwrap()
+                .abs()
+                <= 1
+        );
+
+        // 16-bit data cannot be loaded into an 8-bit frame
+        assert!(MonoFrameData::<u8>::load_from_fits(filename).is_err());
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_fits_rgb8_roundtrip() {
+        let data = FrameData::<RGB8> {
+            width: 5,
+            height: 4,
+            data: (0..20)
+                .map(|x| RGB8::new(x as u8, (x * 2) as u8, (x * 3) as u8))
+                .collect(),
+        };
+        let filename = std::env::temp_dir().join("camera_test_rgb8.fits");
+        let filename = filename.to_str().unwrap();
+        data.save_to_fits(filename).unwrap();
+        let data2 = FrameData::<RGB8>::load_from_fits(filename).unwrap();
+        assert_eq!(data2.data, data.data);
+        assert!(MonoFrameData::<u8>::load_from_fits(filename).is_err());
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_fits_float_requires_scale() {
+        let mut header = FitsHeader::new();
+        header.push_logical("SIMPLE", true, "");
+        header.push_int("BITPIX", -32, "");
+        header.push_int("NAXIS", 2, "");
+        header.push_int("NAXIS1", 3, "");
+        header.push_int("NAXIS2", 1, "");
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+        for v in [0.25f32, 1.0, 0.0] {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        bytes.resize(bytes.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+        let filename = std::env::temp_dir().join("camera_test_float.fits");
+        let filename = filename.to_str().unwrap();
+        std::fs::write(filename, bytes).unwrap();
+
+        // Normalized data is not silently rounded to 0 and 1
+        assert!(matches!(
+            MonoFrameData::<u8>::load_from_fits(filename),
+            Err(FrameFileError::Unsupported(_))
+        ));
+        assert!(CameraFrame::<Gray<u16>>::load_from_fits(filename).is_err());
+        let data = MonoFrameData::<u16>::load_from_fits_scaled(filename, 65535.0).unwrap();
+        assert_eq!(
+            data.data,
+            [Gray::new(16384), Gray::new(65535), Gray::new(0)]
+        );
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_fits_oversized_header() {
+        let filename = std::env::temp_dir().join("camera_test_oversized.fits");
+        let filename = filename.to_str().unwrap();
+        // Sizes beyond the file, and beyond the range of the byte count
+        for (width, height) in [(100_000, 100_000), (1 << 40, 1 << 40)] {
+            let mut header = FitsHeader::new();
+            header.push_logical("SIMPLE", true, "");
+            header.push_int("BITPIX", 16, "");
+            header.push_int("NAXIS", 2, "");
+            header.push_int("NAXIS1", width, "");
+            header.push_int("NAXIS2", height, "");
+            let mut bytes = Vec::new();
+            header.write(&mut bytes).unwrap();
+            bytes.resize(bytes.len() + BLOCK_SIZE, 0);
+            std::fs::write(filename, bytes).unwrap();
+            assert!(matches!(
+                MonoFrameData::<u16>::load_from_fits(filename),
+                Err(FrameFileError::Format(_))
+            ));
+        }
+        let _ = std::fs::remove_file(filename);
+    }
+}
@@ -0,0 +1,266 @@
+//!
+//! Floating-point frames: conversion to and from integer frames, and arithmetic.
+//!
+//! Arithmetic on `MonoFrameData<f32>` and `MonoFrameData<f64>` keeps fractional and
+//! negative results, so e.g. dark subtraction can be done without clipping and the
+//! result converted back to an integer frame once, with an explicit scale and
+//! offset.  Float frames are `MonoFrameData`, so flips, rotations, cropping,
+//! binning and resizing apply to them; histograms, display conversion, filters,
+//! file output and the other analyses take integer frames, so convert with
+//! `to_mono` first.
+//!
+
+use super::FrameData;
+use super::MonoFrameData;
+use crate::pixel::FloatPixel;
+use crate::MonoPixel;
+
+use rgb::Gray;
+
+impl<F> MonoFrameData<F>
+where
+    F: FloatPixel,
+{
+    /// Convert to an integer frame, computing `value * scale + offset` for each
+    /// pixel, rounding to the nearest integer and clamping to the range of the type.
+    ///
+    /// # Arguments
+    /// `scale` - Factor applied to each value.
+    /// `offset` - Amount added after scaling.
+    ///
+    /// # Returns
+    /// The integer frame; NaN values become zero.
+    ///
+    pub fn to_mono<T: MonoPixel>(&self, scale: F, offset: F) -> MonoFrameData<T> {
+        MonoFrameData {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .map(|v| {
+                    let v = (v.value() * scale + offset).to_f64().unwrap_or(0.0);
+                    Gray::new(T::from_f64(if v.is_nan() { 0.0 } else { v }))
+                })
+                .collect(),
+        }
+    }
+
+    /// Convert values from 0 to 1 to an integer frame spanning the range of the
+    /// type, the inverse of [`FrameData::to_normalized`].
+    ///
+    /// # Returns
+    /// The integer frame.
+    ///
+    pub fn from_normalized<T: MonoPixel>(&self) -> MonoFrameData<T> {
+        let min = F::from(T::min_value()).unwrap_or(F::zero());
+        let max = F::from(T::max_value()).unwrap_or(F::one());
+        self.to_mono(max - min, min)
+    }
+}
+
+impl<T> MonoFrameData<T>
+where
+    T: MonoPixel,
+{
+    /// Convert to a floating-point frame, computing `value * scale + offset` for
+    /// each pixel.
+    ///
+    /// # Arguments
+    /// `scale` - Factor applied to each value.
+    /// `offset` - Amount added after scaling.
+    ///
+    /// # Returns
+    /// The floating-point frame.
+    ///
+    pub fn to_float<F: FloatPixel>(&self, scale: F, offset: F) -> MonoFrameData<F> {
+        FrameData {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .map(|p| Gray::new(F::from(p.value()).unwrap_or(F::zero()) * scale + offset))
+                .collect(),
+        }
+    }
+
+    /// The pixel values in double precision, row-major, for analyses working on
+    /// plain arrays of values.
+    ///
+    /// # Returns
+    /// The values.
+    ///
+    pub fn to_f64_values(&self) -> Vec<f64> {
+        self.data
+            .iter()
+            .map(|p| p.value().to_f64().unwrap_or(0.0))
+            .collect()
+    }
+
+    /// Convert to a floating-point frame with the range of the type mapped to 0 to 1.
+    ///
+    /// # Returns
+    /// The floating-point frame.
+    ///
+    pub fn to_normalized<F: FloatPixel>(&self) -> MonoFrameData<F> {
+        let min = F::from(T::min_value()).unwrap_or(F::zero());
+        let max = F::from(T::max_value()).unwrap_or(F::one());
+        let scale = F::one() / (max - min);
+        self.to_float(scale, -min * scale)
+    }
+}
+
+/// Combine two frames of the same size pixel-by-pixel
+fn zip_with<F: FloatPixel>(
+    a: &MonoFrameData<F>,
+    b: &MonoFrameData<F>,
+    op: impl Fn(F, F) -> F,
+) -> MonoFrameData<F> {
+    assert_eq!(a.width, b.width);
+    assert_eq!(a.height, b.height);
+    FrameData {
+        width: a.width,
+        height: a.height,
+        data: a
+            .data
+            .iter()
+            .zip(b.data.iter())
+            .map(|(x, y)| Gray::new(op(x.value(), y.value())))
+            .collect(),
+    }
+}
+
+// The operators are implemented for each float type rather than for any
+// `FloatPixel`, which the compiler could not tell apart from the `MonoPixel`
+// operators.
+macro_rules! impl_float_ops {
+    ($f:ty, $op:ident, $method:ident, $assign:ident, $assign_method:ident) => {
+        impl std::ops::$op<&MonoFrameData<$f>> for &MonoFrameData<$f> {
+            type Output = MonoFrameData<$f>;
+
+            fn $method(self, other: &MonoFrameData<$f>) -> MonoFrameData<$f> {
+                zip_with(self, other, |a, b| std::ops::$op::$method(a, b))
+            }
+        }
+
+        impl std::ops::$op<$f> for &MonoFrameData<$f> {
+            type Output = MonoFrameData<$f>;
+
+            fn $method(self, other: $f) -> MonoFrameData<$f> {
+                FrameData {
+                    width: self.width,
+                    height: self.height,
+                    data: self
+                        .data
+                        .iter()
+                        .map(|a| Gray::new(std::ops::$op::$method(a.value(), other)))
+                        .collect(),
+                }
+            }
+        }
+
+        impl std::ops::$assign<&MonoFrameData<$f>> for MonoFrameData<$f> {
+            fn $assign_method(&mut self, other: &MonoFrameData<$f>) {
+                assert_eq!(self.width, other.width);
+                assert_eq!(self.height, other.height);
+                self.data
+                    .iter_mut()
+                    .zip(other.data.iter())
+                    .for_each(|(a, b)| {
+                        *a = Gray::new(std::ops::$op::$method(a.value(), b.value()))
+                    });
+            }
+        }
+    };
+    ($f:ty) => {
+        impl_float_ops!($f, Add, add, AddAssign, add_assign);
+        impl_float_ops!($f, Sub, sub, SubAssign, sub_assign);
+        impl_float_ops!($f, Mul, mul, MulAssign, mul_assign);
+        impl_float_ops!($f, Div, div, DivAssign, div_assign);
+    };
+}
+
+impl_float_ops!(f32);
+impl_float_ops!(f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResizeFilter;
+
+    #[test]
+    fn test_conversions() {
+        let mono = MonoFrameData::<u16> {
+            width: 2,
+            height: 2,
+            data: [0, 1000, 32768, 65535]
+                .iter()
+                .map(|v| Gray::new(*v))
+                .collect(),
+        };
+        let f = mono.to_float::<f32>(0.5, -100.0);
+        assert_eq!(floats(&f), [-100.0, 400.0, 16284.0, 32667.5]);
+        let back: MonoFrameData<u16> = f.to_mono(2.0, 200.0);
+        assert_eq!(back.data, mono.data);
+
+        // Out-of-range and NaN values are clamped or zeroed
+        let wild = MonoFrameData::<f64> {
+            width: 3,
+            height: 1,
+            data: [-5.0, 300.4, f64::NAN].map(Gray::new).to_vec(),
+        };
+        let m8: MonoFrameData<u8> = wild.to_mono(1.0, 0.0);
+        assert_eq!(m8.data, [Gray::new(0), Gray::new(255), Gray::new(0)]);
+
+        let n = mono.to_normalized::<f64>();
+        assert_eq!(n.data[0].value(), 0.0);
+        assert_eq!(n.data[3].value(), 1.0);
+        assert_eq!(n.from_normalized::<u16>().data, mono.data);
+        let signed = MonoFrameData::<i8> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new(-128), Gray::new(127)],
+        };
+        let n = signed.to_normalized::<f32>();
+        assert!(n.data[0].value().abs() < 1e-6 && (n.data[1].value() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_float_arithmetic() {
+        let light = MonoFrameData::<u16> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new(100), Gray::new(500)],
+        };
+        let dark = MonoFrameData::<u16> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new(120), Gray::new(100)],
+        };
+        // The difference goes negative without wrapping
+        let diff = &light.to_float::<f32>(1.0, 0.0) - &dark.to_float(1.0, 0.0);
+        assert_eq!(floats(&diff), [-20.0, 400.0]);
+        let scaled = &(&diff * 0.5) + 10.0;
+        assert_eq!(floats(&scaled), [0.0, 210.0]);
+        let mut acc = MonoFrameData::<f32>::zeros(2, 1);
+        acc += &diff;
+        acc *= &diff;
+        assert_eq!(floats(&acc), [400.0, 160000.0]);
+        acc /= &diff;
+        acc -= &diff;
+        assert_eq!(floats(&acc), [0.0, 0.0]);
+        assert_eq!(floats(&(&diff / 4.0)), [-5.0, 100.0]);
+
+        // Float frames are mono frames, so the generic transforms apply
+        assert_eq!(floats(&diff.flip_horizontal()), [400.0, -20.0]);
+        assert_eq!(
+            floats(&diff.resize(4, 1, ResizeFilter::Nearest).unwrap()),
+            [-20.0, -20.0, 400.0, 400.0]
+        );
+    }
+
+    fn floats<F: FloatPixel>(frame: &MonoFrameData<F>) -> Vec<F> {
+        frame.data.iter().map(|p| p.value()).collect()
+    }
+}
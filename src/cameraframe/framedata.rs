@@ -13,6 +13,17 @@ where
 
 pub type MonoFrameData<T> = FrameData<Gray<T>>;
 
+/// A borrowed view of frame data, e.g. into a memory-mapped file
+#[derive(Debug, Clone, Copy)]
+pub struct FrameDataView<'a, T>
+where
+    T: Pixel,
+{
+    pub width: u32,
+    pub height: u32,
+    pub data: &'a [T],
+}
+
 impl<T> Default for FrameData<T>
 where
     T: Pixel,
@@ -129,3 +140,33 @@ where
         (y * self.width + x) as usize
     }
 }
+
+impl<T> FrameDataView<'_, T>
+where
+    T: Pixel,
+{
+    /// Get the value at the given x and y coordinates.
+    ///
+    /// # Arguments:
+    /// * `x` - The x coordinate (column) of the value to get.
+    /// * `y` - The y coordinate (row) of the value to get.
+    ///
+    /// # Returns
+    /// The value at the given x and y coordinates.
+    #[inline]
+    pub fn at(&self, x: u32, y: u32) -> T {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    /// Copy the viewed data into an owned FrameData.
+    ///
+    /// # Returns
+    /// A new FrameData containing a copy of the data.
+    pub fn to_frame_data(&self) -> FrameData<T> {
+        FrameData {
+            width: self.width,
+            height: self.height,
+            data: self.data.to_vec(),
+        }
+    }
+}
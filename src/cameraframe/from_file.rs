@@ -0,0 +1,137 @@
+//!
+//! This module contains functions for loading FrameData from PNG files.
+//!
+//! Each pixel type accepts the PNG colour types that can be represented without loss;
+//! 8-bit images may be loaded into 16-bit frames, but not the reverse.
+//!
+
+use super::FrameData;
+use super::FrameFileError;
+use super::PngPixel;
+
+use std::fs::File;
+use std::io::BufReader;
+
+/// Decoded PNG image, along with its header and text chunks
+pub(super) struct PngImage {
+    pub info: png::Info<'static>,
+    pub color_type: png::ColorType,
+    pub bit_depth: png::BitDepth,
+    pub bytes: Vec<u8>,
+}
+
+/// Open a PNG file and read its header, expanding palettes and sub-byte bit depths
+/// to 8 bits
+fn png_reader(filename: &str) -> Result<png::Reader<BufReader<File>>, FrameFileError> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(filename)?));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    Ok(decoder.read_info()?)
+}
+
+/// Read only the header of a PNG file, returning the colour type and bit depth the
+/// image decodes to
+pub(super) fn read_png_type(
+    filename: &str,
+) -> Result<(png::ColorType, png::BitDepth), FrameFileError> {
+    Ok(png_reader(filename)?.output_color_type())
+}
+
+impl PngImage {
+    /// Read a PNG file, expanding palettes and sub-byte bit depths to 8 bits
+    pub fn read(filename: &str) -> Result<Self, FrameFileError> {
+        let mut reader = png_reader(filename)?;
+        let mut bytes = vec![0u8; reader.output_buffer_size()];
+        let output = reader.next_frame(&mut bytes)?;
+        bytes.truncate(output.buffer_size());
+        Ok(PngImage {
+            info: reader.info().clone(),
+            color_type: output.color_type,
+            bit_depth: output.bit_depth,
+            bytes,
+        })
+    }
+
+    /// Convert the image to FrameData of the given pixel type
+    pub fn to_frame_data<T: PngPixel>(&self) -> Result<FrameData<T>, FrameFileError> {
+        let data =
+            T::from_png_bytes(self.color_type, self.bit_depth, &self.bytes).ok_or_else(|| {
+                FrameFileError::Unsupported(format!(
+                    "cannot load {:?} PNG with {}-bit samples as {:?} {}-bit pixels",
+                    self.color_type,
+                    self.bit_depth as u8,
+                    T::COLOR,
+                    T::DEPTH as u8
+                ))
+            })?;
+        Ok(FrameData::<T> {
+            width: self.info.width,
+            height: self.info.height,
+            data,
+        })
+    }
+}
+
+impl<T> FrameData<T>
+where
+    T: PngPixel,
+{
+    /// Load FrameData from a PNG file.
+    ///
+    /// The PNG must have the colour type of the pixel, and a bit depth no greater than
+    /// that of the pixel.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the PNG file to load.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or does not match
+    /// the pixel type.
+    ///
+    pub fn load_from_png(filename: &str) -> Result<Self, FrameFileError> {
+        PngImage::read(filename)?.to_frame_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoFrameData;
+    use rgb::Gray;
+
+    #[test]
+    fn test_png16_roundtrip() {
+        let data = MonoFrameData::<u16> {
+            width: 64,
+            height: 33,
+            data: (0..64 * 33)
+                .map(|x| Gray::<u16>::new((x * 31) as u16))
+                .collect(),
+        };
+        let filename = std::env::temp_dir().join("camera_test_mono16.png");
+        let filename = filename.to_str().unwrap();
+        data.save_to_png(filename).unwrap();
+        let data2 = MonoFrameData::<u16>::load_from_png(filename).unwrap();
+        assert_eq!(data2.width, 64);
+        assert_eq!(data2.height, 33);
+        assert_eq!(data2.data, data.data);
+        assert!(MonoFrameData::<u8>::load_from_png(filename).is_err());
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_rgb16_roundtrip() {
+        let data = FrameData::<rgb::RGB16> {
+            width: 7,
+            height: 3,
+            data: (0..21)
+                .map(|x| rgb::RGB16::new(x * 1000, x * 2000, 65535 - x))
+                .collect(),
+        };
+        let filename = std::env::temp_dir().join("camera_test_rgb16.png");
+        let filename = filename.to_str().unwrap();
+        data.save_to_png(filename).unwrap();
+        let data2 = FrameData::<rgb::RGB16>::load_from_png(filename).unwrap();
+        assert_eq!(data2.data, data.data);
+        let _ = std::fs::remove_file(filename);
+    }
+}
@@ -1,13 +1,45 @@
 mod cameraframe_def;
+mod dispatch;
+mod file_error;
+mod fits;
+mod float_frame;
 mod framedata;
+mod from_file;
 mod mono_cast;
+mod mono_hist;
 mod mono_ops;
 mod mono_stats;
+mod mono_stretch;
+mod netpbm;
+mod npy;
+mod png_metadata;
+mod resample;
+mod tiff_file;
 mod to_file;
+mod transform;
 
 pub use cameraframe_def::CameraFrame;
+pub use file_error::FrameFileError;
+pub use fits::FitsPixel;
 pub use framedata::FrameData;
+pub use framedata::FrameDataView;
 pub use framedata::MonoFrameData;
+pub use mono_hist::AutoLevels;
+pub use mono_hist::Histogram;
+pub use mono_stretch::Stretch;
+pub use netpbm::PnmFormat;
+pub use netpbm::PnmPixel;
+pub(crate) use npy::load_f32_from_npy;
+pub use npy::load_frames_from_npz;
+pub(crate) use npy::save_f32_to_npy;
+pub use npy::save_frames_to_npz;
+pub use npy::NpyPixel;
+pub use resample::BinMode;
+pub use resample::ResampleError;
+pub use resample::ResizeFilter;
+pub use to_file::PngPixel;
+pub use transform::Flip;
+pub use transform::TransformError;
 
 pub type MonoCameraFrame<T> = CameraFrame<rgb::Gray<T>>;
 pub type CameraFrameRGB = CameraFrame<rgb::RGB<u8>>;
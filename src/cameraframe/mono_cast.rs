@@ -2,6 +2,7 @@
 //! This module contains functions that enable casting of FrameData between different pixel types.
 //!
 
+use super::mono_hist::offset;
 use super::FrameData;
 use super::MonoFrameData;
 use crate::colormap::ColorMap;
@@ -50,10 +51,16 @@ where
         gamma: f64,
         cmap: &ColorMap,
     ) -> FrameData<RGBA8> {
-        let maxcolor = 255_i64;
-        let minscale = minscale.to_i64().unwrap();
-        let maxscale = maxscale.to_i64().unwrap();
-        let range = maxscale - minscale;
+        let maxcolor = 255.0;
+        // Differences from the minimum, exact for integer types of any width
+        let diff = |x: T| {
+            if x >= minscale {
+                offset(x, minscale) as f64
+            } else {
+                -(offset(minscale, x) as f64)
+            }
+        };
+        let range = diff(maxscale);
 
         if f64::abs(gamma - 1.0) < 0.02 {
             FrameData::<RGBA8> {
@@ -63,8 +70,9 @@ where
                     .data
                     .iter()
                     .map(|x| {
-                        let idx = (((*x).to_i64().unwrap() - minscale) * maxcolor / range)
-                            .clamp(0, 255) as usize;
+                        let idx = (diff(x.value()) * maxcolor / range)
+                            .floor()
+                            .clamp(0.0, 255.0) as usize;
                         cmap[idx]
                     })
                     .collect(),
@@ -78,10 +86,9 @@ where
                     .data
                     .iter()
                     .map(|x| {
-                        let scaled = (((*x).to_i64().unwrap() - minscale) as f64 / range as f64)
-                            .clamp(0.0, 1.0);
+                        let scaled = (diff(x.value()) / range).clamp(0.0, 1.0);
                         let scaled = f64::powf(scaled, invgamma);
-                        let idx = (scaled * maxcolor as f64).clamp(0.0, 255.0) as usize;
+                        let idx = (scaled * maxcolor).clamp(0.0, 255.0) as usize;
                         cmap[idx]
                     })
                     .collect(),
@@ -0,0 +1,400 @@
+//!
+//! Histograms, percentiles and automatic display levels of monochrome FrameData.
+//!
+//! Frames of 8- and 16-bit pixels are histogrammed at full resolution, one bin per
+//! representable value; wider pixel types, or an explicit bin count, use equal-width
+//! bins spanning the data range.
+//!
+
+use super::FrameData;
+use super::MonoFrameData;
+use crate::colormap::ColorMap;
+use crate::MonoPixel;
+use rgb::RGBA8;
+
+/// Number of bins of a full-resolution histogram of 16-bit pixels
+const FULL_BINS: usize = 65536;
+
+/// Distance of `v` above `base`, exact for every pixel type, or zero if `v` is not
+/// above `base`
+pub(super) fn offset<T: MonoPixel>(v: T, base: T) -> u128 {
+    if v <= base {
+        return 0;
+    }
+    match (v.to_i128(), base.to_i128()) {
+        (Some(v), Some(base)) => v.abs_diff(base),
+        // Only u128 values can exceed i128
+        _ => v.to_u128().unwrap_or(0) - base.to_u128().unwrap_or(0),
+    }
+}
+
+/// Histogram of integer pixel values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Lowest value counted in the first bin, saturating for u128 values beyond
+    /// the range of i128
+    pub start: i128,
+    /// Number of consecutive values counted in each bin
+    pub bin_width: u128,
+    /// Number of pixels in each bin
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// Total number of pixels counted
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Range of values counted in a bin.
+    ///
+    /// # Arguments
+    /// `bin` - Index of the bin.
+    ///
+    /// # Returns
+    /// The lowest and highest values, inclusive, counted in the bin.
+    ///
+    pub fn bin_range(&self, bin: usize) -> (i128, i128) {
+        let lo = self
+            .start
+            .saturating_add_unsigned(bin as u128 * self.bin_width);
+        (lo, lo.saturating_add_unsigned(self.bin_width - 1))
+    }
+
+    /// Value below which the given percentage of pixels fall.
+    ///
+    /// Within a bin wider than one value the result is interpolated linearly, so it is
+    /// exact for full-resolution histograms and approximate otherwise.
+    ///
+    /// # Arguments
+    /// `percent` - The percentile, 0 to 100.
+    ///
+    /// # Returns
+    /// The value at the percentile, or `None` if the histogram is empty.
+    ///
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        // Rank of the wanted pixel in sorted order, 0-based
+        let rank = (percent.clamp(0.0, 100.0) / 100.0 * (total - 1) as f64).round() as u64;
+        let mut seen = 0u64;
+        for (bin, count) in self.counts.iter().enumerate() {
+            if seen + count > rank {
+                let (lo, _) = self.bin_range(bin);
+                let frac = (rank - seen) as f64 / *count as f64;
+                return Some(lo as f64 + (frac * self.bin_width as f64).floor());
+            }
+            seen += count;
+        }
+        None
+    }
+}
+
+/// Method of choosing display levels automatically
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutoLevels {
+    /// Map the `low` and `high` percentiles (0-100) to the ends of the colour map
+    Percentile { low: f64, high: f64 },
+    /// The IRAF zscale algorithm: fit a line to a sample of sorted pixel values and
+    /// span the range around the median given by the slope divided by `contrast`
+    ZScale { contrast: f64 },
+}
+
+impl Default for AutoLevels {
+    fn default() -> Self {
+        AutoLevels::Percentile {
+            low: 0.5,
+            high: 99.5,
+        }
+    }
+}
+
+/// Maximum number of pixels sampled by zscale
+const ZSCALE_SAMPLES: usize = 1000;
+
+/// Display range of sorted sample values by the zscale algorithm
+fn zscale(sorted: &[f64], contrast: f64) -> (f64, f64) {
+    let n = sorted.len();
+    let (first, last) = (sorted[0], sorted[n - 1]);
+    let median = if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    };
+    if n < 5 {
+        return (first, last);
+    }
+
+    // Fit value = intercept + slope * index, iteratively rejecting outliers while
+    // keeping at least half of the samples
+    let mut keep = vec![true; n];
+    let mut slope = 0.0;
+    for _ in 0..5 {
+        let (mut sn, mut sx, mut sy, mut sxx, mut sxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (i, v) in sorted.iter().enumerate().filter(|(i, _)| keep[*i]) {
+            let x = i as f64;
+            sn += 1.0;
+            sx += x;
+            sy += v;
+            sxx += x * x;
+            sxy += x * v;
+        }
+        let denom = sn * sxx - sx * sx;
+        if denom == 0.0 {
+            break;
+        }
+        slope = (sn * sxy - sx * sy) / denom;
+        let intercept = (sy - slope * sx) / sn;
+        let residual = |i: usize| sorted[i] - (intercept + slope * i as f64);
+        let sigma = ((0..n)
+            .filter(|i| keep[*i])
+            .map(|i| residual(i).powi(2))
+            .sum::<f64>()
+            / sn)
+            .sqrt();
+        let next: Vec<bool> = (0..n).map(|i| residual(i).abs() <= 2.5 * sigma).collect();
+        let kept = next.iter().filter(|k| **k).count();
+        if kept < n / 2 || next == keep {
+            break;
+        }
+        keep = next;
+    }
+
+    let center = (n / 2) as f64;
+    let slope = slope / contrast;
+    (
+        (median - center * slope).max(first),
+        (median + (n as f64 - 1.0 - center) * slope).min(last),
+    )
+}
+
+impl<T> MonoFrameData<T>
+where
+    T: MonoPixel,
+{
+    /// Calculate the histogram of the data in the FrameData.
+    ///
+    /// 8- and 16-bit pixel types get one bin per representable value; wider types get
+    /// up to 65536 equal-width bins spanning the data range.
+    ///
+    /// # Returns
+    /// The histogram of the pixel values.
+    ///
+    pub fn histogram(&self) -> Histogram {
+        if std::mem::size_of::<T>() <= 2 {
+            let span = offset(T::max_value(), T::min_value()) + 1;
+            self.histogram_over(T::min_value(), 1, span as usize)
+        } else {
+            self.histogram_with_bins(FULL_BINS)
+        }
+    }
+
+    /// Calculate a histogram with the given number of equal-width bins spanning the
+    /// range of the data.
+    ///
+    /// # Arguments
+    /// `bins` - The maximum number of bins; fewer are used if the data range is
+    ///          narrower, so that each bin covers at least one value.
+    ///
+    /// # Returns
+    /// The histogram of the pixel values.
+    ///
+    pub fn histogram_with_bins(&self, bins: usize) -> Histogram {
+        let values = self.data.iter().map(|p| p.value());
+        let (Some(lo), Some(hi)) = (values.clone().min(), values.max()) else {
+            return Histogram {
+                start: 0,
+                bin_width: 1,
+                counts: vec![0; bins.max(1)],
+            };
+        };
+        let span = offset(hi, lo).saturating_add(1);
+        let bin_width = span.div_ceil(bins.max(1) as u128);
+        self.histogram_over(lo, bin_width, span.div_ceil(bin_width) as usize)
+    }
+
+    fn histogram_over(&self, start: T, bin_width: u128, bins: usize) -> Histogram {
+        let mut counts = vec![0u64; bins];
+        for p in self.data.iter() {
+            let bin = offset(p.value(), start) / bin_width;
+            counts[(bin as usize).min(bins - 1)] += 1;
+        }
+        Histogram {
+            start: start.to_i128().unwrap_or(i128::MAX),
+            bin_width,
+            counts,
+        }
+    }
+
+    /// Calculate a percentile of the data in the FrameData.
+    ///
+    /// # Arguments
+    /// `percent` - The percentile, 0 to 100.
+    ///
+    /// # Returns
+    /// The pixel value below which the given percentage of pixels fall.
+    ///
+    pub fn percentile(&self, percent: f64) -> T {
+        self.histogram()
+            .percentile(percent)
+            .and_then(T::from)
+            .unwrap_or(T::zero())
+    }
+
+    /// Choose display levels for `to_rgba` automatically.
+    ///
+    /// # Arguments
+    /// `method` - How the levels are chosen.
+    ///
+    /// # Returns
+    /// The minimum and maximum scale values; the maximum is always greater than the
+    /// minimum unless the pixel type has a single value.
+    ///
+    pub fn auto_levels(&self, method: AutoLevels) -> (T, T) {
+        let (lo, hi) = match method {
+            AutoLevels::Percentile { low, high } => {
+                let hist = self.histogram();
+                (
+                    hist.percentile(low).unwrap_or(0.0),
+                    hist.percentile(high).unwrap_or(0.0),
+                )
+            }
+            AutoLevels::ZScale { contrast } => {
+                if self.data.is_empty() {
+                    (0.0, 0.0)
+                } else {
+                    let step = self.data.len().div_ceil(ZSCALE_SAMPLES);
+                    let mut sample: Vec<f64> = self
+                        .data
+                        .iter()
+                        .step_by(step)
+                        .map(|p| p.value().to_f64().unwrap())
+                        .collect();
+                    sample.sort_unstable_by(f64::total_cmp);
+                    zscale(&sample, contrast)
+                }
+            }
+        };
+        let tmin = T::min_value().to_f64().unwrap();
+        let tmax = T::max_value().to_f64().unwrap();
+        let mut lo = lo.floor().clamp(tmin, tmax);
+        let mut hi = hi.ceil().clamp(tmin, tmax);
+        if hi <= lo {
+            if lo < tmax {
+                hi = lo + 1.0;
+            } else {
+                lo = (hi - 1.0).max(tmin);
+            }
+        }
+        (
+            T::from(lo).unwrap_or(T::min_value()),
+            T::from(hi).unwrap_or(T::max_value()),
+        )
+    }
+
+    /// Convert the FrameData to an RGBA FrameData with automatically chosen levels.
+    ///
+    /// # Arguments
+    /// * `method` - How the display levels are chosen.
+    /// * `gamma` - The gamma applied between the levels.
+    /// * `cmap` - The color map to use.
+    ///
+    /// # Returns
+    /// An RGBA FrameData.
+    ///
+    pub fn to_rgba_auto(
+        &self,
+        method: AutoLevels,
+        gamma: f64,
+        cmap: &ColorMap,
+    ) -> FrameData<RGBA8> {
+        let (minscale, maxscale) = self.auto_levels(method);
+        self.to_rgba(minscale, maxscale, gamma, cmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgb::Gray;
+
+    fn ramp(n: u16) -> MonoFrameData<u16> {
+        MonoFrameData {
+            width: n as u32,
+            height: 1,
+            data: (0..n).map(Gray::new).collect(),
+        }
+    }
+
+    #[test]
+    fn test_histogram_and_percentiles() {
+        let frame = ramp(1000);
+        let hist = frame.histogram();
+        assert_eq!(hist.counts.len(), 65536);
+        assert_eq!(hist.total(), 1000);
+        assert_eq!(hist.counts[999], 1);
+        assert_eq!(hist.counts[1000], 0);
+        assert_eq!(frame.percentile(0.0), 0);
+        assert_eq!(frame.percentile(50.0), 500);
+        assert_eq!(frame.percentile(100.0), 999);
+
+        let coarse = frame.histogram_with_bins(10);
+        assert_eq!(coarse.bin_width, 100);
+        assert_eq!(coarse.counts, vec![100; 10]);
+        assert_eq!(coarse.bin_range(3), (300, 399));
+        assert_eq!(coarse.percentile(25.0), Some(250.0));
+
+        let narrow = MonoFrameData::<u32> {
+            width: 3,
+            height: 1,
+            data: vec![Gray::new(7), Gray::new(8), Gray::new(8)],
+        };
+        assert_eq!(narrow.histogram().counts, vec![1, 2]);
+
+        // Values beyond the range of i64, and spans wider than it, are binned
+        let wide = MonoFrameData::<u64> {
+            width: 3,
+            height: 1,
+            data: vec![Gray::new(0), Gray::new(u64::MAX - 1), Gray::new(u64::MAX)],
+        };
+        let hist = wide.histogram_with_bins(4);
+        assert_eq!(hist.counts, vec![1, 0, 0, 2]);
+        assert_eq!(hist.bin_range(3).1, u64::MAX as i128);
+        assert_eq!(wide.percentile(0.0), 0);
+        assert!(wide.percentile(100.0) >= u64::MAX - (u64::MAX >> 16));
+        let signed = MonoFrameData::<i64> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new(i64::MIN), Gray::new(i64::MAX)],
+        };
+        assert_eq!(signed.histogram().start, i64::MIN as i128);
+        assert_eq!(signed.percentile(0.0), i64::MIN);
+        let rgba = signed.to_rgba_auto(AutoLevels::default(), 1.0, crate::colormap::grayscale());
+        assert_eq!(rgba.data[1], crate::colormap::grayscale()[255]);
+    }
+
+    #[test]
+    fn test_auto_levels() {
+        let mut frame = ramp(1000);
+        // A few hot pixels must not set the upper level
+        for p in frame.data.iter_mut().take(3) {
+            *p = Gray::new(65535);
+        }
+        let (lo, hi) = frame.auto_levels(AutoLevels::default());
+        assert!(lo <= 10 && (985..=1000).contains(&hi), "{} {}", lo, hi);
+
+        let (lo, hi) = frame.auto_levels(AutoLevels::ZScale { contrast: 0.25 });
+        assert!(lo < hi && hi < 65535, "{} {}", lo, hi);
+
+        let flat = MonoFrameData::<u8> {
+            width: 4,
+            height: 4,
+            data: vec![Gray::new(255); 16],
+        };
+        assert_eq!(flat.auto_levels(AutoLevels::default()), (254, 255));
+        let rgba = flat.to_rgba_auto(AutoLevels::default(), 1.0, crate::colormap::grayscale());
+        assert_eq!(rgba.data[0], crate::colormap::grayscale()[255]);
+    }
+}
@@ -1,4 +1,10 @@
 //! This module contains the implementation of the arithmetic operations for the `FrameData` struct.
+//!
+//! The operators use the integer operations of the pixel type, which panic on
+//! overflow in debug builds and wrap in release builds.  The `saturating_*` methods
+//! clamp results to the range of the type instead, and the `checked_*` methods
+//! return `None` if any pixel overflows.  For results that may be negative or
+//! fractional, convert to a floating-point frame with `to_float`.
 
 use super::FrameData;
 use super::MonoFrameData;
@@ -7,7 +13,7 @@ use rgb::Gray;
 
 impl<T> MonoFrameData<T>
 where
-    T: crate::MonoPixel,
+    T: crate::pixel::GrayValue,
 {
     pub fn zeros(width: u32, height: u32) -> FrameData<Gray<T>> {
         FrameData::<Gray<T>> {
@@ -220,6 +226,154 @@ where
     }
 }
 
+/// Product of two integers, saturating at the bound the true product lies beyond
+fn saturating_mul<T: MonoPixel>(a: T, b: T) -> T {
+    a.checked_mul(&b).unwrap_or_else(|| {
+        if (a < T::zero()) != (b < T::zero()) {
+            T::min_value()
+        } else {
+            T::max_value()
+        }
+    })
+}
+
+impl<T> MonoFrameData<T>
+where
+    T: MonoPixel,
+{
+    /// Combine two frames of the same size pixel-by-pixel
+    fn zip_with(&self, other: &MonoFrameData<T>, op: impl Fn(T, T) -> T) -> MonoFrameData<T> {
+        assert_eq!(self.width, other.width);
+        assert_eq!(self.height, other.height);
+        MonoFrameData::<T> {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(other.data.iter())
+                .map(|(a, b)| Gray::new(op(a.value(), b.value())))
+                .collect(),
+        }
+    }
+
+    /// Combine two frames of the same size pixel-by-pixel, or `None` if any pixel fails
+    fn try_zip_with(
+        &self,
+        other: &MonoFrameData<T>,
+        op: impl Fn(&T, &T) -> Option<T>,
+    ) -> Option<MonoFrameData<T>> {
+        assert_eq!(self.width, other.width);
+        assert_eq!(self.height, other.height);
+        Some(MonoFrameData::<T> {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(other.data.iter())
+                .map(|(a, b)| op(&a.value(), &b.value()).map(Gray::new))
+                .collect::<Option<Vec<_>>>()?,
+        })
+    }
+
+    /// Add two frames, clamping each sum to the range of the pixel type.
+    ///
+    /// # Panics
+    /// Panics if the frames differ in size.
+    ///
+    pub fn saturating_add(&self, other: &MonoFrameData<T>) -> MonoFrameData<T> {
+        self.zip_with(other, |a, b| a.saturating_add(b))
+    }
+
+    /// Subtract a frame, clamping each difference to the range of the pixel type,
+    /// e.g. to zero for unsigned pixels.
+    ///
+    /// # Panics
+    /// Panics if the frames differ in size.
+    ///
+    pub fn saturating_sub(&self, other: &MonoFrameData<T>) -> MonoFrameData<T> {
+        self.zip_with(other, |a, b| a.saturating_sub(b))
+    }
+
+    /// Multiply two frames, clamping each product to the range of the pixel type.
+    ///
+    /// # Panics
+    /// Panics if the frames differ in size.
+    ///
+    pub fn saturating_mul(&self, other: &MonoFrameData<T>) -> MonoFrameData<T> {
+        self.zip_with(other, saturating_mul)
+    }
+
+    /// Multiply every pixel by a value, clamping each product to the range of the
+    /// pixel type.
+    pub fn saturating_scale(&self, factor: T) -> MonoFrameData<T> {
+        MonoFrameData::<T> {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .map(|a| Gray::new(saturating_mul(a.value(), factor)))
+                .collect(),
+        }
+    }
+
+    /// Add two frames.
+    ///
+    /// # Returns
+    /// The sum, or `None` if any pixel overflows.
+    ///
+    /// # Panics
+    /// Panics if the frames differ in size.
+    ///
+    pub fn checked_add(&self, other: &MonoFrameData<T>) -> Option<MonoFrameData<T>> {
+        self.try_zip_with(other, T::checked_add)
+    }
+
+    /// Subtract a frame.
+    ///
+    /// # Returns
+    /// The difference, or `None` if any pixel overflows, e.g. goes negative for
+    /// unsigned pixels.
+    ///
+    /// # Panics
+    /// Panics if the frames differ in size.
+    ///
+    pub fn checked_sub(&self, other: &MonoFrameData<T>) -> Option<MonoFrameData<T>> {
+        self.try_zip_with(other, T::checked_sub)
+    }
+
+    /// Multiply two frames.
+    ///
+    /// # Returns
+    /// The product, or `None` if any pixel overflows.
+    ///
+    /// # Panics
+    /// Panics if the frames differ in size.
+    ///
+    pub fn checked_mul(&self, other: &MonoFrameData<T>) -> Option<MonoFrameData<T>> {
+        self.try_zip_with(other, T::checked_mul)
+    }
+
+    /// Multiply every pixel by a value.
+    ///
+    /// # Returns
+    /// The product, or `None` if any pixel overflows.
+    ///
+    pub fn checked_scale(&self, factor: T) -> Option<MonoFrameData<T>> {
+        Some(MonoFrameData::<T> {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .map(|a| a.value().checked_mul(&factor).map(Gray::new))
+                .collect::<Option<Vec<_>>>()?,
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -259,4 +413,53 @@ mod test {
         assert_eq!(frame3.height, 3);
         //assert_eq!(frame3.data, vec![2; 9]);
     }
+
+    fn frame<T: MonoPixel>(values: &[T]) -> MonoFrameData<T> {
+        MonoFrameData::<T> {
+            width: values.len() as u32,
+            height: 1,
+            data: values.iter().map(|v| Gray::new(*v)).collect(),
+        }
+    }
+
+    fn values<T: MonoPixel>(frame: &MonoFrameData<T>) -> Vec<T> {
+        frame.data.iter().map(|p| p.value()).collect()
+    }
+
+    #[test]
+    fn test_saturating() {
+        let light = frame::<u16>(&[100, 65000, 3]);
+        let dark = frame::<u16>(&[120, 1000, 3]);
+        assert_eq!(values(&light.saturating_sub(&dark)), [0, 64000, 0]);
+        assert_eq!(values(&light.saturating_add(&dark)), [220, 65535, 6]);
+        assert_eq!(values(&light.saturating_mul(&dark)), [12000, 65535, 9]);
+        assert_eq!(values(&light.saturating_scale(700)), [65535, 65535, 2100]);
+
+        let signed = frame::<i16>(&[-200, 200, -5]);
+        assert_eq!(
+            values(&signed.saturating_scale(300)),
+            [i16::MIN, i16::MAX, -1500]
+        );
+        assert_eq!(
+            values(&signed.saturating_mul(&frame(&[-200, 200, 5]))),
+            [i16::MAX, i16::MAX, -25]
+        );
+    }
+
+    #[test]
+    fn test_checked() {
+        let light = frame::<u8>(&[100, 250]);
+        let dark = frame::<u8>(&[20, 10]);
+        assert_eq!(values(&light.checked_sub(&dark).unwrap()), [80, 240]);
+        assert!(dark.checked_sub(&light).is_none());
+        assert!(light.checked_add(&dark).is_none());
+        assert_eq!(values(&dark.checked_add(&dark).unwrap()), [40, 20]);
+        assert!(dark.checked_mul(&dark).is_none());
+        assert_eq!(
+            values(&dark.checked_mul(&frame(&[2, 3])).unwrap()),
+            [40, 30]
+        );
+        assert_eq!(values(&dark.checked_scale(12).unwrap()), [240, 120]);
+        assert!(dark.checked_scale(13).is_none());
+    }
 }
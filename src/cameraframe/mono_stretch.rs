@@ -0,0 +1,357 @@
+//!
+//! Non-linear display stretches of monochrome FrameData.
+//!
+//! A stretch maps pixel values between the display levels onto the 256 entries of a
+//! `ColorMap`.  For 8- and 16-bit pixels the mapping is precomputed as a lookup
+//! table over every representable value, so converting a frame costs one table
+//! lookup per pixel whatever the stretch.
+//!
+
+use super::mono_hist::offset;
+use super::FrameData;
+use super::MonoFrameData;
+use crate::colormap::ColorMap;
+use crate::MonoPixel;
+use rgb::RGBA8;
+
+/// Mapping of normalized pixel values, 0 at the minimum display level and 1 at the
+/// maximum, onto the colour map
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Stretch {
+    #[default]
+    Linear,
+    /// Power law `x^(1/gamma)`, as used by `to_rgba`
+    Gamma(f64),
+    /// `log(scale * x + 1) / log(scale + 1)`; larger scales lift faint values more
+    Log {
+        scale: f64,
+    },
+    Sqrt,
+    /// `asinh(x / softening) / asinh(1 / softening)`; linear for values well below
+    /// the softening parameter and logarithmic above it
+    Asinh {
+        softening: f64,
+    },
+    /// `sinh(scale * x) / sinh(scale)`; compresses faint values and expands bright ones
+    Sinh {
+        scale: f64,
+    },
+    /// Global histogram equalization of the values between the display levels
+    Equalize,
+    /// Contrast-limited adaptive histogram equalization over a grid of tiles, with
+    /// each tile histogram clipped at `clip_limit` times its mean bin count
+    Clahe {
+        tiles_x: u32,
+        tiles_y: u32,
+        clip_limit: f64,
+    },
+}
+
+impl Stretch {
+    /// Apply a pointwise stretch to a normalized value.
+    ///
+    /// # Arguments
+    /// `x` - The normalized value, clamped to 0 to 1.
+    ///
+    /// # Returns
+    /// The stretched value, 0 to 1.  Histogram-based stretches depend on the whole
+    /// frame and return the value unchanged.
+    ///
+    pub fn apply(&self, x: f64) -> f64 {
+        let x = x.clamp(0.0, 1.0);
+        let y = match *self {
+            Stretch::Linear | Stretch::Equalize | Stretch::Clahe { .. } => x,
+            Stretch::Gamma(gamma) => x.powf(1.0 / gamma),
+            Stretch::Log { scale } => (scale * x).ln_1p() / scale.ln_1p(),
+            Stretch::Sqrt => x.sqrt(),
+            Stretch::Asinh { softening } => (x / softening).asinh() / (1.0 / softening).asinh(),
+            Stretch::Sinh { scale } => (scale * x).sinh() / scale.sinh(),
+        };
+        y.clamp(0.0, 1.0)
+    }
+}
+
+/// Colour map index of a stretched value
+fn to_index(y: f64) -> u8 {
+    (y * 255.0).clamp(0.0, 255.0) as u8
+}
+
+/// Number of colour map levels
+const LEVELS: usize = 256;
+
+/// Largest number of histogram bins used for equalization, enough for one bin per
+/// value of 16-bit data
+const EQUALIZE_BINS: u128 = 1 << 16;
+
+impl<T> MonoFrameData<T>
+where
+    T: MonoPixel,
+{
+    /// Colour map index of every pixel for a global stretch
+    fn stretch_indices(&self, minscale: T, maxscale: T, stretch: &Stretch) -> Vec<u8> {
+        let lo = minscale.to_f64().unwrap();
+        let range = (maxscale.to_f64().unwrap() - lo).max(f64::MIN_POSITIVE);
+
+        let index: Box<dyn Fn(T) -> u8> = if *stretch == Stretch::Equalize {
+            // Fraction of pixels at or below each value, after clamping all values to
+            // the levels, rescaled so the minimum level maps to zero
+            let (minscale, maxscale) = (minscale.min(maxscale), minscale.max(maxscale));
+            let span = offset(maxscale, minscale).saturating_add(1);
+            let width = span.div_ceil(EQUALIZE_BINS);
+            let bins = span.div_ceil(width) as usize;
+            let bin = move |v: T| {
+                let v = v.clamp(minscale, maxscale);
+                ((offset(v, minscale) / width) as usize).min(bins - 1)
+            };
+            let mut counts = vec![0u64; bins];
+            self.data.iter().for_each(|p| counts[bin(p.value())] += 1);
+            let mut total = 0;
+            let cdf: Vec<u64> = counts
+                .iter()
+                .map(|c| {
+                    total += c;
+                    total
+                })
+                .collect();
+            let n = self.data.len() as u64;
+            let cmin = cdf[0];
+            Box::new(move |v: T| {
+                let b = bin(v);
+                let c = if b >= bins - 1 { n } else { cdf[b] };
+                if n <= cmin {
+                    0
+                } else {
+                    to_index((c - cmin) as f64 / (n - cmin) as f64)
+                }
+            })
+        } else {
+            let stretch = *stretch;
+            Box::new(move |v: T| stretch_value(&stretch, v, lo, range))
+        };
+
+        if std::mem::size_of::<T>() <= 2 {
+            // Every 8- and 16-bit value fits in i32
+            let start = T::min_value().to_i32().unwrap_or(0);
+            let end = T::max_value().to_i32().unwrap_or(0);
+            let lut: Vec<u8> = (start..=end)
+                .map(|v| index(T::from(v).unwrap_or(T::zero())))
+                .collect();
+            self.data
+                .iter()
+                .map(|p| lut[offset(p.value(), T::min_value()) as usize])
+                .collect()
+        } else {
+            self.data.iter().map(|p| index(p.value())).collect()
+        }
+    }
+
+    /// Contrast-limited adaptive histogram equalization of linear colour map indices
+    fn clahe(&self, linear: &[u8], tiles_x: u32, tiles_y: u32, clip_limit: f64) -> Vec<u8> {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let tx = (tiles_x.max(1) as usize).min(w.max(1));
+        let ty = (tiles_y.max(1) as usize).min(h.max(1));
+
+        // Equalization map of each tile
+        let maps: Vec<[f64; LEVELS]> = (0..ty * tx)
+            .map(|t| {
+                let (i, j) = (t % tx, t / tx);
+                let (x0, x1) = (i * w / tx, (i + 1) * w / tx);
+                let (y0, y1) = (j * h / ty, (j + 1) * h / ty);
+                let mut hist = [0f64; LEVELS];
+                for y in y0..y1 {
+                    for l in &linear[y * w + x0..y * w + x1] {
+                        hist[*l as usize] += 1.0;
+                    }
+                }
+                let npix = ((x1 - x0) * (y1 - y0)).max(1) as f64;
+                let limit = (clip_limit * npix / LEVELS as f64).max(1.0);
+                let excess: f64 = hist.iter().map(|c| (c - limit).max(0.0)).sum();
+                let mut map = [0f64; LEVELS];
+                let mut cdf = 0.0;
+                for (m, c) in map.iter_mut().zip(hist.iter()) {
+                    cdf += c.min(limit) + excess / LEVELS as f64;
+                    *m = 255.0 * cdf / npix;
+                }
+                map
+            })
+            .collect();
+
+        // Interpolate bilinearly between the maps of the nearest tile centres
+        let axis = |p: usize, len: usize, tiles: usize| {
+            let f =
+                ((p as f64 + 0.5) * tiles as f64 / len as f64 - 0.5).clamp(0.0, (tiles - 1) as f64);
+            let i0 = f.floor() as usize;
+            (i0, (i0 + 1).min(tiles - 1), f - i0 as f64)
+        };
+        let mut out = Vec::with_capacity(linear.len());
+        for y in 0..h {
+            let (j0, j1, wy) = axis(y, h, ty);
+            for x in 0..w {
+                let (i0, i1, wx) = axis(x, w, tx);
+                let l = linear[y * w + x] as usize;
+                let top = maps[j0 * tx + i0][l] * (1.0 - wx) + maps[j0 * tx + i1][l] * wx;
+                let bottom = maps[j1 * tx + i0][l] * (1.0 - wx) + maps[j1 * tx + i1][l] * wx;
+                out.push((top * (1.0 - wy) + bottom * wy).round().clamp(0.0, 255.0) as u8);
+            }
+        }
+        out
+    }
+
+    /// Convert the FrameData to an RGBA FrameData through a display stretch.
+    ///
+    /// # Arguments
+    /// * `minscale` - The value of the data that matches the minimum value of the color map.
+    /// * `maxscale` - The value of the data that matches the maximum value of the color map.
+    /// * `stretch` - The mapping of values between the scale limits onto the color map.
+    /// * `cmap` - The color map to use.
+    ///
+    /// # Returns
+    /// An RGBA FrameData.
+    ///
+    pub fn to_rgba_stretched(
+        &self,
+        minscale: T,
+        maxscale: T,
+        stretch: &Stretch,
+        cmap: &ColorMap,
+    ) -> FrameData<RGBA8> {
+        let indices = match *stretch {
+            Stretch::Clahe {
+                tiles_x,
+                tiles_y,
+                clip_limit,
+            } => {
+                let linear = self.stretch_indices(minscale, maxscale, &Stretch::Linear);
+                self.clahe(&linear, tiles_x, tiles_y, clip_limit)
+            }
+            _ => self.stretch_indices(minscale, maxscale, stretch),
+        };
+        FrameData::<RGBA8> {
+            width: self.width,
+            height: self.height,
+            data: indices.into_iter().map(|i| cmap[i as usize]).collect(),
+        }
+    }
+}
+
+/// Colour map index of a pixel value under a pointwise stretch
+fn stretch_value<T: MonoPixel>(stretch: &Stretch, v: T, lo: f64, range: f64) -> u8 {
+    to_index(stretch.apply((v.to_f64().unwrap() - lo) / range))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colormap::grayscale;
+    use rgb::Gray;
+
+    fn gray(frame: &FrameData<RGBA8>) -> Vec<u8> {
+        frame.data.iter().map(|p| p.r).collect()
+    }
+
+    #[test]
+    fn test_point_stretches() {
+        let frame = MonoFrameData::<u16> {
+            width: 5,
+            height: 1,
+            data: [0, 100, 500, 1000, 4000].map(Gray::new).to_vec(),
+        };
+        let linear = frame.to_rgba_stretched(0, 1000, &Stretch::Linear, grayscale());
+        assert_eq!(
+            gray(&linear),
+            gray(&frame.to_rgba(0, 1000, 1.0, grayscale()))
+        );
+        assert_eq!(gray(&linear), vec![0, 25, 127, 255, 255]);
+
+        for stretch in [
+            Stretch::Gamma(2.2),
+            Stretch::Log { scale: 1000.0 },
+            Stretch::Sqrt,
+            Stretch::Asinh { softening: 0.1 },
+        ] {
+            let s = gray(&frame.to_rgba_stretched(0, 1000, &stretch, grayscale()));
+            assert_eq!((s[0], s[3]), (0, 255), "{:?}", stretch);
+            assert!(s[1] > 25 && s[2] > 127, "{:?}", stretch);
+        }
+        let s = gray(&frame.to_rgba_stretched(0, 1000, &Stretch::Sinh { scale: 3.0 }, grayscale()));
+        assert!(s[1] < 25 && s[2] < 127 && s[3] == 255);
+
+        // Wide pixel types are mapped without a lookup table
+        let wide = MonoFrameData::<u32> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new(250), Gray::new(1000)],
+        };
+        let s = gray(&wide.to_rgba_stretched(0, 1000, &Stretch::Sqrt, grayscale()));
+        assert_eq!(s, vec![127, 255]);
+    }
+
+    #[test]
+    fn test_equalization() {
+        // Mostly faint pixels with a few bright ones
+        let mut data: Vec<Gray<u8>> = (0..90).map(|i| Gray::new(i % 10)).collect();
+        data.extend((0..10).map(|i| Gray::new(200 + i)));
+        let frame = MonoFrameData::<u8> {
+            width: 10,
+            height: 10,
+            data,
+        };
+        let eq = gray(&frame.to_rgba_stretched(0, 255, &Stretch::Equalize, grayscale()));
+        // Faint values spread over most of the output range
+        assert_eq!(eq[0], 0);
+        assert!(eq[9] > 200);
+        assert_eq!(eq[99], 255);
+
+        // Wider types equalize like 16-bit data, and inverted levels are ordered
+        let f16 = MonoFrameData::<u16> {
+            width: 4,
+            height: 1,
+            data: [100, 200, 300, 400].map(Gray::new).to_vec(),
+        };
+        let f32bit = MonoFrameData::<u32> {
+            width: 4,
+            height: 1,
+            data: [100, 200, 300, 400].map(Gray::new).to_vec(),
+        };
+        let eq16 = gray(&f16.to_rgba_stretched(0, 500, &Stretch::Equalize, grayscale()));
+        assert_eq!(eq16, [63, 127, 191, 255]);
+        let eq32 = gray(&f32bit.to_rgba_stretched(0, 500, &Stretch::Equalize, grayscale()));
+        assert_eq!(eq32, eq16);
+        let inverted = gray(&f16.to_rgba_stretched(500, 0, &Stretch::Equalize, grayscale()));
+        assert_eq!(inverted, eq16);
+        let f64bit = MonoFrameData::<u64> {
+            width: 4,
+            height: 1,
+            data: [0, 1 << 62, 1 << 63, u64::MAX].map(Gray::new).to_vec(),
+        };
+        let eq64 = gray(&f64bit.to_rgba_stretched(0, u64::MAX, &Stretch::Equalize, grayscale()));
+        assert_eq!(eq64, [0, 85, 170, 255]);
+
+        // Two halves of very different brightness both get full contrast
+        let frame = MonoFrameData::<u16> {
+            width: 64,
+            height: 32,
+            data: (0..32 * 64)
+                .map(|i| {
+                    let (x, y) = (i % 64, i / 64);
+                    Gray::new(if x < 32 { 1000 } else { 50000 } + (x + y) as u16 % 8 * 100)
+                })
+                .collect(),
+        };
+        let spread = |clip_limit| {
+            let clahe = Stretch::Clahe {
+                tiles_x: 2,
+                tiles_y: 1,
+                clip_limit,
+            };
+            let s = gray(&frame.to_rgba_stretched(0, 65535, &clahe, grayscale()));
+            let range = |v: &[u8]| v.iter().max().unwrap() - v.iter().min().unwrap();
+            (range(&s[0..8]), range(&s[56..64]))
+        };
+        let (left, right) = spread(100.0);
+        assert!(left > 100 && right > 100, "{} {}", left, right);
+        // A low clip limit restrains the contrast gain
+        let (left, right) = spread(2.0);
+        assert!(left < 50 && right < 50, "{} {}", left, right);
+    }
+}
@@ -0,0 +1,483 @@
+//!
+//! This module contains functions for reading and writing frames to Netpbm files.
+//!
+//! Monochrome frames are stored as PGM (`P5` binary or `P2` ASCII) and RGB frames as
+//! PPM (`P6` binary or `P3` ASCII).  Sample values are stored unscaled, with the
+//! maximum value (`maxval`) in the header describing their range; binary files use
+//! one byte per sample when `maxval` is below 256, and two big-endian bytes otherwise.
+//!
+
+use super::CameraFrame;
+use super::FrameData;
+use super::FrameFileError;
+use crate::Pixel;
+use rgb::Gray;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+/// Netpbm encoding of the sample values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PnmFormat {
+    /// Raw binary samples (`P5` / `P6`)
+    Binary,
+    /// Plain-text decimal samples (`P2` / `P3`)
+    Ascii,
+}
+
+/// Pixel types that can be stored in a Netpbm file
+pub trait PnmPixel: Pixel {
+    /// Number of samples per pixel: 1 for PGM, 3 for PPM
+    const CHANNELS: usize;
+
+    /// Largest sample value the pixel type can hold
+    const MAXVAL: u16;
+
+    /// Value of the given sample (colour channel) of the pixel
+    fn sample(&self, channel: usize) -> u16;
+
+    /// Construct a pixel from its samples
+    fn from_samples(samples: &[u16]) -> Self;
+}
+
+impl PnmPixel for Gray<u8> {
+    const CHANNELS: usize = 1;
+    const MAXVAL: u16 = u8::MAX as u16;
+
+    fn sample(&self, _channel: usize) -> u16 {
+        self.value() as u16
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        Gray::<u8>::new(samples[0] as u8)
+    }
+}
+
+impl PnmPixel for Gray<u16> {
+    const CHANNELS: usize = 1;
+    const MAXVAL: u16 = u16::MAX;
+
+    fn sample(&self, _channel: usize) -> u16 {
+        self.value()
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        Gray::<u16>::new(samples[0])
+    }
+}
+
+impl PnmPixel for rgb::RGB8 {
+    const CHANNELS: usize = 3;
+    const MAXVAL: u16 = u8::MAX as u16;
+
+    fn sample(&self, channel: usize) -> u16 {
+        match channel {
+            0 => self.r as u16,
+            1 => self.g as u16,
+            _ => self.b as u16,
+        }
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        rgb::RGB8::new(samples[0] as u8, samples[1] as u8, samples[2] as u8)
+    }
+}
+
+impl PnmPixel for rgb::RGB16 {
+    const CHANNELS: usize = 3;
+    const MAXVAL: u16 = u16::MAX;
+
+    fn sample(&self, channel: usize) -> u16 {
+        match channel {
+            0 => self.r,
+            1 => self.g,
+            _ => self.b,
+        }
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        rgb::RGB16::new(samples[0], samples[1], samples[2])
+    }
+}
+
+/// Maximum length of a line in an ASCII Netpbm file
+const ASCII_LINE_LENGTH: usize = 70;
+
+/// Header of a Netpbm file
+pub(super) struct PnmHeader {
+    /// Number of samples per pixel: 1 for PGM, 3 for PPM
+    pub channels: usize,
+    /// Whether the samples are plain-text decimal
+    pub ascii: bool,
+    pub width: u32,
+    pub height: u32,
+    pub maxval: u16,
+}
+
+/// Read the next whitespace-separated token, skipping comments; the whitespace
+/// character ending the token is consumed
+fn next_token<R: BufRead>(reader: &mut R) -> Result<String, FrameFileError> {
+    let next_byte = |reader: &mut R| -> Result<Option<u8>, FrameFileError> {
+        let byte = reader.fill_buf()?.first().copied();
+        if byte.is_some() {
+            reader.consume(1);
+        }
+        Ok(byte)
+    };
+    // Tokens may be interleaved with comments, which run to the end of the line
+    let first = loop {
+        match next_byte(reader)? {
+            Some(b'#') => while next_byte(reader)?.is_some_and(|b| b != b'\n') {},
+            Some(b) if b.is_ascii_whitespace() => {}
+            Some(b) => break b,
+            None => {
+                return Err(FrameFileError::Format(
+                    "unexpected end of Netpbm file".to_string(),
+                ))
+            }
+        }
+    };
+    let mut token = vec![first];
+    while let Some(b) = next_byte(reader)?.filter(|b| !b.is_ascii_whitespace()) {
+        token.push(b);
+    }
+    Ok(String::from_utf8_lossy(&token).to_string())
+}
+
+fn invalid(what: &str) -> FrameFileError {
+    FrameFileError::Format(format!("invalid Netpbm {}", what))
+}
+
+impl PnmHeader {
+    /// Read the header, leaving the reader at the start of the raster
+    fn read<R: BufRead>(reader: &mut R) -> Result<Self, FrameFileError> {
+        let magic = next_token(reader)?;
+        let (channels, ascii) = match magic.as_str() {
+            "P2" => (1, true),
+            "P3" => (3, true),
+            "P5" => (1, false),
+            "P6" => (3, false),
+            _ => {
+                return Err(FrameFileError::Unsupported(format!(
+                    "Netpbm format '{}'",
+                    magic
+                )))
+            }
+        };
+        let width: u32 = next_token(reader)?.parse().map_err(|_| invalid("width"))?;
+        let height: u32 = next_token(reader)?.parse().map_err(|_| invalid("height"))?;
+        let maxval: u16 = next_token(reader)?.parse().map_err(|_| invalid("maxval"))?;
+        if maxval == 0 {
+            return Err(invalid("maxval"));
+        }
+        Ok(PnmHeader {
+            channels,
+            ascii,
+            width,
+            height,
+            maxval,
+        })
+    }
+
+    /// Read only the header of a Netpbm file
+    pub fn read_file(filename: &str) -> Result<Self, FrameFileError> {
+        Self::read(&mut BufReader::new(File::open(filename)?))
+    }
+}
+
+impl<T> FrameData<T>
+where
+    T: PnmPixel,
+{
+    /// Write the frame as a Netpbm file with the given maxval
+    fn write_pnm(
+        &self,
+        filename: &str,
+        format: PnmFormat,
+        maxval: u16,
+    ) -> Result<(), FrameFileError> {
+        let magic = match (format, T::CHANNELS) {
+            (PnmFormat::Ascii, 1) => "P2",
+            (PnmFormat::Ascii, _) => "P3",
+            (PnmFormat::Binary, 1) => "P5",
+            (PnmFormat::Binary, _) => "P6",
+        };
+        let mut writer = BufWriter::new(File::create(filename)?);
+        write!(
+            writer,
+            "{}\n{} {}\n{}\n",
+            magic, self.width, self.height, maxval
+        )?;
+
+        let samples = self
+            .data
+            .iter()
+            .flat_map(|p| (0..T::CHANNELS).map(move |c| p.sample(c)));
+        match format {
+            PnmFormat::Binary => {
+                let bytes: Vec<u8> = if maxval < 256 {
+                    samples.map(|s| s as u8).collect()
+                } else {
+                    samples.flat_map(|s| s.to_be_bytes()).collect()
+                };
+                writer.write_all(&bytes)?;
+            }
+            PnmFormat::Ascii => {
+                let row_len = self.width as usize * T::CHANNELS;
+                let mut line = String::new();
+                for (i, s) in samples.enumerate() {
+                    let s = s.to_string();
+                    if !line.is_empty() && line.len() + 1 + s.len() > ASCII_LINE_LENGTH {
+                        writeln!(writer, "{}", line)?;
+                        line.clear();
+                    }
+                    if !line.is_empty() {
+                        line.push(' ');
+                    }
+                    line.push_str(&s);
+                    if (i + 1) % row_len == 0 {
+                        writeln!(writer, "{}", line)?;
+                        line.clear();
+                    }
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a Netpbm file, returning the frame and the maxval from its header
+    fn read_pnm(filename: &str) -> Result<(Self, u16), FrameFileError> {
+        let mut reader = BufReader::new(File::open(filename)?);
+        let PnmHeader {
+            channels,
+            ascii,
+            width,
+            height,
+            maxval,
+        } = PnmHeader::read(&mut reader)?;
+        if channels != T::CHANNELS {
+            return Err(FrameFileError::Unsupported(format!(
+                "cannot load {}-channel Netpbm file into pixels with {} channel(s)",
+                channels,
+                T::CHANNELS
+            )));
+        }
+        if maxval > T::MAXVAL {
+            return Err(FrameFileError::Unsupported(format!(
+                "maxval {} does not fit in the pixel type",
+                maxval
+            )));
+        }
+
+        let nsamples = width as usize * height as usize * channels;
+        let samples: Vec<u16> = if ascii {
+            (0..nsamples)
+                .map(|_| {
+                    next_token(&mut reader)?
+                        .parse::<u16>()
+                        .map_err(|_| invalid("sample"))
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            // The single whitespace character ending the header has been consumed
+            let mut raster = Vec::new();
+            reader.read_to_end(&mut raster)?;
+            if maxval < 256 {
+                raster.iter().take(nsamples).map(|b| *b as u16).collect()
+            } else {
+                raster
+                    .chunks_exact(2)
+                    .take(nsamples)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .collect()
+            }
+        };
+        if samples.len() != nsamples {
+            return Err(FrameFileError::Format(
+                "truncated Netpbm raster".to_string(),
+            ));
+        }
+        if samples.iter().any(|v| *v > maxval) {
+            return Err(FrameFileError::Format(format!(
+                "Netpbm sample exceeds maxval {}",
+                maxval
+            )));
+        }
+
+        Ok((
+            FrameData::<T> {
+                width,
+                height,
+                data: samples
+                    .chunks_exact(channels)
+                    .map(T::from_samples)
+                    .collect(),
+            },
+            maxval,
+        ))
+    }
+
+    /// Save the FrameData to a Netpbm file: PGM for monochrome pixels, PPM for RGB.
+    ///
+    /// The maxval is the largest value of the pixel type (255 or 65535);
+    /// use `CameraFrame::save_to_pnm` to record the sensor bit depth instead.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save to.
+    /// `format` - Binary or ASCII encoding of the samples.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_pnm(&self, filename: &str, format: PnmFormat) -> Result<(), FrameFileError> {
+        self.write_pnm(filename, format, T::MAXVAL)
+    }
+
+    /// Load FrameData from a Netpbm file: PGM for monochrome pixels, PPM for RGB.
+    ///
+    /// Binary and ASCII files are accepted.  Sample values are not rescaled, so the
+    /// maxval of the file must fit in the pixel type.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to load.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or does not match
+    /// the pixel type.
+    ///
+    pub fn load_from_pnm(filename: &str) -> Result<Self, FrameFileError> {
+        Ok(Self::read_pnm(filename)?.0)
+    }
+}
+
+impl<T> CameraFrame<T>
+where
+    T: PnmPixel,
+{
+    /// Save the CameraFrame to a Netpbm file: PGM for monochrome pixels, PPM for RGB.
+    ///
+    /// The maxval is taken from the frame bit depth (e.g. 4095 for 12-bit data), or the
+    /// largest value of the pixel type if any pixel exceeds the bit depth.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save to.
+    /// `format` - Binary or ASCII encoding of the samples.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_pnm(&self, filename: &str, format: PnmFormat) -> Result<(), FrameFileError> {
+        let maxval = match self.bit_depth {
+            1..=16 => ((1u32 << self.bit_depth) - 1).min(T::MAXVAL as u32) as u16,
+            _ => T::MAXVAL,
+        };
+        let fits = self
+            .data
+            .data
+            .iter()
+            .all(|p| (0..T::CHANNELS).all(|c| p.sample(c) <= maxval));
+        self.data
+            .write_pnm(filename, format, if fits { maxval } else { T::MAXVAL })
+    }
+
+    /// Load a CameraFrame from a Netpbm file: PGM for monochrome pixels, PPM for RGB.
+    ///
+    /// The bit depth is derived from the maxval of the file; Netpbm files carry no
+    /// other metadata, so the exposure is zero and the timestamp is the current time.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to load.
+    ///
+    /// # Returns
+    /// The CameraFrame, or an error if the file could not be read or does not match
+    /// the pixel type.
+    ///
+    pub fn load_from_pnm(filename: &str) -> Result<Self, FrameFileError> {
+        let (data, maxval) = FrameData::<T>::read_pnm(filename)?;
+        let bit_depth = (16 - maxval.leading_zeros()) as u8;
+        Ok(CameraFrame::create(
+            0.0,
+            chrono::Utc::now(),
+            bit_depth,
+            data,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoCameraFrame;
+    use crate::MonoFrameData;
+
+    #[test]
+    fn test_pgm_12bit_roundtrip() {
+        let data = MonoFrameData::<u16> {
+            width: 9,
+            height: 4,
+            data: (0..36).map(|x| Gray::<u16>::new(x * 113)).collect(),
+        };
+        let frame = MonoCameraFrame::<u16>::create(0.0, chrono::Utc::now(), 12, data);
+        for (format, name) in [
+            (PnmFormat::Binary, "camera_test_12bit_binary.pgm"),
+            (PnmFormat::Ascii, "camera_test_12bit_ascii.pgm"),
+        ] {
+            let filename = std::env::temp_dir().join(name);
+            let filename = filename.to_str().unwrap();
+            frame.save_to_pnm(filename, format).unwrap();
+            let header = std::fs::read(filename).unwrap();
+            assert!(header.windows(5).any(|w| w == b"4095\n"));
+
+            let frame2 = MonoCameraFrame::<u16>::load_from_pnm(filename).unwrap();
+            assert_eq!(frame2.bit_depth, 12);
+            assert_eq!(frame2.data.data, frame.data.data);
+            assert!(MonoFrameData::<u8>::load_from_pnm(filename).is_err());
+            let _ = std::fs::remove_file(filename);
+        }
+    }
+
+    #[test]
+    fn test_ppm_roundtrip() {
+        let data = FrameData::<rgb::RGB8> {
+            width: 30,
+            height: 2,
+            data: (0..60).map(|x| rgb::RGB8::new(x, 255 - x, x * 4)).collect(),
+        };
+        for format in [PnmFormat::Binary, PnmFormat::Ascii] {
+            let filename = std::env::temp_dir().join("camera_test_rgb8.ppm");
+            let filename = filename.to_str().unwrap();
+            data.save_to_pnm(filename, format).unwrap();
+            let data2 = FrameData::<rgb::RGB8>::load_from_pnm(filename).unwrap();
+            assert_eq!(data2.width, 30);
+            assert_eq!(data2.data, data.data);
+            if format == PnmFormat::Ascii {
+                let text = std::fs::read_to_string(filename).unwrap();
+                assert!(text.lines().all(|l| l.len() <= ASCII_LINE_LENGTH));
+            }
+            let _ = std::fs::remove_file(filename);
+        }
+    }
+
+    #[test]
+    fn test_pgm_comments() {
+        let filename = std::env::temp_dir().join("camera_test_comments.pgm");
+        let filename = filename.to_str().unwrap();
+        std::fs::write(
+            filename,
+            "P2\n# a comment\n3 1 # trailing\n1023\n0 512 1023\n",
+        )
+        .unwrap();
+        let frame = MonoCameraFrame::<u16>::load_from_pnm(filename).unwrap();
+        assert_eq!(frame.bit_depth, 10);
+        assert_eq!(frame.data.data[1].value(), 512);
+
+        // Samples above maxval are rejected rather than truncated
+        std::fs::write(filename, "P2\n3 1\n255\n0 300 255\n").unwrap();
+        assert!(matches!(
+            MonoFrameData::<u8>::load_from_pnm(filename),
+            Err(FrameFileError::Format(_))
+        ));
+        let _ = std::fs::remove_file(filename);
+    }
+}
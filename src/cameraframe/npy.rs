@@ -0,0 +1,710 @@
+//!
+//! This module contains functions for reading and writing frames in NumPy formats.
+//!
+//! A single FrameData is stored as a `.npy` array of shape `(height, width)` for
+//! monochrome pixels or `(height, width, channels)` for colour pixels.
+//! A stack of CameraFrames is stored as a `.npz` archive (as written by `numpy.savez`)
+//! containing:
+//! * `frames` - the pixel data, of shape `(n, height, width)` or `(n, height, width, channels)`
+//! * `exposure` - exposure times in seconds, `float64`
+//! * `timestamp` - centers of integration, `datetime64[us]` (UTC)
+//! * `bit_depth` - bit depths, `uint8`
+//! * `gain`, `temperature` - `float64`, NaN where unknown
+//!
+
+use super::CameraFrame;
+use super::FrameData;
+use super::FrameFileError;
+use super::MonoFrameData;
+use crate::Pixel;
+use rgb::Gray;
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Pixel types that can be stored in a NumPy array
+pub trait NpyPixel: Pixel {
+    /// NumPy type descriptor of each sample
+    const DESCR: &'static str;
+
+    /// Number of samples per pixel; monochrome pixels have no channel axis
+    const CHANNELS: usize;
+
+    /// Append the little-endian samples of the pixels to a buffer
+    fn write_samples(data: &[Self], out: &mut Vec<u8>);
+
+    /// Construct a pixel from its samples
+    fn from_samples(samples: &[u16]) -> Self;
+}
+
+impl NpyPixel for Gray<u8> {
+    const DESCR: &'static str = "|u1";
+    const CHANNELS: usize = 1;
+
+    fn write_samples(data: &[Self], out: &mut Vec<u8>) {
+        out.extend(data.iter().map(|x| x.value()));
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        Gray::<u8>::new(samples[0] as u8)
+    }
+}
+
+impl NpyPixel for Gray<u16> {
+    const DESCR: &'static str = "<u2";
+    const CHANNELS: usize = 1;
+
+    fn write_samples(data: &[Self], out: &mut Vec<u8>) {
+        out.extend(data.iter().flat_map(|x| x.value().to_le_bytes()));
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        Gray::<u16>::new(samples[0])
+    }
+}
+
+impl NpyPixel for rgb::RGB8 {
+    const DESCR: &'static str = "|u1";
+    const CHANNELS: usize = 3;
+
+    fn write_samples(data: &[Self], out: &mut Vec<u8>) {
+        out.extend(data.iter().flat_map(|x| [x.r, x.g, x.b]));
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        rgb::RGB8::new(samples[0] as u8, samples[1] as u8, samples[2] as u8)
+    }
+}
+
+impl NpyPixel for rgb::RGB16 {
+    const DESCR: &'static str = "<u2";
+    const CHANNELS: usize = 3;
+
+    fn write_samples(data: &[Self], out: &mut Vec<u8>) {
+        out.extend(
+            data.iter()
+                .flat_map(|x| [x.r, x.g, x.b])
+                .flat_map(|x| x.to_le_bytes()),
+        );
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        rgb::RGB16::new(samples[0], samples[1], samples[2])
+    }
+}
+
+impl NpyPixel for rgb::RGBA8 {
+    const DESCR: &'static str = "|u1";
+    const CHANNELS: usize = 4;
+
+    fn write_samples(data: &[Self], out: &mut Vec<u8>) {
+        out.extend(data.iter().flat_map(|x| [x.r, x.g, x.b, x.a]));
+    }
+
+    fn from_samples(samples: &[u16]) -> Self {
+        rgb::RGBA8::new(
+            samples[0] as u8,
+            samples[1] as u8,
+            samples[2] as u8,
+            samples[3] as u8,
+        )
+    }
+}
+
+/// Build a version 1.0 `.npy` header for an array of the given type and shape
+fn npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let shape = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+    // Magic, version and length take 10 bytes; the total is padded to a multiple of 64
+    let total = (10 + dict.len() + 1).div_ceil(64) * 64;
+    dict.extend(std::iter::repeat_n(' ', total - 10 - dict.len() - 1));
+    dict.push('\n');
+
+    let mut header = Vec::with_capacity(total);
+    header.extend_from_slice(NPY_MAGIC);
+    header.extend_from_slice(&[1, 0]);
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+/// Shape of the array holding `n` frames of the given pixel type (n = None for a single frame)
+fn frame_shape<T: NpyPixel>(n: Option<usize>, width: u32, height: u32) -> Vec<usize> {
+    let mut shape: Vec<usize> = n.into_iter().collect();
+    shape.extend([height as usize, width as usize]);
+    if T::CHANNELS > 1 {
+        shape.push(T::CHANNELS);
+    }
+    shape
+}
+
+/// Size in bytes of each item of a NumPy type descriptor, e.g. 2 for `<u2`
+fn itemsize(descr: &str) -> Option<usize> {
+    descr
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|s| s.parse().ok())
+}
+
+/// Header of a `.npy` file: the type descriptor and shape of the array
+pub(super) struct NpyHeader {
+    pub descr: String,
+    pub shape: Vec<usize>,
+}
+
+/// A NumPy array read from a `.npy` file
+struct NpyArray {
+    descr: String,
+    shape: Vec<usize>,
+    bytes: Vec<u8>,
+}
+
+/// Extract the text following `'key':` in a header dictionary
+fn dict_entry<'a>(dict: &'a str, key: &str) -> Result<&'a str, FrameFileError> {
+    let pattern = format!("'{}':", key);
+    dict.find(&pattern)
+        .map(|idx| dict[idx + pattern.len()..].trim_start())
+        .ok_or_else(|| FrameFileError::Format(format!("missing '{}' in .npy header", key)))
+}
+
+impl NpyHeader {
+    /// Read the header, leaving the reader at the start of the array data
+    fn read<R: Read>(reader: &mut R) -> Result<Self, FrameFileError> {
+        let mut preamble = [0u8; 8];
+        reader.read_exact(&mut preamble)?;
+        if &preamble[..6] != NPY_MAGIC {
+            return Err(FrameFileError::Format("not a .npy file".to_string()));
+        }
+        let header_len = match preamble[6] {
+            1 => {
+                let mut len = [0u8; 2];
+                reader.read_exact(&mut len)?;
+                u16::from_le_bytes(len) as usize
+            }
+            2 | 3 => {
+                let mut len = [0u8; 4];
+                reader.read_exact(&mut len)?;
+                u32::from_le_bytes(len) as usize
+            }
+            v => {
+                return Err(FrameFileError::Unsupported(format!(
+                    ".npy format version {}",
+                    v
+                )))
+            }
+        };
+        let mut dict = vec![0u8; header_len];
+        reader.read_exact(&mut dict)?;
+        let dict = String::from_utf8_lossy(&dict);
+
+        let descr = dict_entry(&dict, "descr")?;
+        let descr = descr
+            .strip_prefix('\'')
+            .and_then(|d| d.split('\'').next())
+            .ok_or_else(|| FrameFileError::Format("invalid 'descr' in .npy header".to_string()))?
+            .to_string();
+        if dict_entry(&dict, "fortran_order")?.starts_with("True") {
+            return Err(FrameFileError::Unsupported(
+                "Fortran-ordered .npy arrays".to_string(),
+            ));
+        }
+        let shape = dict_entry(&dict, "shape")?;
+        let shape = shape
+            .strip_prefix('(')
+            .and_then(|s| s.split(')').next())
+            .ok_or_else(|| FrameFileError::Format("invalid 'shape' in .npy header".to_string()))?
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| FrameFileError::Format("invalid 'shape' in .npy header".to_string()))?;
+        Ok(NpyHeader { descr, shape })
+    }
+
+    /// Read only the header of a `.npy` file
+    pub fn read_file(filename: &str) -> Result<Self, FrameFileError> {
+        Self::read(&mut BufReader::new(File::open(filename)?))
+    }
+}
+
+impl NpyArray {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, FrameFileError> {
+        let NpyHeader { descr, shape } = NpyHeader::read(reader)?;
+        let itemsize = itemsize(&descr)
+            .ok_or_else(|| FrameFileError::Unsupported(format!("dtype '{}'", descr)))?;
+        let size = shape
+            .iter()
+            .try_fold(itemsize, |size, n| size.checked_mul(*n))
+            .ok_or_else(|| FrameFileError::Format("array size overflows".to_string()))?;
+        let mut bytes = vec![0u8; size];
+        reader.read_exact(&mut bytes)?;
+        Ok(NpyArray {
+            descr,
+            shape,
+            bytes,
+        })
+    }
+
+    /// Samples as 16-bit values; 8-bit samples are accepted only if `allow_u8` is set,
+    /// and 16-bit samples only if `allow_u16` is set
+    fn samples16(&self, allow_u8: bool, allow_u16: bool) -> Result<Vec<u16>, FrameFileError> {
+        match self.descr.as_str() {
+            "|u1" | "<u1" | ">u1" | "u1" if allow_u8 => {
+                Ok(self.bytes.iter().map(|x| *x as u16).collect())
+            }
+            "<u2" if allow_u16 => Ok(self
+                .bytes
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect()),
+            ">u2" if allow_u16 => Ok(self
+                .bytes
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect()),
+            d => Err(FrameFileError::Unsupported(format!(
+                "cannot load dtype '{}' into this pixel type",
+                d
+            ))),
+        }
+    }
+
+    /// Samples as 64-bit values, for little-endian 8-byte dtypes
+    fn samples64(&self) -> Vec<[u8; 8]> {
+        self.bytes
+            .chunks_exact(8)
+            .map(|b| b.try_into().unwrap())
+            .collect()
+    }
+
+    /// Convert to pixels of the given type, checking the trailing axes match
+    /// `(height, width[, channels])`; returns the pixels and (width, height)
+    fn pixels<T: NpyPixel>(&self, leading: usize) -> Result<(Vec<T>, u32, u32), FrameFileError> {
+        let expected = leading + if T::CHANNELS > 1 { 3 } else { 2 };
+        if self.shape.len() != expected
+            || (T::CHANNELS > 1 && self.shape[expected - 1] != T::CHANNELS)
+        {
+            return Err(FrameFileError::Unsupported(format!(
+                "array of shape {:?} for pixels with {} channel(s)",
+                self.shape,
+                T::CHANNELS
+            )));
+        }
+        let dimension = |n: usize| {
+            u32::try_from(n).map_err(|_| {
+                FrameFileError::Unsupported(format!("array of shape {:?}", self.shape))
+            })
+        };
+        let width = dimension(self.shape[leading + 1])?;
+        let height = dimension(self.shape[leading])?;
+        let allow_u16 = T::DESCR == "<u2";
+        let samples = self.samples16(true, allow_u16)?;
+        Ok((
+            samples
+                .chunks_exact(T::CHANNELS)
+                .map(T::from_samples)
+                .collect(),
+            width,
+            height,
+        ))
+    }
+}
+
+impl<T> FrameData<T>
+where
+    T: NpyPixel,
+{
+    /// Save the FrameData to a NumPy `.npy` file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the array to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_npy(&self, filename: &str) -> Result<(), FrameFileError> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writer.write_all(&npy_header(
+            T::DESCR,
+            &frame_shape::<T>(None, self.width, self.height),
+        ))?;
+        let mut bytes = Vec::new();
+        T::write_samples(&self.data, &mut bytes);
+        writer.write_all(&bytes)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load FrameData from a NumPy `.npy` file.
+    ///
+    /// The array must be C-ordered with shape `(height, width)` for monochrome pixels or
+    /// `(height, width, channels)` for colour pixels.  `uint8` arrays may be loaded into
+    /// 16-bit pixel types, but not the reverse.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the `.npy` file to load.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or does not match
+    /// the pixel type.
+    ///
+    pub fn load_from_npy(filename: &str) -> Result<Self, FrameFileError> {
+        let array = NpyArray::read(&mut BufReader::new(File::open(filename)?))?;
+        let (data, width, height) = array.pixels::<T>(0)?;
+        Ok(FrameData::<T> {
+            width,
+            height,
+            data,
+        })
+    }
+}
+
+/// Save a single-precision frame as an image of shape `(height, width)` to a `.npy`
+/// file
+pub(crate) fn save_f32_to_npy(
+    filename: &str,
+    frame: &MonoFrameData<f32>,
+) -> Result<(), FrameFileError> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+    let shape = [frame.height as usize, frame.width as usize];
+    writer.write_all(&npy_header("<f4", &shape))?;
+    let bytes: Vec<u8> = frame
+        .data
+        .iter()
+        .flat_map(|x| x.value().to_le_bytes())
+        .collect();
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Load a single-precision frame from an image of shape `(height, width)` in a
+/// `.npy` file
+pub(crate) fn load_f32_from_npy(filename: &str) -> Result<MonoFrameData<f32>, FrameFileError> {
+    let array = NpyArray::read(&mut BufReader::new(File::open(filename)?))?;
+    if array.descr != "<f4" || array.shape.len() != 2 {
+        return Err(FrameFileError::Unsupported(format!(
+            "expected 2-D '<f4' array, found '{}' array of shape {:?}",
+            array.descr, array.shape
+        )));
+    }
+    let data = array
+        .bytes
+        .chunks_exact(4)
+        .map(|b| Gray::new(f32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+        .collect();
+    Ok(MonoFrameData {
+        width: array.shape[1] as u32,
+        height: array.shape[0] as u32,
+        data,
+    })
+}
+
+/// Save a stack of CameraFrames to a NumPy `.npz` archive.
+///
+/// The archive holds the pixel data as a single `frames` array, plus `exposure`,
+/// `timestamp`, `bit_depth`, `gain` and `temperature` arrays with one entry per frame.
+///
+/// # Arguments
+/// `filename` - The name of the file to save the archive to.
+/// `frames` - The frames to save; all must have the same width and height.
+///
+/// # Returns
+/// An empty Result if the save was successful, or an error if the save failed.
+///
+pub fn save_frames_to_npz<T>(
+    filename: &str,
+    frames: &[CameraFrame<T>],
+) -> Result<(), FrameFileError>
+where
+    T: NpyPixel,
+{
+    let (width, height) = frames
+        .first()
+        .map(|f| (f.data.width, f.data.height))
+        .unwrap_or((0, 0));
+    if frames
+        .iter()
+        .any(|f| f.data.width != width || f.data.height != height)
+    {
+        return Err(FrameFileError::Unsupported(
+            "frames of differing sizes in one .npz stack".to_string(),
+        ));
+    }
+
+    let mut zip = zip::ZipWriter::new(BufWriter::new(File::create(filename)?));
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    // Pixel data, written one frame at a time
+    let header = npy_header(
+        T::DESCR,
+        &frame_shape::<T>(Some(frames.len()), width, height),
+    );
+    let size = header.len()
+        + frames.iter().map(|f| f.data.data.len()).sum::<usize>()
+            * T::CHANNELS
+            * itemsize(T::DESCR).unwrap_or(1);
+    zip.start_file("frames.npy", options.large_file(size >= u32::MAX as usize))?;
+    zip.write_all(&header)?;
+    let mut bytes = Vec::new();
+    for frame in frames {
+        bytes.clear();
+        T::write_samples(&frame.data.data, &mut bytes);
+        zip.write_all(&bytes)?;
+    }
+
+    let n = frames.len();
+    let mut write_array = |name: &str, descr: &str, data: Vec<u8>| -> Result<(), FrameFileError> {
+        zip.start_file(name, options)?;
+        zip.write_all(&npy_header(descr, &[n]))?;
+        zip.write_all(&data)?;
+        Ok(())
+    };
+    write_array(
+        "exposure.npy",
+        "<f8",
+        frames
+            .iter()
+            .flat_map(|f| f.exposure.to_le_bytes())
+            .collect(),
+    )?;
+    write_array(
+        "timestamp.npy",
+        "<M8[us]",
+        frames
+            .iter()
+            .flat_map(|f| f.center_of_integration.timestamp_micros().to_le_bytes())
+            .collect(),
+    )?;
+    write_array(
+        "bit_depth.npy",
+        "|u1",
+        frames.iter().map(|f| f.bit_depth).collect(),
+    )?;
+    write_array(
+        "gain.npy",
+        "<f8",
+        frames
+            .iter()
+            .flat_map(|f| f.gain.unwrap_or(f64::NAN).to_le_bytes())
+            .collect(),
+    )?;
+    write_array(
+        "temperature.npy",
+        "<f8",
+        frames
+            .iter()
+            .flat_map(|f| f.temperature.unwrap_or(f64::NAN).to_le_bytes())
+            .collect(),
+    )?;
+
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+/// Load a stack of CameraFrames from a NumPy `.npz` archive.
+///
+/// The archive must contain a `frames` array of shape `(n, height, width)` or
+/// `(n, height, width, channels)`.  The optional `exposure`, `timestamp`, `bit_depth`,
+/// `gain` and `temperature` arrays, as written by `save_frames_to_npz`, populate the
+/// frame metadata; NaN values are treated as unknown.
+///
+/// # Arguments
+/// `filename` - The name of the `.npz` archive to load.
+///
+/// # Returns
+/// The frames, or an error if the file could not be read or does not match
+/// the pixel type.
+///
+pub fn load_frames_from_npz<T>(filename: &str) -> Result<Vec<CameraFrame<T>>, FrameFileError>
+where
+    T: NpyPixel,
+{
+    let mut zip = zip::ZipArchive::new(BufReader::new(File::open(filename)?))?;
+    let mut read_array = |name: &str| -> Result<Option<NpyArray>, FrameFileError> {
+        match zip.by_name(name) {
+            Ok(mut file) => Ok(Some(NpyArray::read(&mut file)?)),
+            Err(zip::result::ZipError::FileNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    };
+
+    let frames = read_array("frames.npy")?
+        .ok_or_else(|| FrameFileError::Format("missing 'frames' array in .npz".to_string()))?;
+    let (pixels, width, height) = frames.pixels::<T>(1)?;
+    let n = frames.shape[0];
+
+    let float_array = |array: Option<NpyArray>| -> Vec<Option<f64>> {
+        match array {
+            Some(a) if a.descr == "<f8" && a.shape == [n] => a
+                .samples64()
+                .into_iter()
+                .map(f64::from_le_bytes)
+                .map(|v| if v.is_nan() { None } else { Some(v) })
+                .collect(),
+            _ => vec![None; n],
+        }
+    };
+    let exposure = float_array(read_array("exposure.npy")?);
+    let gain = float_array(read_array("gain.npy")?);
+    let temperature = float_array(read_array("temperature.npy")?);
+    let timestamp: Vec<Option<chrono::DateTime<chrono::Utc>>> = match read_array("timestamp.npy")? {
+        Some(a) if a.descr == "<M8[us]" && a.shape == [n] => a
+            .samples64()
+            .into_iter()
+            .map(|b| chrono::DateTime::from_timestamp_micros(i64::from_le_bytes(b)))
+            .collect(),
+        _ => vec![None; n],
+    };
+    let bit_depth: Vec<Option<u8>> = match read_array("bit_depth.npy")? {
+        Some(a) if a.shape == [n] => a
+            .samples16(true, false)
+            .map(|v| v.into_iter().map(|b| Some(b as u8)).collect())
+            .unwrap_or_else(|_| vec![None; n]),
+        _ => vec![None; n],
+    };
+
+    let default_depth = if T::DESCR == "<u2" { 16 } else { 8 };
+    let npixels = (width as usize)
+        .checked_mul(height as usize)
+        .filter(|npixels| *npixels > 0)
+        .ok_or_else(|| FrameFileError::Format("empty frames in .npz stack".to_string()))?;
+    if n.checked_mul(npixels)
+        .is_none_or(|total| pixels.len() < total)
+    {
+        return Err(FrameFileError::Format(format!(
+            "'frames' array too short for {} frames of {}x{}",
+            n, width, height
+        )));
+    }
+    Ok(pixels
+        .chunks_exact(npixels)
+        .take(n)
+        .enumerate()
+        .map(|(i, data)| CameraFrame {
+            gain: gain[i],
+            temperature: temperature[i],
+            ..CameraFrame::create(
+                exposure[i].unwrap_or(0.0),
+                timestamp[i].unwrap_or_else(chrono::Utc::now),
+                bit_depth[i].unwrap_or(default_depth),
+                FrameData::<T> {
+                    width,
+                    height,
+                    data: data.to_vec(),
+                },
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoCameraFrame;
+    use crate::MonoFrameData;
+
+    #[test]
+    fn test_npy_header() {
+        let header = npy_header("<u2", &[3, 4]);
+        assert_eq!(header.len() % 64, 0);
+        assert_eq!(&header[..6], NPY_MAGIC);
+        let dict = String::from_utf8_lossy(&header[10..]);
+        assert!(dict.starts_with("{'descr': '<u2', 'fortran_order': False, 'shape': (3, 4), }"));
+        assert!(dict.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_npy_roundtrip() {
+        let data = FrameData::<rgb::RGB16> {
+            width: 5,
+            height: 3,
+            data: (0..15)
+                .map(|x| rgb::RGB16::new(x, x * 1000, 65535 - x))
+                .collect(),
+        };
+        let filename = std::env::temp_dir().join("camera_test_rgb16.npy");
+        let filename = filename.to_str().unwrap();
+        data.save_to_npy(filename).unwrap();
+        let data2 = FrameData::<rgb::RGB16>::load_from_npy(filename).unwrap();
+        assert_eq!(data2.width, 5);
+        assert_eq!(data2.height, 3);
+        assert_eq!(data2.data, data.data);
+        assert!(MonoFrameData::<u16>::load_from_npy(filename).is_err());
+        assert!(FrameData::<rgb::RGB8>::load_from_npy(filename).is_err());
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_npz_roundtrip() {
+        let frames: Vec<MonoCameraFrame<u16>> = (0..3)
+            .map(|i| {
+                let data = MonoFrameData::<u16> {
+                    width: 4,
+                    height: 2,
+                    data: (0..8).map(|x| Gray::<u16>::new(x * 100 + i)).collect(),
+                };
+                let mut frame = MonoCameraFrame::<u16>::create(
+                    0.1 * (i + 1) as f64,
+                    chrono::Utc::now(),
+                    12,
+                    data,
+                );
+                frame.gain = if i == 1 { None } else { Some(i as f64) };
+                frame
+            })
+            .collect();
+
+        let filename = std::env::temp_dir().join("camera_test_stack.npz");
+        let filename = filename.to_str().unwrap();
+        save_frames_to_npz(filename, &frames).unwrap();
+        let frames2 = load_frames_from_npz::<Gray<u16>>(filename).unwrap();
+        assert_eq!(frames2.len(), 3);
+        for (a, b) in frames.iter().zip(frames2.iter()) {
+            assert_eq!(a.data.data, b.data.data);
+            assert_eq!(a.exposure, b.exposure);
+            assert_eq!(a.gain, b.gain);
+            assert_eq!(a.bit_depth, b.bit_depth);
+            assert_eq!(
+                a.center_of_integration.timestamp_micros(),
+                b.center_of_integration.timestamp_micros()
+            );
+        }
+
+        // A stack of zero-size frames is an error rather than an empty result
+        let empty: Vec<MonoCameraFrame<u16>> = (0..2)
+            .map(|_| {
+                let data = MonoFrameData::<u16> {
+                    width: 0,
+                    height: 3,
+                    data: Vec::new(),
+                };
+                MonoCameraFrame::<u16>::create(0.1, chrono::Utc::now(), 12, data)
+            })
+            .collect();
+        save_frames_to_npz(filename, &empty).unwrap();
+        assert!(matches!(
+            load_frames_from_npz::<Gray<u16>>(filename),
+            Err(FrameFileError::Format(_))
+        ));
+        let _ = std::fs::remove_file(filename);
+    }
+}
@@ -0,0 +1,216 @@
+//!
+//! This module contains functions for saving and loading CameraFrames to PNG files
+//! with the frame metadata embedded in text chunks.
+//!
+//! Numeric fields are stored as `tEXt` chunks and the camera name as a UTF-8 `iTXt`
+//! chunk.  When the bit depth of the frame is less than the PNG sample depth, samples
+//! are scaled up to the full PNG range and an `sBIT` chunk records the true bit depth,
+//! so the image displays correctly in other viewers; loading reverses the scaling.
+//!
+
+use super::from_file::PngImage;
+use super::to_file::png_encoder;
+use super::CameraFrame;
+use super::FrameFileError;
+use super::PngPixel;
+
+const KEY_EXPOSURE: &str = "Exposure";
+const KEY_TIMESTAMP: &str = "Timestamp";
+const KEY_BIT_DEPTH: &str = "BitDepth";
+const KEY_GAIN: &str = "Gain";
+const KEY_BINNING: &str = "Binning";
+const KEY_TEMPERATURE: &str = "Temperature";
+const KEY_CAMERA: &str = "Camera";
+
+/// Number of samples per pixel for a PNG colour type
+fn samples_per_pixel(color: png::ColorType) -> usize {
+    match color {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => 1,
+    }
+}
+
+/// Apply a function to each sample of PNG-encoded data, in place
+fn map_samples(bytes: &mut [u8], depth: png::BitDepth, mut f: impl FnMut(u16) -> u16) {
+    match depth {
+        png::BitDepth::Sixteen => bytes.chunks_exact_mut(2).for_each(|b| {
+            let v = f(u16::from_be_bytes([b[0], b[1]]));
+            b.copy_from_slice(&v.to_be_bytes());
+        }),
+        _ => bytes.iter_mut().for_each(|b| *b = f(*b as u16) as u8),
+    }
+}
+
+impl<T> CameraFrame<T>
+where
+    T: PngPixel,
+{
+    /// Save the CameraFrame to a PNG file, embedding the frame metadata.
+    ///
+    /// Exposure, UTC timestamp (center of integration), bit depth and, where known,
+    /// gain, binning, temperature and camera name are written as text chunks.
+    /// If every pixel fits in the frame bit depth, the samples are scaled to the full
+    /// PNG range and the bit depth is recorded in an `sBIT` chunk.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the PNG to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_png(&self, filename: &str) -> Result<(), FrameFileError> {
+        let mut encoder = png_encoder::<T>(filename, self.data.width, self.data.height)?;
+        encoder.add_text_chunk(KEY_EXPOSURE.to_string(), self.exposure.to_string())?;
+        encoder.add_text_chunk(
+            KEY_TIMESTAMP.to_string(),
+            self.center_of_integration
+                .to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+        )?;
+        encoder.add_text_chunk(KEY_BIT_DEPTH.to_string(), self.bit_depth.to_string())?;
+        if let Some(gain) = self.gain {
+            encoder.add_text_chunk(KEY_GAIN.to_string(), gain.to_string())?;
+        }
+        if let Some(binning) = self.binning {
+            encoder.add_text_chunk(KEY_BINNING.to_string(), binning.to_string())?;
+        }
+        if let Some(temperature) = self.temperature {
+            encoder.add_text_chunk(KEY_TEMPERATURE.to_string(), temperature.to_string())?;
+        }
+        if let Some(name) = &self.camera_name {
+            encoder.add_itxt_chunk(KEY_CAMERA.to_string(), name.clone())?;
+        }
+
+        let depth = T::DEPTH as u8;
+        let mut bytes = T::to_png_bytes(&self.data.data);
+        let mut sbit = None;
+        if self.bit_depth > 0 && self.bit_depth < depth {
+            let shift = depth - self.bit_depth;
+            let mut fits = true;
+            map_samples(&mut bytes, T::DEPTH, |v| {
+                fits &= v >> self.bit_depth == 0;
+                v
+            });
+            if fits {
+                map_samples(&mut bytes, T::DEPTH, |v| v << shift);
+                sbit = Some(vec![self.bit_depth; samples_per_pixel(T::COLOR)]);
+            }
+        }
+
+        let mut writer = encoder.write_header()?;
+        if let Some(sbit) = sbit {
+            writer.write_chunk(png::chunk::sBIT, &sbit)?;
+        }
+        writer.write_image_data(&bytes)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Load a CameraFrame from a PNG file, restoring embedded frame metadata.
+    ///
+    /// Fields missing from the file are left unset; the exposure defaults to zero,
+    /// the timestamp to the current time, and the bit depth to the `sBIT` chunk or the
+    /// PNG sample depth.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the PNG file to load.
+    ///
+    /// # Returns
+    /// The CameraFrame, or an error if the file could not be read or does not match
+    /// the pixel type.
+    ///
+    pub fn load_from_png(filename: &str) -> Result<Self, FrameFileError> {
+        let mut image = PngImage::read(filename)?;
+
+        let mut text = std::collections::HashMap::new();
+        for chunk in image.info.uncompressed_latin1_text.iter() {
+            text.insert(chunk.keyword.clone(), chunk.text.clone());
+        }
+        for chunk in image.info.utf8_text.iter() {
+            if let Ok(value) = chunk.get_text() {
+                text.insert(chunk.keyword.clone(), value);
+            }
+        }
+
+        let sample_depth = image.bit_depth as u8;
+        let sbit = image
+            .info
+            .sbit
+            .as_ref()
+            .and_then(|s| s.first().copied())
+            .filter(|b| *b > 0 && *b < sample_depth);
+        if let Some(sbit) = sbit {
+            map_samples(&mut image.bytes, image.bit_depth, |v| {
+                v >> (sample_depth - sbit)
+            });
+        }
+        let data = image.to_frame_data::<T>()?;
+
+        let bit_depth = text
+            .get(KEY_BIT_DEPTH)
+            .and_then(|v| v.parse().ok())
+            .or(sbit)
+            .unwrap_or(sample_depth);
+        let center_of_integration = text
+            .get(KEY_TIMESTAMP)
+            .and_then(|v| chrono::DateTime::parse_from_rfc3339(v).ok())
+            .map(|t| t.with_timezone(&chrono::Utc))
+            .unwrap_or_else(chrono::Utc::now);
+
+        Ok(CameraFrame {
+            gain: text.get(KEY_GAIN).and_then(|v| v.parse().ok()),
+            binning: text.get(KEY_BINNING).and_then(|v| v.parse().ok()),
+            temperature: text.get(KEY_TEMPERATURE).and_then(|v| v.parse().ok()),
+            camera_name: text.get(KEY_CAMERA).cloned(),
+            ..CameraFrame::create(
+                text.get(KEY_EXPOSURE)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0.0),
+                center_of_integration,
+                bit_depth,
+                data,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MonoCameraFrame;
+    use crate::MonoFrameData;
+
+    #[test]
+    fn test_png_metadata_roundtrip() {
+        let data = MonoFrameData::<u16> {
+            width: 20,
+            height: 10,
+            data: (0..200).map(|x| rgb::Gray::<u16>::new(x * 20)).collect(),
+        };
+        let mut frame = MonoCameraFrame::<u16>::create(0.05, chrono::Utc::now(), 12, data);
+        frame.gain = Some(30.0);
+        frame.camera_name = Some("SVBONY SV305 Ü".to_string());
+
+        let filename = std::env::temp_dir().join("camera_test_metadata.png");
+        let filename = filename.to_str().unwrap();
+        frame.save_to_png(filename).unwrap();
+
+        // Plain FrameData load sees the samples scaled to the full 16-bit range
+        let raw = MonoFrameData::<u16>::load_from_png(filename).unwrap();
+        assert_eq!(raw.data[1].value(), 20 << 4);
+
+        let frame2 = MonoCameraFrame::<u16>::load_from_png(filename).unwrap();
+        assert_eq!(frame2.data.data, frame.data.data);
+        assert_eq!(frame2.exposure, 0.05);
+        assert_eq!(frame2.bit_depth, 12);
+        assert_eq!(frame2.gain, Some(30.0));
+        assert_eq!(frame2.binning, None);
+        assert_eq!(frame2.camera_name, frame.camera_name);
+        assert_eq!(
+            frame2.center_of_integration.timestamp_micros(),
+            frame.center_of_integration.timestamp_micros()
+        );
+        let _ = std::fs::remove_file(filename);
+    }
+}
@@ -0,0 +1,430 @@
+//!
+//! Software binning and resizing of frames, for the crate's own pixel types.
+//!
+//! Resizing is separable, along rows and then columns.  Each output pixel is a
+//! weighted sum of the input pixels under the filter kernel centred on it; when
+//! shrinking, the kernel is stretched by the reduction factor so every input pixel
+//! contributes and fine detail does not alias.
+//!
+
+use super::FrameData;
+use crate::pixel::Interpolate;
+
+/// Errors binning or resizing frames
+#[derive(Debug, thiserror::Error)]
+pub enum ResampleError {
+    #[error("Cannot bin a {size:?} frame by {bin_x}x{bin_y}")]
+    InvalidBinning {
+        bin_x: u32,
+        bin_y: u32,
+        size: (u32, u32),
+    },
+    #[error("Cannot resize a {size:?} frame to {width}x{height}")]
+    InvalidSize {
+        width: u32,
+        height: u32,
+        size: (u32, u32),
+    },
+}
+
+/// How the pixels of a bin are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinMode {
+    /// Sum, saturating at the maximum of the pixel type as hardware binning does
+    #[default]
+    Sum,
+    /// Mean, rounded to the nearest value
+    Mean,
+}
+
+/// Interpolation filter for resizing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeFilter {
+    /// The input pixel nearest each output pixel centre
+    Nearest,
+    /// Triangle kernel
+    #[default]
+    Bilinear,
+    /// Keys cubic kernel with `a = -0.5`
+    Bicubic,
+    /// Windowed sinc kernel with three lobes
+    Lanczos3,
+    /// Mean of the input pixels covered by each output pixel
+    Area,
+}
+
+impl ResizeFilter {
+    /// Half-width of the kernel at unit scale
+    fn support(&self) -> f64 {
+        match self {
+            ResizeFilter::Nearest | ResizeFilter::Area => 0.5,
+            ResizeFilter::Bilinear => 1.0,
+            ResizeFilter::Bicubic => 2.0,
+            ResizeFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn kernel(&self, x: f64) -> f64 {
+        match self {
+            // Half-open, so a sample on a pixel boundary belongs to one pixel only
+            ResizeFilter::Nearest | ResizeFilter::Area => {
+                if (-0.5..0.5).contains(&x) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ResizeFilter::Bilinear => (1.0 - x.abs()).max(0.0),
+            ResizeFilter::Bicubic => {
+                const A: f64 = -0.5;
+                let x = x.abs();
+                if x < 1.0 {
+                    ((A + 2.0) * x - (A + 3.0)) * x * x + 1.0
+                } else if x < 2.0 {
+                    (((x - 5.0) * x + 8.0) * x - 4.0) * A
+                } else {
+                    0.0
+                }
+            }
+            ResizeFilter::Lanczos3 => {
+                let x = x.abs();
+                if x == 0.0 {
+                    1.0
+                } else if x < 3.0 {
+                    let px = std::f64::consts::PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Input indices and normalized weights contributing to each output pixel along
+/// one axis
+fn weights(input: u32, output: u32, filter: ResizeFilter) -> Vec<(usize, Vec<f64>)> {
+    let scale = input as f64 / output as f64;
+    if filter == ResizeFilter::Nearest {
+        return (0..output)
+            .map(|i| {
+                let j = (((i as f64 + 0.5) * scale) as usize).min(input as usize - 1);
+                (j, vec![1.0])
+            })
+            .collect();
+    }
+    if filter == ResizeFilter::Area {
+        // Each input pixel is weighted by its overlap with the output pixel
+        return (0..output)
+            .map(|i| {
+                let (lo, hi) = (i as f64 * scale, (i + 1) as f64 * scale);
+                let start = lo.floor() as usize;
+                let end = (hi.ceil() as usize).clamp(start + 1, input as usize);
+                let w: Vec<f64> = (start..end)
+                    .map(|j| (hi.min((j + 1) as f64) - lo.max(j as f64)).max(0.0) / scale)
+                    .collect();
+                (start, w)
+            })
+            .collect();
+    }
+    let filter_scale = scale.max(1.0);
+    let support = filter.support() * filter_scale;
+    (0..output)
+        .map(|i| {
+            let center = (i as f64 + 0.5) * scale;
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(input as usize);
+            let mut w: Vec<f64> = (start..end)
+                .map(|j| filter.kernel((j as f64 + 0.5 - center) / filter_scale))
+                .collect();
+            let total: f64 = w.iter().sum();
+            if total != 0.0 {
+                w.iter_mut().for_each(|w| *w /= total);
+            }
+            (start, w)
+        })
+        .collect()
+}
+
+impl<T> FrameData<T>
+where
+    T: Interpolate,
+{
+    /// Combine blocks of pixels into single pixels, as hardware binning does.
+    ///
+    /// Integer components are summed exactly, in 128 bits for monochrome pixels, so
+    /// sums of many pixels cannot overflow or lose precision before saturating.
+    /// Rows and columns left over at the right and bottom edges are dropped.
+    ///
+    /// # Arguments
+    /// `bin_x` - Columns per bin.
+    /// `bin_y` - Rows per bin.
+    /// `mode` - Whether to sum or average the pixels of each bin.
+    ///
+    /// # Returns
+    /// The binned frame, or an error if a bin size is zero or larger than the frame.
+    ///
+    pub fn bin(&self, bin_x: u32, bin_y: u32, mode: BinMode) -> Result<Self, ResampleError> {
+        if bin_x == 0 || bin_y == 0 || bin_x > self.width || bin_y > self.height {
+            return Err(ResampleError::InvalidBinning {
+                bin_x,
+                bin_y,
+                size: (self.width, self.height),
+            });
+        }
+        let (width, height) = (self.width / bin_x, self.height / bin_y);
+        let mut sums = vec![T::Sum::default(); width as usize * height as usize];
+        for y in 0..height * bin_y {
+            let row = &self.data[y as usize * self.width as usize..];
+            let out = &mut sums[(y / bin_y) as usize * width as usize..];
+            for x in 0..width * bin_x {
+                row[x as usize].add_to(&mut out[(x / bin_x) as usize]);
+            }
+        }
+        let count = match mode {
+            BinMode::Sum => 1,
+            BinMode::Mean => bin_x as u64 * bin_y as u64,
+        };
+        let data = sums.into_iter().map(|s| T::from_sum(s, count)).collect();
+        Ok(FrameData {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Resample the frame to a new size.
+    ///
+    /// # Arguments
+    /// `width` - The new width.
+    /// `height` - The new height.
+    /// `filter` - The interpolation filter.
+    ///
+    /// # Returns
+    /// The resized frame, or an error if either the frame or the new size is empty.
+    ///
+    pub fn resize(
+        &self,
+        width: u32,
+        height: u32,
+        filter: ResizeFilter,
+    ) -> Result<Self, ResampleError> {
+        if width == 0 || height == 0 || self.width == 0 || self.height == 0 {
+            return Err(ResampleError::InvalidSize {
+                width,
+                height,
+                size: (self.width, self.height),
+            });
+        }
+        let accumulate = |weights: &[f64], pixels: &mut dyn Iterator<Item = [f64; 4]>| {
+            let mut sum = [0f64; 4];
+            for (w, c) in weights.iter().zip(pixels) {
+                for i in 0..4 {
+                    sum[i] += w * c[i];
+                }
+            }
+            sum
+        };
+
+        // Along rows
+        let wx = weights(self.width, width, filter);
+        let in_w = self.width as usize;
+        let mut rows = Vec::with_capacity(width as usize * self.height as usize);
+        for row in self.data.chunks_exact(in_w) {
+            for (start, w) in wx.iter() {
+                let mut pixels = row[*start..].iter().map(|p| p.to_components());
+                rows.push(accumulate(w, &mut pixels));
+            }
+        }
+
+        // Along columns
+        let wy = weights(self.height, height, filter);
+        let out_w = width as usize;
+        let mut data = Vec::with_capacity(out_w * height as usize);
+        for (start, w) in wy.iter() {
+            for x in 0..out_w {
+                let mut pixels = rows[start * out_w + x..].iter().step_by(out_w).copied();
+                data.push(T::from_components(accumulate(w, &mut pixels)));
+            }
+        }
+        Ok(FrameData {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Resize the frame to fit within a size, keeping its aspect ratio, e.g. for a
+    /// preview thumbnail.  Frames that already fit are copied unchanged.
+    ///
+    /// # Arguments
+    /// `max_width` - The largest width.
+    /// `max_height` - The largest height.
+    /// `filter` - The interpolation filter.
+    ///
+    /// # Returns
+    /// The resized frame, or an error if either the frame or the size is empty.
+    ///
+    pub fn resize_to_fit(
+        &self,
+        max_width: u32,
+        max_height: u32,
+        filter: ResizeFilter,
+    ) -> Result<Self, ResampleError> {
+        if max_width == 0 || max_height == 0 {
+            return Err(ResampleError::InvalidSize {
+                width: max_width,
+                height: max_height,
+                size: (self.width, self.height),
+            });
+        }
+        if self.width <= max_width && self.height <= max_height {
+            return Ok(self.clone());
+        }
+        let scale =
+            (max_width as f64 / self.width as f64).min(max_height as f64 / self.height as f64);
+        let width = ((self.width as f64 * scale).round() as u32).clamp(1, max_width);
+        let height = ((self.height as f64 * scale).round() as u32).clamp(1, max_height);
+        self.resize(width, height, filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoFrameData;
+    use rgb::Gray;
+
+    fn values(f: &MonoFrameData<u16>) -> Vec<u16> {
+        f.data.iter().map(|p| p.value()).collect()
+    }
+
+    #[test]
+    fn test_bin() {
+        let frame = MonoFrameData::<u16> {
+            width: 5,
+            height: 4,
+            data: (0..20).map(|v| Gray::new(v * 1000)).collect(),
+        };
+        let sum = frame.bin(2, 2, BinMode::Sum).unwrap();
+        assert_eq!((sum.width, sum.height), (2, 2));
+        assert_eq!(values(&sum), [12000, 20000, 52000, 60000]);
+        // A sum exceeding the pixel maximum saturates
+        assert_eq!(values(&frame.bin(5, 4, BinMode::Sum).unwrap()), [65535]);
+        let mean = frame.bin(2, 2, BinMode::Mean).unwrap();
+        assert_eq!(values(&mean), [3000, 5000, 13000, 15000]);
+        let column = frame.bin(1, 4, BinMode::Mean).unwrap();
+        assert_eq!(values(&column), [7500, 8500, 9500, 10500, 11500]);
+        assert!(matches!(
+            frame.bin(0, 1, BinMode::Sum),
+            Err(ResampleError::InvalidBinning { .. })
+        ));
+        assert!(frame.bin(6, 1, BinMode::Sum).is_err());
+
+        let rgb = FrameData::<rgb::RGB8> {
+            width: 2,
+            height: 2,
+            data: vec![rgb::RGB8::new(100, 10, 1); 4],
+        };
+        assert_eq!(
+            rgb.bin(2, 2, BinMode::Sum).unwrap().data,
+            [rgb::RGB8::new(255, 40, 4)]
+        );
+
+        // Wide integer pixels are summed exactly
+        let wide = MonoFrameData::<u64> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new((1 << 60) + 1), Gray::new((1 << 60) + 2)],
+        };
+        assert_eq!(
+            wide.bin(2, 1, BinMode::Mean).unwrap().data,
+            [Gray::new((1 << 60) + 2)]
+        );
+        let saturated = MonoFrameData::<u64> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new(u64::MAX), Gray::new(1)],
+        };
+        assert_eq!(
+            saturated.bin(2, 1, BinMode::Sum).unwrap().data,
+            [Gray::new(u64::MAX)]
+        );
+        let signed = MonoFrameData::<i16> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new(-3), Gray::new(-4)],
+        };
+        assert_eq!(
+            signed.bin(2, 1, BinMode::Mean).unwrap().data,
+            [Gray::new(-4)]
+        );
+    }
+
+    #[test]
+    fn test_resize() {
+        let ramp = MonoFrameData::<u16> {
+            width: 8,
+            height: 4,
+            data: (0..32).map(|i| Gray::new((i % 8) * 100)).collect(),
+        };
+        // Halving with the area filter averages pairs of columns
+        let half = ramp.resize(4, 2, ResizeFilter::Area).unwrap();
+        assert_eq!(values(&half), [50, 250, 450, 650, 50, 250, 450, 650]);
+        let nearest = ramp.resize(4, 2, ResizeFilter::Nearest).unwrap();
+        assert_eq!(values(&nearest)[..4], [100, 300, 500, 700]);
+
+        // Output pixels straddling input pixel boundaries take the share of each
+        let pair = MonoFrameData::<u16> {
+            width: 2,
+            height: 1,
+            data: vec![Gray::new(0), Gray::new(300)],
+        };
+        let up = pair.resize(3, 1, ResizeFilter::Area).unwrap();
+        assert_eq!(values(&up), [0, 150, 300]);
+        let triple = MonoFrameData::<u16> {
+            width: 3,
+            height: 1,
+            data: vec![Gray::new(0), Gray::new(300), Gray::new(600)],
+        };
+        let down = triple.resize(2, 1, ResizeFilter::Area).unwrap();
+        assert_eq!(values(&down), [100, 500]);
+
+        // Constant frames stay constant with every filter, upscaling or downscaling
+        let flat = MonoFrameData::<u16> {
+            width: 7,
+            height: 5,
+            data: vec![Gray::new(1234); 35],
+        };
+        for filter in [
+            ResizeFilter::Nearest,
+            ResizeFilter::Bilinear,
+            ResizeFilter::Bicubic,
+            ResizeFilter::Lanczos3,
+            ResizeFilter::Area,
+        ] {
+            for (w, h) in [(3, 2), (20, 11), (14, 10), (7, 3)] {
+                let r = flat.resize(w, h, filter).unwrap();
+                assert_eq!((r.width, r.height), (w, h));
+                assert!(r.data.iter().all(|p| p.value() == 1234), "{:?}", filter);
+            }
+        }
+
+        // Upscaling a ramp with the bilinear filter interpolates between columns
+        let up = ramp.resize(16, 4, ResizeFilter::Bilinear).unwrap();
+        assert_eq!(values(&up)[3..7], [125, 175, 225, 275]);
+
+        let rgb = FrameData::<rgb::RGB8> {
+            width: 4,
+            height: 4,
+            data: vec![rgb::RGB8::new(1, 2, 3); 16],
+        };
+        let thumb = rgb.resize_to_fit(2, 3, ResizeFilter::Lanczos3).unwrap();
+        assert_eq!((thumb.width, thumb.height), (2, 2));
+        assert!(thumb.data.iter().all(|p| *p == rgb::RGB8::new(1, 2, 3)));
+        assert!(matches!(
+            ramp.resize(0, 2, ResizeFilter::Bilinear),
+            Err(ResampleError::InvalidSize { .. })
+        ));
+    }
+}
@@ -0,0 +1,289 @@
+//!
+//! This module contains functions for reading and writing FrameData to TIFF files.
+//!
+//! Images are written uncompressed.  Loading accepts any compression supported by the
+//! `tiff` crate; 8-bit images may be loaded into 16-bit frames, but not the reverse.
+//!
+
+use super::FrameData;
+use super::FrameFileError;
+use super::MonoFrameData;
+
+use rgb::Gray;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::{colortype, TiffEncoder};
+
+/// Decoded TIFF image: width, height, colour type and samples
+struct TiffImage {
+    width: u32,
+    height: u32,
+    color_type: tiff::ColorType,
+    samples: DecodingResult,
+}
+
+impl TiffImage {
+    /// Read the first image in a TIFF file
+    fn read(filename: &str) -> Result<Self, FrameFileError> {
+        let mut decoder = Decoder::new(BufReader::new(File::open(filename)?))?;
+        let (width, height) = decoder.dimensions()?;
+        let color_type = decoder.colortype()?;
+        let samples = decoder.read_image()?;
+        Ok(TiffImage {
+            width,
+            height,
+            color_type,
+            samples,
+        })
+    }
+
+    /// Samples of the image as 16-bit values, widening 8-bit samples
+    fn samples16(self, target: &str) -> Result<Vec<u16>, FrameFileError> {
+        match self.samples {
+            DecodingResult::U16(v) => Ok(v),
+            DecodingResult::U8(v) => Ok(v.into_iter().map(|x| x as u16).collect()),
+            _ => Err(self.unsupported(target)),
+        }
+    }
+
+    /// Samples of the image as 8-bit values
+    fn samples8(self, target: &str) -> Result<Vec<u8>, FrameFileError> {
+        match self.samples {
+            DecodingResult::U8(v) => Ok(v),
+            _ => Err(self.unsupported(target)),
+        }
+    }
+
+    fn unsupported(&self, target: &str) -> FrameFileError {
+        FrameFileError::Unsupported(format!(
+            "cannot load {:?} TIFF as {}",
+            self.color_type, target
+        ))
+    }
+}
+
+/// Read only the header of the first image in a TIFF file, returning its colour type
+pub(super) fn read_tiff_color_type(filename: &str) -> Result<tiff::ColorType, FrameFileError> {
+    Ok(Decoder::new(BufReader::new(File::open(filename)?))?.colortype()?)
+}
+
+fn write_tiff<C>(
+    filename: &str,
+    width: u32,
+    height: u32,
+    data: &[C::Inner],
+) -> Result<(), FrameFileError>
+where
+    C: colortype::ColorType,
+    [C::Inner]: tiff::encoder::TiffValue,
+{
+    let mut encoder = TiffEncoder::new(BufWriter::new(File::create(filename)?))?;
+    encoder.write_image::<C>(width, height, data)?;
+    Ok(())
+}
+
+impl MonoFrameData<u8> {
+    /// Save the FrameData to an 8-bit grayscale TIFF file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the TIFF to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_tiff(&self, filename: &str) -> Result<(), FrameFileError> {
+        let data: Vec<u8> = self.data.iter().map(|x| x.value()).collect();
+        write_tiff::<colortype::Gray8>(filename, self.width, self.height, &data)
+    }
+
+    /// Load FrameData from an 8-bit grayscale TIFF file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the TIFF file to load.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or is not 8-bit grayscale.
+    ///
+    pub fn load_from_tiff(filename: &str) -> Result<Self, FrameFileError> {
+        let image = TiffImage::read(filename)?;
+        if image.color_type != tiff::ColorType::Gray(8) {
+            return Err(image.unsupported("8-bit grayscale"));
+        }
+        let (width, height) = (image.width, image.height);
+        Ok(MonoFrameData::<u8> {
+            width,
+            height,
+            data: image
+                .samples8("8-bit grayscale")?
+                .into_iter()
+                .map(Gray::<u8>::new)
+                .collect(),
+        })
+    }
+}
+
+impl MonoFrameData<u16> {
+    /// Save the FrameData to a 16-bit grayscale TIFF file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the TIFF to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_tiff(&self, filename: &str) -> Result<(), FrameFileError> {
+        let data: Vec<u16> = self.data.iter().map(|x| x.value()).collect();
+        write_tiff::<colortype::Gray16>(filename, self.width, self.height, &data)
+    }
+
+    /// Load FrameData from an 8 or 16-bit grayscale TIFF file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the TIFF file to load.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or is not grayscale.
+    ///
+    pub fn load_from_tiff(filename: &str) -> Result<Self, FrameFileError> {
+        let image = TiffImage::read(filename)?;
+        if !matches!(image.color_type, tiff::ColorType::Gray(8 | 16)) {
+            return Err(image.unsupported("16-bit grayscale"));
+        }
+        let (width, height) = (image.width, image.height);
+        Ok(MonoFrameData::<u16> {
+            width,
+            height,
+            data: image
+                .samples16("16-bit grayscale")?
+                .into_iter()
+                .map(Gray::<u16>::new)
+                .collect(),
+        })
+    }
+}
+
+impl FrameData<rgb::RGB8> {
+    /// Save the FrameData to an 8-bit RGB TIFF file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the TIFF to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_tiff(&self, filename: &str) -> Result<(), FrameFileError> {
+        let data: Vec<u8> = self.data.iter().flat_map(|x| [x.r, x.g, x.b]).collect();
+        write_tiff::<colortype::RGB8>(filename, self.width, self.height, &data)
+    }
+
+    /// Load FrameData from an 8-bit RGB TIFF file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the TIFF file to load.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or is not 8-bit RGB.
+    ///
+    pub fn load_from_tiff(filename: &str) -> Result<Self, FrameFileError> {
+        let image = TiffImage::read(filename)?;
+        if image.color_type != tiff::ColorType::RGB(8) {
+            return Err(image.unsupported("8-bit RGB"));
+        }
+        let (width, height) = (image.width, image.height);
+        Ok(FrameData::<rgb::RGB8> {
+            width,
+            height,
+            data: image
+                .samples8("8-bit RGB")?
+                .chunks_exact(3)
+                .map(|c| rgb::RGB8::new(c[0], c[1], c[2]))
+                .collect(),
+        })
+    }
+}
+
+impl FrameData<rgb::RGB16> {
+    /// Save the FrameData to a 16-bit-per-channel RGB TIFF file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the TIFF to.
+    ///
+    /// # Returns
+    /// An empty Result if the save was successful, or an error if the save failed.
+    ///
+    pub fn save_to_tiff(&self, filename: &str) -> Result<(), FrameFileError> {
+        let data: Vec<u16> = self.data.iter().flat_map(|x| [x.r, x.g, x.b]).collect();
+        write_tiff::<colortype::RGB16>(filename, self.width, self.height, &data)
+    }
+
+    /// Load FrameData from an 8 or 16-bit RGB TIFF file.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the TIFF file to load.
+    ///
+    /// # Returns
+    /// The FrameData, or an error if the file could not be read or is not RGB.
+    ///
+    pub fn load_from_tiff(filename: &str) -> Result<Self, FrameFileError> {
+        let image = TiffImage::read(filename)?;
+        if !matches!(image.color_type, tiff::ColorType::RGB(8 | 16)) {
+            return Err(image.unsupported("16-bit RGB"));
+        }
+        let (width, height) = (image.width, image.height);
+        Ok(FrameData::<rgb::RGB16> {
+            width,
+            height,
+            data: image
+                .samples16("16-bit RGB")?
+                .chunks_exact(3)
+                .map(|c| rgb::RGB16::new(c[0], c[1], c[2]))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiff16_roundtrip() {
+        let data = MonoFrameData::<u16> {
+            width: 40,
+            height: 25,
+            data: (0..40 * 25)
+                .map(|x| Gray::<u16>::new((x * 65) as u16))
+                .collect(),
+        };
+        let filename = std::env::temp_dir().join("camera_test_mono16.tiff");
+        let filename = filename.to_str().unwrap();
+        data.save_to_tiff(filename).unwrap();
+        let data2 = MonoFrameData::<u16>::load_from_tiff(filename).unwrap();
+        assert_eq!(data2.width, 40);
+        assert_eq!(data2.height, 25);
+        assert_eq!(data2.data, data.data);
+        assert!(MonoFrameData::<u8>::load_from_tiff(filename).is_err());
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_tiff_rgb8_roundtrip() {
+        let data = FrameData::<rgb::RGB8> {
+            width: 6,
+            height: 2,
+            data: (0..12)
+                .map(|x| rgb::RGB8::new(x, x * 10, 255 - x))
+                .collect(),
+        };
+        let filename = std::env::temp_dir().join("camera_test_rgb8.tiff");
+        let filename = filename.to_str().unwrap();
+        data.save_to_tiff(filename).unwrap();
+        let data2 = FrameData::<rgb::RGB8>::load_from_tiff(filename).unwrap();
+        assert_eq!(data2.data, data.data);
+        // 8-bit data widens losslessly into a 16-bit frame
+        let data3 = FrameData::<rgb::RGB16>::load_from_tiff(filename).unwrap();
+        assert_eq!(data3.data[1], rgb::RGB16::new(1, 10, 254));
+        let _ = std::fs::remove_file(filename);
+    }
+}
@@ -1,89 +1,209 @@
+//!
+//! This module contains functions for saving FrameData to PNG files.
+//!
+
 use super::FrameData;
-use super::MonoFrameData;
+use super::FrameFileError;
+use crate::Pixel;
 
+use rgb::Gray;
 use std::fs::File;
+use std::io::BufWriter;
 
-impl MonoFrameData<u8> {
-    /// Save the FrameData to a PNG file.
-    ///
-    /// # Arguments
-    /// `filename` - The name of the file to save the PNG to.
-    ///
-    /// # Returns
-    /// An empty Result if the save was successful, or an error if the save failed.
-    ///
-    pub fn save_to_png(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut encoder = png::Encoder::new(File::create(filename)?, self.width, self.height);
-        encoder.set_color(png::ColorType::Grayscale);
-        let bitdepth = png::BitDepth::Eight;
+/// Pixel types that can be stored in a PNG file
+pub trait PngPixel: Pixel {
+    /// PNG colour type used to store the pixel
+    const COLOR: png::ColorType;
 
-        encoder.set_depth(bitdepth);
-        let mut writer = encoder.write_header()?;
-        writer.write_image_data(unsafe {
-            std::slice::from_raw_parts(
-                self.data
-                    .iter()
-                    .map(|x| x.0.swap_bytes())
-                    .collect::<Vec<u8>>()
-                    .as_ptr(),
-                self.data.len(),
-            )
-        })?;
-        Ok(())
+    /// PNG bit depth of each sample
+    const DEPTH: png::BitDepth;
+
+    /// Encode pixels as PNG sample bytes (16-bit samples are big-endian)
+    fn to_png_bytes(data: &[Self]) -> Vec<u8>;
+
+    /// Decode PNG sample bytes with the given colour type and bit depth,
+    /// or None if they cannot be represented by the pixel type without loss
+    fn from_png_bytes(
+        color: png::ColorType,
+        depth: png::BitDepth,
+        bytes: &[u8],
+    ) -> Option<Vec<Self>>;
+}
+
+/// Samples of a decoded PNG as 16-bit values, widening 8-bit samples
+fn samples16(depth: png::BitDepth, bytes: &[u8]) -> Vec<u16> {
+    match depth {
+        png::BitDepth::Sixteen => bytes
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect(),
+        _ => bytes.iter().map(|b| *b as u16).collect(),
     }
 }
 
-impl FrameData<rgb::RGBA8> {
-    /// Save the FrameData to a PNG file.
-    ///
-    /// # Arguments
-    /// `filename` - The name of the file to save the PNG to.
-    ///
-    /// # Returns
-    /// An empty Result if the save was successful, or an error if the save failed.
-    ///    
-    pub fn save_to_png(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut encoder = png::Encoder::new(File::create(filename)?, self.width, self.height);
-        encoder.set_color(png::ColorType::Rgba);
-        encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = encoder.write_header()?;
-        writer.write_image_data(unsafe {
-            std::slice::from_raw_parts(
-                self.data.as_ptr() as *const u8,
-                self.data.len() * std::mem::size_of::<rgb::RGBA8>(),
-            )
-        })?;
-        Ok(())
+impl PngPixel for Gray<u8> {
+    const COLOR: png::ColorType = png::ColorType::Grayscale;
+    const DEPTH: png::BitDepth = png::BitDepth::Eight;
+
+    fn to_png_bytes(data: &[Self]) -> Vec<u8> {
+        data.iter().map(|x| x.value()).collect()
+    }
+
+    fn from_png_bytes(
+        color: png::ColorType,
+        depth: png::BitDepth,
+        bytes: &[u8],
+    ) -> Option<Vec<Self>> {
+        match (color, depth) {
+            (png::ColorType::Grayscale, png::BitDepth::Eight) => {
+                Some(bytes.iter().map(|x| Gray::<u8>::new(*x)).collect())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl PngPixel for Gray<u16> {
+    const COLOR: png::ColorType = png::ColorType::Grayscale;
+    const DEPTH: png::BitDepth = png::BitDepth::Sixteen;
+
+    fn to_png_bytes(data: &[Self]) -> Vec<u8> {
+        data.iter().flat_map(|x| x.value().to_be_bytes()).collect()
+    }
+
+    fn from_png_bytes(
+        color: png::ColorType,
+        depth: png::BitDepth,
+        bytes: &[u8],
+    ) -> Option<Vec<Self>> {
+        match color {
+            png::ColorType::Grayscale => Some(
+                samples16(depth, bytes)
+                    .into_iter()
+                    .map(Gray::<u16>::new)
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl PngPixel for rgb::RGB8 {
+    const COLOR: png::ColorType = png::ColorType::Rgb;
+    const DEPTH: png::BitDepth = png::BitDepth::Eight;
+
+    fn to_png_bytes(data: &[Self]) -> Vec<u8> {
+        data.iter().flat_map(|x| [x.r, x.g, x.b]).collect()
+    }
+
+    fn from_png_bytes(
+        color: png::ColorType,
+        depth: png::BitDepth,
+        bytes: &[u8],
+    ) -> Option<Vec<Self>> {
+        match (color, depth) {
+            (png::ColorType::Rgb, png::BitDepth::Eight) => Some(
+                bytes
+                    .chunks_exact(3)
+                    .map(|c| rgb::RGB8::new(c[0], c[1], c[2]))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl PngPixel for rgb::RGB16 {
+    const COLOR: png::ColorType = png::ColorType::Rgb;
+    const DEPTH: png::BitDepth = png::BitDepth::Sixteen;
+
+    fn to_png_bytes(data: &[Self]) -> Vec<u8> {
+        data.iter()
+            .flat_map(|x| [x.r, x.g, x.b])
+            .flat_map(|x| x.to_be_bytes())
+            .collect()
+    }
+
+    fn from_png_bytes(
+        color: png::ColorType,
+        depth: png::BitDepth,
+        bytes: &[u8],
+    ) -> Option<Vec<Self>> {
+        match color {
+            png::ColorType::Rgb => Some(
+                samples16(depth, bytes)
+                    .chunks_exact(3)
+                    .map(|c| rgb::RGB16::new(c[0], c[1], c[2]))
+                    .collect(),
+            ),
+            _ => None,
+        }
     }
 }
 
-impl FrameData<rgb::RGB8> {
+impl PngPixel for rgb::RGBA8 {
+    const COLOR: png::ColorType = png::ColorType::Rgba;
+    const DEPTH: png::BitDepth = png::BitDepth::Eight;
+
+    fn to_png_bytes(data: &[Self]) -> Vec<u8> {
+        data.iter().flat_map(|x| [x.r, x.g, x.b, x.a]).collect()
+    }
+
+    fn from_png_bytes(
+        color: png::ColorType,
+        depth: png::BitDepth,
+        bytes: &[u8],
+    ) -> Option<Vec<Self>> {
+        match (color, depth) {
+            (png::ColorType::Rgba, png::BitDepth::Eight) => Some(
+                bytes
+                    .chunks_exact(4)
+                    .map(|c| rgb::RGBA8::new(c[0], c[1], c[2], c[3]))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Create a PNG encoder for the given pixel type and image size
+pub(super) fn png_encoder<T: PngPixel>(
+    filename: &str,
+    width: u32,
+    height: u32,
+) -> Result<png::Encoder<'static, BufWriter<File>>, FrameFileError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(filename)?), width, height);
+    encoder.set_color(T::COLOR);
+    encoder.set_depth(T::DEPTH);
+    Ok(encoder)
+}
+
+impl<T> FrameData<T>
+where
+    T: PngPixel,
+{
     /// Save the FrameData to a PNG file.
     ///
+    /// 16-bit pixel types are saved as 16-bit PNG files, so no dynamic range is lost.
+    ///
     /// # Arguments
     /// `filename` - The name of the file to save the PNG to.
     ///
     /// # Returns
     /// An empty Result if the save was successful, or an error if the save failed.
-    ///    
-    pub fn save_to_png(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut encoder = png::Encoder::new(File::create(filename)?, self.width, self.height);
-        encoder.set_color(png::ColorType::Rgb);
-        encoder.set_depth(png::BitDepth::Eight);
+    ///
+    pub fn save_to_png(&self, filename: &str) -> Result<(), FrameFileError> {
+        let encoder = png_encoder::<T>(filename, self.width, self.height)?;
         let mut writer = encoder.write_header()?;
-        writer.write_image_data(unsafe {
-            std::slice::from_raw_parts(
-                self.data.as_ptr() as *const u8,
-                self.data.len() * std::mem::size_of::<rgb::RGB8>(),
-            )
-        })?;
+        writer.write_image_data(&T::to_png_bytes(&self.data))?;
+        writer.finish()?;
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::MonoFrameData;
 
     fn test_data() -> MonoFrameData<u16> {
         MonoFrameData::<u16> {
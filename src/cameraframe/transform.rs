@@ -0,0 +1,370 @@
+//!
+//! Geometric transforms of frames: flips, rotations, transposition, cropping and
+//! padding, for any pixel type.  Rotation by an arbitrary angle interpolates, so
+//! it is limited to the crate's own pixel types.
+//!
+
+use super::FrameData;
+use crate::pixel::Interpolate;
+use crate::svbony::lowlevel::FlipStatus;
+use crate::Pixel;
+
+/// Errors transforming frames
+#[derive(Debug, thiserror::Error)]
+pub enum TransformError {
+    #[error("Region {region:?} is empty or outside the {size:?} frame")]
+    InvalidRegion {
+        region: (u32, u32, u32, u32),
+        size: (u32, u32),
+    },
+}
+
+/// Mirroring of a frame about its axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flip {
+    #[default]
+    None,
+    /// Mirror left to right
+    Horizontal,
+    /// Mirror top to bottom
+    Vertical,
+    /// Mirror about both axes, the same as rotating by 180 degrees
+    Both,
+}
+
+impl From<FlipStatus> for Flip {
+    fn from(status: FlipStatus) -> Self {
+        match status {
+            FlipStatus::None => Flip::None,
+            FlipStatus::Horizontal => Flip::Horizontal,
+            FlipStatus::Vertical => Flip::Vertical,
+            FlipStatus::Both => Flip::Both,
+        }
+    }
+}
+
+impl<T> FrameData<T>
+where
+    T: Pixel,
+{
+    /// Build a frame of the given size from a function giving, for each output
+    /// pixel, the coordinates of the input pixel to copy
+    fn remap(&self, width: u32, height: u32, source: impl Fn(u32, u32) -> (u32, u32)) -> Self {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (sx, sy) = source(x, y);
+                self.at(sx, sy)
+            })
+            .collect();
+        FrameData {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Mirror the frame left to right.
+    ///
+    /// # Returns
+    /// The flipped frame.
+    ///
+    pub fn flip_horizontal(&self) -> Self {
+        let w = self.width;
+        self.remap(self.width, self.height, |x, y| (w - 1 - x, y))
+    }
+
+    /// Mirror the frame top to bottom.
+    ///
+    /// # Returns
+    /// The flipped frame.
+    ///
+    pub fn flip_vertical(&self) -> Self {
+        let h = self.height;
+        self.remap(self.width, self.height, |x, y| (x, h - 1 - y))
+    }
+
+    /// Mirror the frame about either or both axes.
+    ///
+    /// # Arguments
+    /// `flip` - The axes to mirror about.
+    ///
+    /// # Returns
+    /// The flipped frame.
+    ///
+    pub fn flip(&self, flip: Flip) -> Self {
+        match flip {
+            Flip::None => self.clone(),
+            Flip::Horizontal => self.flip_horizontal(),
+            Flip::Vertical => self.flip_vertical(),
+            Flip::Both => self.rotate180(),
+        }
+    }
+
+    /// Swap the rows and columns of the frame.
+    ///
+    /// # Returns
+    /// The transposed frame, `height` wide and `width` high.
+    ///
+    pub fn transpose(&self) -> Self {
+        self.remap(self.height, self.width, |x, y| (y, x))
+    }
+
+    /// Rotate the frame 90 degrees clockwise, as displayed with the first row at the top.
+    ///
+    /// # Returns
+    /// The rotated frame, `height` wide and `width` high.
+    ///
+    pub fn rotate90(&self) -> Self {
+        let h = self.height;
+        self.remap(self.height, self.width, |x, y| (y, h - 1 - x))
+    }
+
+    /// Rotate the frame 180 degrees.
+    ///
+    /// # Returns
+    /// The rotated frame.
+    ///
+    pub fn rotate180(&self) -> Self {
+        let mut data = self.data.clone();
+        data.reverse();
+        FrameData {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// Rotate the frame 270 degrees clockwise, i.e. 90 degrees anticlockwise.
+    ///
+    /// # Returns
+    /// The rotated frame, `height` wide and `width` high.
+    ///
+    pub fn rotate270(&self) -> Self {
+        let w = self.width;
+        self.remap(self.height, self.width, |x, y| (w - 1 - y, x))
+    }
+
+    /// Add borders around the frame.
+    ///
+    /// # Arguments
+    /// `left` - Columns to add on the left.
+    /// `top` - Rows to add at the top.
+    /// `right` - Columns to add on the right.
+    /// `bottom` - Rows to add at the bottom.
+    /// `value` - Value of the added pixels.
+    ///
+    /// # Returns
+    /// The padded frame.
+    ///
+    pub fn pad(&self, left: u32, top: u32, right: u32, bottom: u32, value: T) -> Self {
+        let width = left + self.width + right;
+        let height = top + self.height + bottom;
+        let mut data = vec![value; (width * height) as usize];
+        for (y, row) in self
+            .data
+            .chunks_exact(self.width.max(1) as usize)
+            .enumerate()
+        {
+            let start = (y as u32 + top) * width + left;
+            data[start as usize..start as usize + row.len()].copy_from_slice(row);
+        }
+        FrameData {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Copy a region of the frame, checking its bounds.
+    ///
+    /// # Arguments
+    /// `top_left_x` - Column of the top-left corner, inclusive.
+    /// `top_left_y` - Row of the top-left corner, inclusive.
+    /// `bottom_right_x` - Column of the bottom-right corner, exclusive.
+    /// `bottom_right_y` - Row of the bottom-right corner, exclusive.
+    ///
+    /// # Returns
+    /// The region, or an error if it is empty or extends outside the frame.
+    ///
+    pub fn crop(
+        &self,
+        top_left_x: u32,
+        top_left_y: u32,
+        bottom_right_x: u32,
+        bottom_right_y: u32,
+    ) -> Result<Self, TransformError> {
+        if top_left_x >= bottom_right_x
+            || top_left_y >= bottom_right_y
+            || bottom_right_x > self.width
+            || bottom_right_y > self.height
+        {
+            return Err(TransformError::InvalidRegion {
+                region: (top_left_x, top_left_y, bottom_right_x, bottom_right_y),
+                size: (self.width, self.height),
+            });
+        }
+        Ok(self.subregion(top_left_x, top_left_y, bottom_right_x, bottom_right_y))
+    }
+}
+
+impl<T> FrameData<T>
+where
+    T: Interpolate,
+{
+    /// Rotate the frame clockwise by an arbitrary angle about its centre, with
+    /// bilinear interpolation.
+    ///
+    /// # Arguments
+    /// `degrees` - The angle, clockwise as displayed with the first row at the top.
+    /// `fill` - Value of output pixels that fall outside the input frame.
+    ///
+    /// # Returns
+    /// The rotated frame, of the same size; corners are cut off.
+    ///
+    pub fn rotate(&self, degrees: f64, fill: T) -> Self {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (cx, cy) = ((w - 1) as f64 / 2.0, (h - 1) as f64 / 2.0);
+        let at = |x: i64, y: i64| {
+            self.data[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize].to_components()
+        };
+        let data = (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                // Rotate the output position back into the input
+                let (ox, oy) = (x as f64 - cx, y as f64 - cy);
+                let sx = cx + ox * cos + oy * sin;
+                let sy = cy - ox * sin + oy * cos;
+                if sx < -0.5 || sy < -0.5 || sx > w as f64 - 0.5 || sy > h as f64 - 0.5 {
+                    return fill;
+                }
+                let (x0, y0) = (sx.floor() as i64, sy.floor() as i64);
+                let (fx, fy) = (sx - x0 as f64, sy - y0 as f64);
+                let (p00, p10) = (at(x0, y0), at(x0 + 1, y0));
+                let (p01, p11) = (at(x0, y0 + 1), at(x0 + 1, y0 + 1));
+                let mut c = [0.0; 4];
+                for (i, c) in c.iter_mut().enumerate() {
+                    let top = p00[i] * (1.0 - fx) + p10[i] * fx;
+                    let bottom = p01[i] * (1.0 - fx) + p11[i] * fx;
+                    *c = top * (1.0 - fy) + bottom * fy;
+                }
+                T::from_components(c)
+            })
+            .collect();
+        FrameData {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoFrameData;
+    use rgb::Gray;
+
+    /// 3 wide, 2 high:
+    /// 0 1 2
+    /// 3 4 5
+    fn frame() -> MonoFrameData<u8> {
+        FrameData {
+            width: 3,
+            height: 2,
+            data: (0..6).map(Gray::new).collect(),
+        }
+    }
+
+    fn values(f: &MonoFrameData<u8>) -> Vec<u8> {
+        f.data.iter().map(|p| p.value()).collect()
+    }
+
+    #[test]
+    fn test_flips_and_rotations() {
+        let f = frame();
+        assert_eq!(values(&f.flip_horizontal()), [2, 1, 0, 5, 4, 3]);
+        assert_eq!(values(&f.flip_vertical()), [3, 4, 5, 0, 1, 2]);
+        assert_eq!(values(&f.flip(Flip::Both)), [5, 4, 3, 2, 1, 0]);
+        assert_eq!(
+            values(&f.flip(FlipStatus::Vertical.into())),
+            [3, 4, 5, 0, 1, 2]
+        );
+
+        let t = f.transpose();
+        assert_eq!((t.width, t.height), (2, 3));
+        assert_eq!(values(&t), [0, 3, 1, 4, 2, 5]);
+
+        let r = f.rotate90();
+        assert_eq!((r.width, r.height), (2, 3));
+        assert_eq!(values(&r), [3, 0, 4, 1, 5, 2]);
+        assert_eq!(values(&f.rotate270()), [2, 5, 1, 4, 0, 3]);
+        assert_eq!(values(&r.rotate90()), values(&f.rotate180()));
+        assert_eq!(
+            values(&f.rotate90().rotate90().rotate90().rotate90()),
+            values(&f)
+        );
+
+        // Pixel types defined outside the crate need only implement `Pixel`
+        #[derive(Debug, Clone, Copy, Default, PartialEq)]
+        struct Label(u32);
+        impl Pixel for Label {}
+        let labels = FrameData {
+            width: 2,
+            height: 1,
+            data: vec![Label(1), Label(2)],
+        };
+        assert_eq!(labels.rotate90().data, [Label(1), Label(2)]);
+        assert_eq!(labels.flip_horizontal().data, [Label(2), Label(1)]);
+    }
+
+    #[test]
+    fn test_arbitrary_rotation() {
+        let square = MonoFrameData::<u16> {
+            width: 5,
+            height: 5,
+            data: (0..25).map(|v| Gray::new(v * 100)).collect(),
+        };
+        assert_eq!(
+            values16(&square.rotate(90.0, Gray::new(0))),
+            values16(&square.rotate90())
+        );
+        assert_eq!(
+            values16(&square.rotate(0.0, Gray::new(0))),
+            values16(&square)
+        );
+
+        // A half-way rotation of an RGB frame blends pixels and fills the corners
+        let rgb = FrameData::<rgb::RGB8> {
+            width: 9,
+            height: 9,
+            data: vec![rgb::RGB8::new(200, 100, 50); 81],
+        };
+        let rotated = rgb.rotate(45.0, rgb::RGB8::new(0, 0, 0));
+        assert_eq!(rotated.at(4, 4), rgb::RGB8::new(200, 100, 50));
+        assert_eq!(rotated.at(0, 0), rgb::RGB8::new(0, 0, 0));
+    }
+
+    fn values16(f: &MonoFrameData<u16>) -> Vec<u16> {
+        f.data.iter().map(|p| p.value()).collect()
+    }
+
+    #[test]
+    fn test_pad_and_crop() {
+        let f = frame();
+        let p = f.pad(1, 0, 2, 1, Gray::new(9));
+        assert_eq!((p.width, p.height), (6, 3));
+        assert_eq!(
+            values(&p),
+            [9, 0, 1, 2, 9, 9, 9, 3, 4, 5, 9, 9, 9, 9, 9, 9, 9, 9]
+        );
+        let c = p.crop(1, 0, 4, 2).unwrap();
+        assert_eq!(values(&c), values(&f));
+        assert!(matches!(
+            f.crop(0, 0, 4, 2),
+            Err(TransformError::InvalidRegion { .. })
+        ));
+        assert!(f.crop(1, 1, 1, 2).is_err());
+    }
+}
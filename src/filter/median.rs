@@ -0,0 +1,220 @@
+//!
+//! Median filtering of monochrome frames.
+//!
+//! For pixel types of up to 16 bits the window histogram is updated as the window
+//! slides along each row, adding the column entering and removing the column
+//! leaving, and the median is found by scanning a two-level histogram: coarse bins
+//! of 256 values, then the fine bins within one.  Wider types sort each window.
+//!
+
+use super::BorderMode;
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+use rgb::Gray;
+
+/// Two-level histogram of pixel values offset from the minimum of the type
+struct WindowHistogram {
+    fine: Vec<u32>,
+    coarse: Vec<u32>,
+}
+
+impl WindowHistogram {
+    fn new(bins: usize) -> Self {
+        WindowHistogram {
+            fine: vec![0; bins],
+            coarse: vec![0; bins.div_ceil(256)],
+        }
+    }
+
+    fn add(&mut self, bin: usize) {
+        self.fine[bin] += 1;
+        self.coarse[bin >> 8] += 1;
+    }
+
+    fn remove(&mut self, bin: usize) {
+        self.fine[bin] -= 1;
+        self.coarse[bin >> 8] -= 1;
+    }
+
+    /// Bin holding the value of the given zero-based rank
+    fn rank(&self, rank: u32) -> usize {
+        let mut below = 0;
+        for (c, count) in self.coarse.iter().enumerate() {
+            if below + count > rank {
+                for (i, n) in self.fine[c << 8..].iter().enumerate() {
+                    below += n;
+                    if below > rank {
+                        return (c << 8) + i;
+                    }
+                }
+            }
+            below += count;
+        }
+        self.fine.len() - 1
+    }
+}
+
+/// Replace each pixel by the median of the square window centred on it.
+///
+/// # Arguments
+/// `frame` - The frame.
+/// `radius` - Half-width of the window, which is `2 * radius + 1` pixels square.
+/// `border` - How pixels beyond the edges are supplied.
+///
+/// # Returns
+/// The filtered frame.
+///
+pub fn median_filter<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    radius: u32,
+    border: BorderMode,
+) -> MonoFrameData<T> {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let r = radius as usize;
+    if r == 0 || w == 0 || h == 0 {
+        return frame.clone();
+    }
+    let size = 2 * r + 1;
+    let pw = w + 2 * r;
+    let constant = T::from_f64(match border {
+        BorderMode::Constant(v) => v,
+        _ => 0.0,
+    });
+    let mut padded = vec![constant; pw * (h + 2 * r)];
+    for y in 0..h + 2 * r {
+        let Some(sy) = border.index(y as i64 - r as i64, h) else {
+            continue;
+        };
+        for x in 0..pw {
+            if let Some(sx) = border.index(x as i64 - r as i64, w) {
+                padded[y * pw + x] = frame.data[sy * w + sx].value();
+            }
+        }
+    }
+
+    let mut data = Vec::with_capacity(w * h);
+    let median_rank = (size * size / 2) as u32;
+    if std::mem::size_of::<T>() <= 2 {
+        let min = T::min_value().to_i64().unwrap_or(0);
+        let bin = |v: T| (v.to_i64().unwrap_or(0) - min) as usize;
+        let mut hist = WindowHistogram::new(1 << (8 * std::mem::size_of::<T>()));
+        for y in 0..h {
+            let column = |x: usize| (y..y + size).map(move |wy| wy * pw + x);
+            for x in 0..size {
+                column(x).for_each(|i| hist.add(bin(padded[i])));
+            }
+            for x in 0..w {
+                if x > 0 {
+                    column(x - 1).for_each(|i| hist.remove(bin(padded[i])));
+                    column(x + size - 1).for_each(|i| hist.add(bin(padded[i])));
+                }
+                let value = (hist.rank(median_rank) as i64 + min) as f64;
+                data.push(Gray::new(T::from_f64(value)));
+            }
+            for x in w - 1..w - 1 + size {
+                column(x).for_each(|i| hist.remove(bin(padded[i])));
+            }
+        }
+    } else {
+        let mut window = Vec::with_capacity(size * size);
+        for y in 0..h {
+            for x in 0..w {
+                window.clear();
+                for wy in y..y + size {
+                    window.extend_from_slice(&padded[wy * pw + x..wy * pw + x + size]);
+                }
+                let (_, median, _) = window.select_nth_unstable(median_rank as usize);
+                data.push(Gray::new(*median));
+            }
+        }
+    }
+    MonoFrameData {
+        width: frame.width,
+        height: frame.height,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Median filter by sorting every window, for comparison
+    fn reference<T: MonoPixel>(frame: &MonoFrameData<T>, radius: i64) -> Vec<T> {
+        let (w, h) = (frame.width as i64, frame.height as i64);
+        let mut out = Vec::new();
+        for y in 0..h {
+            for x in 0..w {
+                let mut window: Vec<T> = (-radius..=radius)
+                    .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+                    .map(|(dx, dy)| {
+                        let sx = BorderMode::Reflect.index(x + dx, w as usize).unwrap();
+                        let sy = BorderMode::Reflect.index(y + dy, h as usize).unwrap();
+                        frame.data[sy * w as usize + sx].value()
+                    })
+                    .collect();
+                window.sort();
+                out.push(window[window.len() / 2]);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_median_filter() {
+        let mut lcg = crate::test_util::lcg(11);
+        let mut next = move || lcg() >> 8;
+        let (w, h) = (13, 9);
+        let f16 = MonoFrameData::<u16> {
+            width: w,
+            height: h,
+            data: (0..w * h).map(|_| Gray::new(next() as u16)).collect(),
+        };
+        let f8 = MonoFrameData::<u8> {
+            width: w,
+            height: h,
+            data: (0..w * h).map(|_| Gray::new(next() as u8)).collect(),
+        };
+        let f32bit = MonoFrameData::<i32> {
+            width: w,
+            height: h,
+            data: (0..w * h)
+                .map(|_| Gray::new(next() as i32 - (1 << 23)))
+                .collect(),
+        };
+        for radius in [1, 2, 4] {
+            let values =
+                |f: MonoFrameData<u16>| f.data.iter().map(|p| p.value()).collect::<Vec<_>>();
+            assert_eq!(
+                values(median_filter(&f16, radius, BorderMode::Reflect)),
+                reference(&f16, radius as i64)
+            );
+            let m8: Vec<u8> = median_filter(&f8, radius, BorderMode::Reflect)
+                .data
+                .iter()
+                .map(|p| p.value())
+                .collect();
+            assert_eq!(m8, reference(&f8, radius as i64));
+            let m32: Vec<i32> = median_filter(&f32bit, radius, BorderMode::Reflect)
+                .data
+                .iter()
+                .map(|p| p.value())
+                .collect();
+            assert_eq!(m32, reference(&f32bit, radius as i64));
+        }
+
+        // Isolated hot pixels are removed, and a constant border pulls edges towards it
+        let mut flat = MonoFrameData::<i16> {
+            width: 5,
+            height: 5,
+            data: vec![Gray::new(-100); 25],
+        };
+        flat.data[12] = Gray::new(30000);
+        let cleaned = median_filter(&flat, 1, BorderMode::Replicate);
+        assert!(cleaned.data.iter().all(|p| p.value() == -100));
+        let bordered = median_filter(&flat, 1, BorderMode::Constant(7.0));
+        assert_eq!(bordered.at(0, 0).value(), 7);
+        assert_eq!(bordered.at(1, 0).value(), -100);
+    }
+}
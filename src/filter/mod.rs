@@ -0,0 +1,587 @@
+//!
+//! Convolution and filtering of monochrome frames.
+//!
+//! [`convolve`] applies a general 2D kernel and [`convolve_separable`] a kernel that
+//! is the product of a row and a column kernel, in floating point, returning
+//! `MonoFrameData<f32>` frames so negative and fractional results are kept.  Kernels are
+//! applied without flipping, i.e. as a correlation, and are centred on each pixel.
+//! Pixels beyond the edges of the frame are supplied by a [`BorderMode`].
+//!
+//! The ready-made smoothing filters return frames of the input pixel type, rounded
+//! and clamped, and [`gradient`] returns the Sobel or Scharr derivatives.
+//!
+
+mod median;
+
+pub use median::median_filter;
+
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+use rgb::Gray;
+
+/// Errors filtering frames
+#[derive(Debug, thiserror::Error)]
+pub enum FilterError {
+    #[error("Kernel of {width}x{height} needs {expected} weights, found {found}")]
+    KernelSize {
+        width: usize,
+        height: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Kernel dimensions must be odd, found {width}x{height}")]
+    EvenKernel { width: usize, height: usize },
+    #[error("Invalid filter parameter: {0}")]
+    InvalidParameter(String),
+}
+
+/// How pixels beyond the edges of a frame are supplied
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BorderMode {
+    /// A fixed value
+    Constant(f64),
+    /// The nearest edge pixel
+    Replicate,
+    /// Mirror image about the edge pixel, which is not repeated
+    #[default]
+    Reflect,
+    /// The pixels from the opposite edge, as if the frame were tiled
+    Wrap,
+}
+
+impl BorderMode {
+    /// Index of the pixel supplying position `i` along an axis of `len` pixels, or
+    /// `None` for a constant border
+    pub(crate) fn index(&self, i: i64, len: usize) -> Option<usize> {
+        let n = len as i64;
+        if (0..n).contains(&i) {
+            return Some(i as usize);
+        }
+        match self {
+            BorderMode::Constant(_) => None,
+            BorderMode::Replicate => Some(i.clamp(0, n - 1) as usize),
+            BorderMode::Reflect => {
+                if n == 1 {
+                    return Some(0);
+                }
+                let period = 2 * (n - 1);
+                let i = i.rem_euclid(period);
+                Some(if i >= n { period - i } else { i } as usize)
+            }
+            BorderMode::Wrap => Some(i.rem_euclid(n) as usize),
+        }
+    }
+
+    fn constant(&self) -> f64 {
+        match self {
+            BorderMode::Constant(v) => *v,
+            _ => 0.0,
+        }
+    }
+}
+
+/// A 2D kernel of odd width and height, centred on its middle weight
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f64>,
+}
+
+impl Kernel {
+    /// Create a kernel.
+    ///
+    /// # Arguments
+    /// `width` - Number of columns, odd.
+    /// `height` - Number of rows, odd.
+    /// `weights` - The `width * height` weights, row-major.
+    ///
+    /// # Returns
+    /// The kernel, or an error if a dimension is even or the weights are the wrong length.
+    ///
+    pub fn new(width: usize, height: usize, weights: Vec<f64>) -> Result<Self, FilterError> {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(FilterError::EvenKernel { width, height });
+        }
+        if weights.len() != width * height {
+            return Err(FilterError::KernelSize {
+                width,
+                height,
+                expected: width * height,
+                found: weights.len(),
+            });
+        }
+        Ok(Kernel {
+            width,
+            height,
+            weights,
+        })
+    }
+
+    /// The 3x3 4-neighbour Laplacian
+    pub fn laplacian() -> Self {
+        Kernel {
+            width: 3,
+            height: 3,
+            weights: vec![0.0, 1.0, 0.0, 1.0, -4.0, 1.0, 0.0, 1.0, 0.0],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+}
+
+/// Normalized 1D Gaussian kernel extending to three standard deviations.
+///
+/// # Arguments
+/// `sigma` - The standard deviation in pixels.
+///
+/// # Returns
+/// The `2 * ceil(3 * sigma) + 1` weights, or an error if `sigma` is not positive.
+///
+pub fn gaussian_kernel(sigma: f64) -> Result<Vec<f64>, FilterError> {
+    if !(sigma > 0.0 && sigma.is_finite()) {
+        return Err(FilterError::InvalidParameter(format!(
+            "Gaussian sigma must be positive, found {}",
+            sigma
+        )));
+    }
+    let radius = (3.0 * sigma).ceil() as i64;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    Ok(weights.into_iter().map(|w| w / total).collect())
+}
+
+/// Frame of the pixel type from floating-point values, rounded and clamped
+fn to_frame<T: MonoPixel>(width: u32, height: u32, values: &[f64]) -> MonoFrameData<T> {
+    MonoFrameData {
+        width,
+        height,
+        data: values.iter().map(|v| Gray::new(T::from_f64(*v))).collect(),
+    }
+}
+
+/// Floating-point frame from values, keeping negative and fractional results
+fn to_float_frame(width: u32, height: u32, values: Vec<f64>) -> MonoFrameData<f32> {
+    MonoFrameData {
+        width,
+        height,
+        data: values.into_iter().map(|v| Gray::new(v as f32)).collect(),
+    }
+}
+
+/// Values extended by `px` columns on each side and `py` rows above and below
+fn pad(values: &[f64], w: usize, h: usize, px: usize, py: usize, border: BorderMode) -> Vec<f64> {
+    let pw = w + 2 * px;
+    let mut padded = vec![border.constant(); pw * (h + 2 * py)];
+    for y in 0..h + 2 * py {
+        let Some(sy) = border.index(y as i64 - py as i64, h) else {
+            continue;
+        };
+        for x in 0..pw {
+            if let Some(sx) = border.index(x as i64 - px as i64, w) {
+                padded[y * pw + x] = values[sy * w + sx];
+            }
+        }
+    }
+    padded
+}
+
+/// Correlate values with an odd-sized kernel
+fn correlate(
+    values: &[f64],
+    w: usize,
+    h: usize,
+    kernel: &[f64],
+    kw: usize,
+    kh: usize,
+    border: BorderMode,
+) -> Vec<f64> {
+    let (px, py) = (kw / 2, kh / 2);
+    let padded = pad(values, w, h, px, py, border);
+    let pw = w + 2 * px;
+    let mut out = vec![0.0; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = 0.0;
+            for (ky, row) in kernel.chunks_exact(kw).enumerate() {
+                let start = (y + ky) * pw + x;
+                sum += row
+                    .iter()
+                    .zip(&padded[start..start + kw])
+                    .map(|(k, v)| k * v)
+                    .sum::<f64>();
+            }
+            out[y * w + x] = sum;
+        }
+    }
+    out
+}
+
+/// Correlate values along rows and then along columns
+fn correlate_separable(
+    values: &[f64],
+    w: usize,
+    h: usize,
+    row: &[f64],
+    column: &[f64],
+    border: BorderMode,
+) -> Vec<f64> {
+    let rows = correlate(values, w, h, row, row.len(), 1, border);
+    correlate(&rows, w, h, column, 1, column.len(), border)
+}
+
+fn check_odd(width: usize, height: usize) -> Result<(), FilterError> {
+    if width.is_multiple_of(2) || height.is_multiple_of(2) {
+        return Err(FilterError::EvenKernel { width, height });
+    }
+    Ok(())
+}
+
+/// Convolve a frame with a 2D kernel.
+///
+/// # Arguments
+/// `frame` - The frame.
+/// `kernel` - The kernel.
+/// `border` - How pixels beyond the edges are supplied.
+///
+/// # Returns
+/// The filtered frame.
+///
+pub fn convolve<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    kernel: &Kernel,
+    border: BorderMode,
+) -> MonoFrameData<f32> {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let filtered = correlate(
+        &frame.to_f64_values(),
+        w,
+        h,
+        &kernel.weights,
+        kernel.width,
+        kernel.height,
+        border,
+    );
+    to_float_frame(frame.width, frame.height, filtered)
+}
+
+/// Convolve a frame with a separable kernel: a row kernel followed by a column kernel.
+///
+/// # Arguments
+/// `frame` - The frame.
+/// `row` - The kernel applied along rows, of odd length.
+/// `column` - The kernel applied along columns, of odd length.
+/// `border` - How pixels beyond the edges are supplied.
+///
+/// # Returns
+/// The filtered frame, or an error if a kernel has even length.
+///
+pub fn convolve_separable<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    row: &[f64],
+    column: &[f64],
+    border: BorderMode,
+) -> Result<MonoFrameData<f32>, FilterError> {
+    check_odd(row.len(), column.len())?;
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let filtered = correlate_separable(&frame.to_f64_values(), w, h, row, column, border);
+    Ok(to_float_frame(frame.width, frame.height, filtered))
+}
+
+/// Blur a frame with a Gaussian.
+///
+/// # Arguments
+/// `frame` - The frame.
+/// `sigma` - The standard deviation in pixels.
+/// `border` - How pixels beyond the edges are supplied.
+///
+/// # Returns
+/// The blurred frame, or an error if `sigma` is not positive.
+///
+pub fn gaussian_blur<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    sigma: f64,
+    border: BorderMode,
+) -> Result<MonoFrameData<T>, FilterError> {
+    let kernel = gaussian_kernel(sigma)?;
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let blurred = correlate_separable(&frame.to_f64_values(), w, h, &kernel, &kernel, border);
+    Ok(to_frame(frame.width, frame.height, &blurred))
+}
+
+/// Replace each pixel by the mean of a rectangle centred on it.
+///
+/// # Arguments
+/// `frame` - The frame.
+/// `width` - Width of the rectangle, odd.
+/// `height` - Height of the rectangle, odd.
+/// `border` - How pixels beyond the edges are supplied.
+///
+/// # Returns
+/// The blurred frame, or an error if a dimension is even.
+///
+pub fn box_blur<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    width: usize,
+    height: usize,
+    border: BorderMode,
+) -> Result<MonoFrameData<T>, FilterError> {
+    check_odd(width, height)?;
+    let row = vec![1.0 / width as f64; width];
+    let column = vec![1.0 / height as f64; height];
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let blurred = correlate_separable(&frame.to_f64_values(), w, h, &row, &column, border);
+    Ok(to_frame(frame.width, frame.height, &blurred))
+}
+
+/// Sharpen a frame by adding the difference between it and a Gaussian blur of it.
+///
+/// # Arguments
+/// `frame` - The frame.
+/// `sigma` - Standard deviation of the blur in pixels, setting the scale of the
+///   detail enhanced.
+/// `amount` - Multiple of the difference added; 1 doubles the contrast of detail.
+/// `border` - How pixels beyond the edges are supplied.
+///
+/// # Returns
+/// The sharpened frame, or an error if `sigma` is not positive.
+///
+pub fn unsharp_mask<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    sigma: f64,
+    amount: f64,
+    border: BorderMode,
+) -> Result<MonoFrameData<T>, FilterError> {
+    let kernel = gaussian_kernel(sigma)?;
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let v = frame.to_f64_values();
+    let blurred = correlate_separable(&v, w, h, &kernel, &kernel, border);
+    let sharpened: Vec<f64> = v
+        .iter()
+        .zip(blurred.iter())
+        .map(|(v, b)| v + amount * (v - b))
+        .collect();
+    Ok(to_frame(frame.width, frame.height, &sharpened))
+}
+
+/// Operator estimating image derivatives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GradientOperator {
+    /// 3x3 Sobel: central difference smoothed by `[1, 2, 1]`
+    #[default]
+    Sobel,
+    /// 3x3 Scharr: central difference smoothed by `[3, 10, 3]`, more rotationally
+    /// symmetric than Sobel
+    Scharr,
+}
+
+/// Horizontal and vertical derivatives of a frame
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    /// Derivative along rows, increasing to the right
+    pub gx: MonoFrameData<f32>,
+    /// Derivative along columns, increasing downwards
+    pub gy: MonoFrameData<f32>,
+}
+
+impl Gradient {
+    /// Combine the derivatives of each pixel
+    fn combine(&self, op: impl Fn(f32, f32) -> f32) -> MonoFrameData<f32> {
+        MonoFrameData {
+            width: self.gx.width,
+            height: self.gx.height,
+            data: self
+                .gx
+                .data
+                .iter()
+                .zip(self.gy.data.iter())
+                .map(|(x, y)| Gray::new(op(x.value(), y.value())))
+                .collect(),
+        }
+    }
+
+    /// Gradient magnitude of each pixel
+    pub fn magnitude(&self) -> MonoFrameData<f32> {
+        self.combine(|x, y| x.hypot(y))
+    }
+
+    /// Gradient direction of each pixel, radians from the x axis towards the y axis
+    pub fn direction(&self) -> MonoFrameData<f32> {
+        self.combine(|x, y| y.atan2(x))
+    }
+}
+
+/// Derivatives of a frame by the Sobel or Scharr operator, unnormalized.
+///
+/// # Arguments
+/// `frame` - The frame.
+/// `operator` - The derivative operator.
+/// `border` - How pixels beyond the edges are supplied.
+///
+/// # Returns
+/// The derivatives.
+///
+pub fn gradient<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    operator: GradientOperator,
+    border: BorderMode,
+) -> Gradient {
+    let smooth: [f64; 3] = match operator {
+        GradientOperator::Sobel => [1.0, 2.0, 1.0],
+        GradientOperator::Scharr => [3.0, 10.0, 3.0],
+    };
+    let diff = [-1.0, 0.0, 1.0];
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let v = frame.to_f64_values();
+    let (fw, fh) = (frame.width, frame.height);
+    Gradient {
+        gx: to_float_frame(
+            fw,
+            fh,
+            correlate_separable(&v, w, h, &diff, &smooth, border),
+        ),
+        gy: to_float_frame(
+            fw,
+            fh,
+            correlate_separable(&v, w, h, &smooth, &diff, border),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(w: u32, h: u32, f: impl Fn(u32, u32) -> u16) -> MonoFrameData<u16> {
+        MonoFrameData {
+            width: w,
+            height: h,
+            data: (0..w * h).map(|i| Gray::new(f(i % w, i / w))).collect(),
+        }
+    }
+
+    #[test]
+    fn test_border_modes() {
+        let idx = |mode: BorderMode| -> Vec<Option<usize>> {
+            (-3..7).map(|i| mode.index(i, 4)).collect()
+        };
+        let some = |v: &[usize]| v.iter().map(|i| Some(*i)).collect::<Vec<_>>();
+        assert_eq!(
+            idx(BorderMode::Reflect),
+            some(&[3, 2, 1, 0, 1, 2, 3, 2, 1, 0])
+        );
+        assert_eq!(
+            idx(BorderMode::Replicate),
+            some(&[0, 0, 0, 0, 1, 2, 3, 3, 3, 3])
+        );
+        assert_eq!(idx(BorderMode::Wrap), some(&[1, 2, 3, 0, 1, 2, 3, 0, 1, 2]));
+        assert_eq!(BorderMode::Constant(5.0).index(-1, 4), None);
+        assert_eq!(BorderMode::Reflect.index(-2, 1), Some(0));
+    }
+
+    #[test]
+    fn test_convolve() {
+        let f = frame(5, 4, |x, y| (x + 10 * y) as u16);
+        // The identity kernel copies the frame
+        let identity =
+            Kernel::new(3, 3, vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]).unwrap();
+        let same = convolve(&f, &identity, BorderMode::Constant(0.0));
+        assert_eq!((same.width, same.height), (5, 4));
+        assert!(same
+            .data
+            .iter()
+            .zip(f.data.iter())
+            .all(|(a, b)| a.value() == b.value() as f32));
+
+        // A shift kernel samples the pixel to the right, with the border beyond the edge
+        let right = Kernel::new(3, 1, vec![0.0, 0.0, 1.0]).unwrap();
+        let shifted = convolve(&f, &right, BorderMode::Constant(-1.0));
+        assert_eq!(shifted.data[..5], [1.0, 2.0, 3.0, 4.0, -1.0].map(Gray::new));
+        let wrapped = convolve(&f, &right, BorderMode::Wrap);
+        assert_eq!(wrapped.at(4, 0).value(), 0.0);
+
+        // The Laplacian of a linear ramp vanishes in the interior
+        let lap = convolve(&f, &Kernel::laplacian(), BorderMode::Replicate);
+        assert_eq!(lap.at(1, 1).value(), 0.0);
+
+        // Separable and general convolution agree
+        let g = gaussian_kernel(0.8).unwrap();
+        let outer: Vec<f64> = g
+            .iter()
+            .flat_map(|a| g.iter().map(move |b| a * b))
+            .collect();
+        let general = convolve(
+            &f,
+            &Kernel::new(g.len(), g.len(), outer).unwrap(),
+            BorderMode::Reflect,
+        );
+        let separable = convolve_separable(&f, &g, &g, BorderMode::Reflect).unwrap();
+        assert!(general
+            .data
+            .iter()
+            .zip(separable.data.iter())
+            .all(|(a, b)| (a.value() - b.value()).abs() < 1e-3));
+
+        assert!(matches!(
+            Kernel::new(2, 3, vec![0.0; 6]),
+            Err(FilterError::EvenKernel { .. })
+        ));
+        assert!(matches!(
+            Kernel::new(3, 3, vec![0.0; 6]),
+            Err(FilterError::KernelSize { .. })
+        ));
+        assert!(convolve_separable(&f, &[1.0, 1.0], &[1.0], BorderMode::Reflect).is_err());
+    }
+
+    #[test]
+    fn test_smoothing_filters() {
+        // A single bright pixel spreads into a normalized Gaussian
+        let point = frame(21, 21, |x, y| if (x, y) == (10, 10) { 10000 } else { 0 });
+        let blurred = gaussian_blur(&point, 2.0, BorderMode::Reflect).unwrap();
+        let total: u32 = blurred.data.iter().map(|p| p.value() as u32).sum();
+        assert!((total as i32 - 10000).abs() < 50);
+        assert!(blurred.at(10, 10).value() > blurred.at(12, 10).value());
+        assert_eq!(blurred.at(12, 10).value(), blurred.at(10, 8).value());
+        assert!(gaussian_blur(&point, 0.0, BorderMode::Reflect).is_err());
+
+        let boxed = box_blur(&point, 3, 5, BorderMode::Constant(0.0)).unwrap();
+        assert_eq!(boxed.at(11, 12).value(), 667);
+        assert_eq!(boxed.at(12, 10).value(), 0);
+        assert!(box_blur(&point, 4, 3, BorderMode::Reflect).is_err());
+
+        // Unsharp masking raises an edge's overshoot on the bright side
+        let edge = frame(20, 5, |x, _| if x < 10 { 1000 } else { 2000 });
+        let sharp = unsharp_mask(&edge, 1.5, 1.0, BorderMode::Replicate).unwrap();
+        assert!(sharp.at(10, 2).value() > 2000);
+        assert!(sharp.at(9, 2).value() < 1000);
+        assert_eq!(sharp.at(0, 2).value(), 1000);
+    }
+
+    #[test]
+    fn test_gradient() {
+        let ramp = frame(6, 5, |x, y| (3 * x + 2 * y) as u16);
+        let sobel = gradient(&ramp, GradientOperator::Sobel, BorderMode::Reflect);
+        // Interior: central difference of 2 * slope, times the smoothing weight of 4
+        let at = |f: &MonoFrameData<f32>| f.at(2, 2).value();
+        assert_eq!((at(&sobel.gx), at(&sobel.gy)), (24.0, 16.0));
+        let scharr = gradient(&ramp, GradientOperator::Scharr, BorderMode::Reflect);
+        assert_eq!((at(&scharr.gx), at(&scharr.gy)), (96.0, 64.0));
+        assert!((at(&sobel.magnitude()) - 24f32.hypot(16.0)).abs() < 1e-4);
+        assert!((at(&sobel.direction()) - 16f32.atan2(24.0)).abs() < 1e-6);
+        // Reflection mirrors the ramp, so the derivative vanishes at the edges
+        assert_eq!(sobel.gx.at(0, 1).value(), 0.0);
+    }
+}
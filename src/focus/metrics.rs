@@ -0,0 +1,172 @@
+//!
+//! Sharpness metrics of monochrome frames.
+//!
+
+use crate::filter::{convolve, gradient, BorderMode, GradientOperator, Kernel};
+use crate::sources::{extract_sources, ExtractOptions};
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+/// Measure of how well a frame is focused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FocusMetric {
+    /// Median half-flux radius of the detected stars, in pixels; lower is better
+    #[default]
+    HalfFluxRadius,
+    /// Variance of the 4-neighbour Laplacian; higher is better
+    LaplacianVariance,
+    /// Mean squared difference of pixels two columns apart; higher is better
+    Brenner,
+    /// Mean squared Sobel gradient magnitude; higher is better
+    Tenengrad,
+}
+
+impl FocusMetric {
+    /// Evaluate the metric over a frame.
+    ///
+    /// # Arguments
+    /// `frame` - The frame, or a region of interest cut from it.
+    ///
+    /// # Returns
+    /// The metric value, or `None` if it cannot be measured, e.g. no stars were found.
+    ///
+    pub fn evaluate<T: MonoPixel>(&self, frame: &MonoFrameData<T>) -> Option<f64> {
+        match self {
+            FocusMetric::HalfFluxRadius => half_flux_radius(frame),
+            FocusMetric::LaplacianVariance => laplacian_variance(frame),
+            FocusMetric::Brenner => brenner(frame),
+            FocusMetric::Tenengrad => tenengrad(frame),
+        }
+    }
+
+    /// Whether lower values of the metric mean better focus
+    pub fn lower_is_better(&self) -> bool {
+        matches!(self, FocusMetric::HalfFluxRadius)
+    }
+}
+
+/// Median half-flux radius of the unsaturated stars in a frame.
+///
+/// Each star's flux is summed in an aperture around its centroid of twice the radius
+/// of a disc with the area of its detected pixels, and the radius enclosing half of
+/// that flux is interpolated between pixels.
+///
+/// # Arguments
+/// `frame` - The frame.
+///
+/// # Returns
+/// The median half-flux radius in pixels, or `None` if no stars were found.
+///
+pub fn half_flux_radius<T: MonoPixel>(frame: &MonoFrameData<T>) -> Option<f64> {
+    let (bg, rms) = crate::sources::estimate_background(frame);
+    let options = ExtractOptions {
+        background: Some((bg, rms)),
+        ..Default::default()
+    };
+    let sources = extract_sources(frame, &options);
+    let values = frame.to_f64_values();
+    let (w, h) = (frame.width as i64, frame.height as i64);
+    let mut radii: Vec<f64> = sources
+        .iter()
+        .filter(|s| !s.saturated)
+        .filter_map(|s| {
+            let aperture = (2.0 * (s.npix as f64 / std::f64::consts::PI).sqrt()).max(3.0);
+            let mut ring: Vec<(f64, f64)> = Vec::new();
+            let r = aperture.ceil() as i64;
+            let (cx, cy) = (s.x.round() as i64, s.y.round() as i64);
+            for y in (cy - r).max(0)..(cy + r + 1).min(h) {
+                for x in (cx - r).max(0)..(cx + r + 1).min(w) {
+                    let d = ((x as f64 - s.x).powi(2) + (y as f64 - s.y).powi(2)).sqrt();
+                    if d <= aperture {
+                        ring.push((d, (values[(y * w + x) as usize] - bg).max(0.0)));
+                    }
+                }
+            }
+            ring.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let total: f64 = ring.iter().map(|(_, f)| f).sum();
+            if total <= 0.0 {
+                return None;
+            }
+            let mut enclosed = 0.0;
+            let mut prev_r = 0.0;
+            for (d, f) in ring {
+                if enclosed + f >= total / 2.0 {
+                    let frac = (total / 2.0 - enclosed) / f;
+                    return Some(prev_r + frac * (d - prev_r));
+                }
+                enclosed += f;
+                prev_r = d;
+            }
+            None
+        })
+        .collect();
+    if radii.is_empty() {
+        return None;
+    }
+    radii.sort_by(f64::total_cmp);
+    Some(radii[radii.len() / 2])
+}
+
+/// Variance of the 4-neighbour Laplacian over the interior of a frame.
+///
+/// # Arguments
+/// `frame` - The frame.
+///
+/// # Returns
+/// The variance, or `None` if the frame is smaller than 3x3.
+///
+pub fn laplacian_variance<T: MonoPixel>(frame: &MonoFrameData<T>) -> Option<f64> {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    if w < 3 || h < 3 {
+        return None;
+    }
+    let full = convolve(frame, &Kernel::laplacian(), BorderMode::Replicate);
+    let lap: Vec<f64> = (1..h - 1)
+        .flat_map(|y| (1..w - 1).map(move |x| y * w + x))
+        .map(|i| full.data[i].value() as f64)
+        .collect();
+    let mean = lap.iter().sum::<f64>() / lap.len() as f64;
+    Some(lap.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / lap.len() as f64)
+}
+
+/// Brenner gradient: mean squared difference of pixels two columns apart.
+///
+/// # Arguments
+/// `frame` - The frame.
+///
+/// # Returns
+/// The gradient, or `None` if the frame is narrower than 3 pixels.
+///
+pub fn brenner<T: MonoPixel>(frame: &MonoFrameData<T>) -> Option<f64> {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    if w < 3 || h == 0 {
+        return None;
+    }
+    let v = frame.to_f64_values();
+    let sum: f64 = (0..h)
+        .flat_map(|y| (0..w - 2).map(move |x| y * w + x))
+        .map(|i| (v[i + 2] - v[i]).powi(2))
+        .sum();
+    Some(sum / (h * (w - 2)) as f64)
+}
+
+/// Tenengrad: mean squared magnitude of the Sobel gradient over the interior.
+///
+/// # Arguments
+/// `frame` - The frame.
+///
+/// # Returns
+/// The mean squared gradient, or `None` if the frame is smaller than 3x3.
+///
+pub fn tenengrad<T: MonoPixel>(frame: &MonoFrameData<T>) -> Option<f64> {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    if w < 3 || h < 3 {
+        return None;
+    }
+    let g = gradient(frame, GradientOperator::Sobel, BorderMode::Replicate);
+    let sum: f64 = (1..h - 1)
+        .flat_map(|y| (1..w - 1).map(move |x| y * w + x))
+        .map(|i| (g.gx.data[i].value() as f64).powi(2) + (g.gy.data[i].value() as f64).powi(2))
+        .sum();
+    Some(sum / ((w - 2) * (h - 2)) as f64)
+}
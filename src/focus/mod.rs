@@ -0,0 +1,434 @@
+//!
+//! Focus metrics and automatic focusing.
+//!
+//! [`autofocus`] steps a [`Focuser`] through a range of positions, grabs a frame from
+//! a running [`Camera`] at each, and evaluates a [`FocusMetric`].  The best position
+//! is found by fitting the samples around the best one: two lines meeting in a V for
+//! the half-flux radius, which grows linearly with defocus, and a parabola for the
+//! contrast metrics, which peak smoothly.
+//!
+
+use crate::Camera;
+use crate::CameraError;
+use crate::CameraFrameType;
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+mod metrics;
+
+pub use metrics::{brenner, half_flux_radius, laplacian_variance, tenengrad, FocusMetric};
+
+/// A motorized focuser
+pub trait Focuser {
+    /// Start moving to an absolute position, in steps
+    fn move_absolute(&mut self, position: i32) -> Result<(), CameraError>;
+
+    /// Current position, in steps
+    fn position(&self) -> Result<i32, CameraError>;
+
+    /// Whether the focuser is still moving
+    fn is_moving(&self) -> Result<bool, CameraError> {
+        Ok(false)
+    }
+}
+
+/// Errors during automatic focusing
+#[derive(Debug, thiserror::Error)]
+pub enum FocusError {
+    #[error("Camera error: {0}")]
+    Camera(#[from] CameraError),
+    #[error("Timed out waiting for the focuser or a frame")]
+    Timeout,
+    #[error("Frame type not supported for focusing")]
+    UnsupportedFrame,
+    #[error("Region of interest {0:?} is outside the frame")]
+    InvalidRoi((u32, u32, u32, u32)),
+    #[error("Focus metric could not be measured at enough positions")]
+    NoSamples,
+}
+
+/// Options for automatic focusing
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutofocusOptions {
+    /// First position of the sweep
+    pub start: i32,
+    /// Last position of the sweep
+    pub end: i32,
+    /// Number of positions sampled, including both ends
+    pub steps: u32,
+    pub metric: FocusMetric,
+    /// Region evaluated, as top-left and bottom-right corners (x0, y0, x1, y1), or
+    /// the whole frame
+    pub roi: Option<(u32, u32, u32, u32)>,
+    /// Frames discarded after each move before the one evaluated
+    pub settle_frames: u32,
+    /// Longest wait for the focuser to stop or for a frame
+    pub timeout: Duration,
+    /// Steps to overshoot below the best position before the final move, so it is
+    /// approached from the same direction as the sweep
+    pub backlash: i32,
+}
+
+impl Default for AutofocusOptions {
+    fn default() -> Self {
+        AutofocusOptions {
+            start: 0,
+            end: 1000,
+            steps: 11,
+            metric: FocusMetric::default(),
+            roi: None,
+            settle_frames: 1,
+            timeout: Duration::from_secs(30),
+            backlash: 0,
+        }
+    }
+}
+
+/// Outcome of automatic focusing
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutofocusResult {
+    /// Position the focuser was moved to
+    pub best_position: i32,
+    /// Metric value at each position where it could be measured
+    pub samples: Vec<(i32, f64)>,
+    /// Whether the best position was fitted, rather than the best sample
+    pub fitted: bool,
+}
+
+/// Least-squares line through points, as (intercept, slope)
+fn fit_line(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let (sx, sy) = points
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+    let (mx, my) = (sx / n, sy / n);
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mx).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|(x, y)| (x - mx) * (y - my)).sum();
+    if points.len() < 2 || sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some((my - slope * mx, slope))
+}
+
+/// Position of the vertex of the least-squares parabola through points, if it
+/// opens in the given direction
+fn fit_parabola(points: &[(f64, f64)], opens_up: bool) -> Option<f64> {
+    if points.len() < 3 {
+        return None;
+    }
+    // Centre x for conditioning, then solve the 3x3 normal equations
+    let mx = points.iter().map(|(x, _)| x).sum::<f64>() / points.len() as f64;
+    let mut s = [0.0; 5];
+    let mut t = [0.0; 3];
+    for (x, y) in points {
+        let x = x - mx;
+        let mut xp = 1.0;
+        for (k, sk) in s.iter_mut().enumerate() {
+            *sk += xp;
+            if k < 3 {
+                t[k] += xp * y;
+            }
+            xp *= x;
+        }
+    }
+    let m = [[s[4], s[3], s[2]], [s[3], s[2], s[1]], [s[2], s[1], s[0]]];
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(m);
+    if d.abs() < f64::EPSILON {
+        return None;
+    }
+    // Cramer's rule for the quadratic and linear coefficients
+    let column = |c: usize| {
+        let mut mc = m;
+        for (row, tk) in mc.iter_mut().zip([t[2], t[1], t[0]]) {
+            row[c] = tk;
+        }
+        det(mc) / d
+    };
+    let (a, b) = (column(0), column(1));
+    if (opens_up && a <= 0.0) || (!opens_up && a >= 0.0) {
+        return None;
+    }
+    Some(mx - b / (2.0 * a))
+}
+
+/// Best position from samples sorted by position.
+///
+/// # Arguments
+/// `samples` - Metric value at each position, sorted by position.
+/// `metric` - The metric sampled.
+///
+/// # Returns
+/// The best position and whether it was fitted, or `None` if there are no samples.
+/// A fit whose vertex falls outside the sampled range is discarded in favour of the
+/// best sample.
+///
+pub fn best_focus(samples: &[(i32, f64)], metric: FocusMetric) -> Option<(f64, bool)> {
+    let lower = metric.lower_is_better();
+    let (ibest, best) = samples.iter().enumerate().min_by(|a, b| {
+        let ord = a.1 .1.total_cmp(&b.1 .1);
+        if lower {
+            ord
+        } else {
+            ord.reverse()
+        }
+    })?;
+    let points: Vec<(f64, f64)> = samples.iter().map(|(p, v)| (*p as f64, *v)).collect();
+    let fitted = if lower {
+        // Intersect lines through each arm of the V.  Far from focus faint stars
+        // fade into the noise and the metric can turn over, so each arm keeps only
+        // the samples that get steadily worse away from the best one.  The best
+        // sample is left out where the arms have enough points without it, as it
+        // may lie on either arm or on the flattened bottom of the curve.
+        let arm = |indices: &mut dyn Iterator<Item = usize>| {
+            let mut last = best.1;
+            let mut arm: Vec<(f64, f64)> = Vec::new();
+            for (p, v) in indices.map(|i| points[i]) {
+                if v >= last {
+                    arm.push((p, v));
+                    last = v;
+                }
+            }
+            if arm.len() < 2 {
+                arm.push(points[ibest]);
+            }
+            arm
+        };
+        let left = arm(&mut (0..ibest).rev());
+        let right = arm(&mut (ibest + 1..points.len()));
+        fit_line(&left)
+            .zip(fit_line(&right))
+            .and_then(|((c1, m1), (c2, m2))| (m1 < 0.0 && m2 > 0.0).then(|| (c2 - c1) / (m1 - m2)))
+    } else {
+        let lo = ibest.saturating_sub(2);
+        let hi = (ibest + 3).min(points.len());
+        fit_parabola(&points[lo..hi], false)
+    };
+    let (first, last) = (points[0].0, points[points.len() - 1].0);
+    Some(match fitted {
+        Some(p) if p >= first.min(last) && p <= first.max(last) => (p, true),
+        _ => (best.0 as f64, false),
+    })
+}
+
+/// Monochrome data of a frame as 16-bit values
+fn mono_data(frame: CameraFrameType) -> Result<MonoFrameData<u16>, FocusError> {
+    match frame {
+        CameraFrameType::Mono16(f) => Ok(f.data),
+        CameraFrameType::Mono8(f) => Ok(MonoFrameData::<u16>::from(&f.data)),
+        _ => Err(FocusError::UnsupportedFrame),
+    }
+}
+
+fn evaluate<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    options: &AutofocusOptions,
+) -> Result<Option<f64>, FocusError> {
+    match options.roi {
+        Some(roi @ (x0, y0, x1, y1)) => {
+            if x0 >= x1 || y0 >= y1 || x1 > frame.width || y1 > frame.height {
+                return Err(FocusError::InvalidRoi(roi));
+            }
+            Ok(options.metric.evaluate(&frame.subregion(x0, y0, x1, y1)))
+        }
+        None => Ok(options.metric.evaluate(frame)),
+    }
+}
+
+/// Move a focuser and wait until it stops
+fn move_and_wait<F: Focuser + ?Sized>(
+    focuser: &mut F,
+    position: i32,
+    timeout: Duration,
+) -> Result<(), FocusError> {
+    focuser.move_absolute(position)?;
+    let deadline = Instant::now() + timeout;
+    while focuser.is_moving()? {
+        if Instant::now() > deadline {
+            return Err(FocusError::Timeout);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+/// Focus a camera automatically.
+///
+/// The camera's frame callback is replaced and the camera is started for the sweep
+/// and stopped afterwards.
+///
+/// # Arguments
+/// `camera` - The camera, connected and configured.
+/// `focuser` - The focuser moving the camera's focus.
+/// `options` - The sweep and the metric.
+///
+/// # Returns
+/// The best position, which the focuser has been moved to, and the samples, or an
+/// error if the camera or focuser failed or no position could be measured.
+///
+pub fn autofocus<C, F>(
+    camera: &mut C,
+    focuser: &mut F,
+    options: &AutofocusOptions,
+) -> Result<AutofocusResult, FocusError>
+where
+    C: Camera + ?Sized,
+    F: Focuser + ?Sized,
+{
+    let (tx, rx) = mpsc::sync_channel::<CameraFrameType>(4);
+    camera.set_frame_callback(Box::new(move |frame| {
+        // Drop frames while the sweep is busy rather than blocking the camera
+        let _ = tx.try_send(frame);
+        Ok(())
+    }))?;
+    camera.start()?;
+    let result = sweep(&rx, focuser, options);
+    camera.stop()?;
+    let samples = result?;
+
+    let (best, fitted) = best_focus(&samples, options.metric).ok_or(FocusError::NoSamples)?;
+    let best_position = best.round() as i32;
+    if options.backlash > 0 {
+        move_and_wait(focuser, best_position - options.backlash, options.timeout)?;
+    }
+    move_and_wait(focuser, best_position, options.timeout)?;
+    Ok(AutofocusResult {
+        best_position,
+        samples,
+        fitted,
+    })
+}
+
+fn sweep<F: Focuser + ?Sized>(
+    rx: &mpsc::Receiver<CameraFrameType>,
+    focuser: &mut F,
+    options: &AutofocusOptions,
+) -> Result<Vec<(i32, f64)>, FocusError> {
+    let steps = options.steps.max(2);
+    let mut samples = Vec::with_capacity(steps as usize);
+    for i in 0..steps {
+        let position = options.start
+            + ((options.end - options.start) as f64 * i as f64 / (steps - 1) as f64).round() as i32;
+        move_and_wait(focuser, position, options.timeout)?;
+        // Frames queued during the move were exposed at the old position
+        while rx.try_recv().is_ok() {}
+        for _ in 0..options.settle_frames {
+            rx.recv_timeout(options.timeout)
+                .map_err(|_| FocusError::Timeout)?;
+        }
+        let frame = rx
+            .recv_timeout(options.timeout)
+            .map_err(|_| FocusError::Timeout)?;
+        if let Some(value) = evaluate(&mono_data(frame)?, options)? {
+            samples.push((position, value));
+        }
+    }
+    if samples.is_empty() {
+        return Err(FocusError::NoSamples);
+    }
+    samples.sort_by_key(|(p, _)| *p);
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimCamera;
+    use crate::SimFocuser;
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn test_best_focus_fits() {
+        // V with its vertex between samples
+        let v: Vec<(i32, f64)> = (0..9)
+            .map(|i| (i * 100, 1.0 + 0.01 * ((i * 100) as f64 - 430.0).abs()))
+            .collect();
+        let (best, fitted) = best_focus(&v, FocusMetric::HalfFluxRadius).unwrap();
+        assert!(fitted && (best - 430.0).abs() < 1.0, "{}", best);
+
+        // Samples where the V turns over far from focus are left out of the fit
+        let mut turned = v.clone();
+        turned[0].1 = 2.0;
+        turned[8].1 = 3.5;
+        let (best, fitted) = best_focus(&turned, FocusMetric::HalfFluxRadius).unwrap();
+        assert!(fitted && (best - 430.0).abs() < 1.0, "{}", best);
+
+        let peak: Vec<(i32, f64)> = (0..9)
+            .map(|i| (i * 100, 100.0 - ((i * 100) as f64 - 370.0).powi(2) / 1000.0))
+            .collect();
+        let (best, fitted) = best_focus(&peak, FocusMetric::Tenengrad).unwrap();
+        assert!(fitted && (best - 370.0).abs() < 1.0, "{}", best);
+
+        // Monotonic samples have no vertex inside the sweep
+        let edge = vec![(0, 3.0), (100, 2.0), (200, 1.0)];
+        assert_eq!(
+            best_focus(&edge, FocusMetric::HalfFluxRadius),
+            Some((200.0, false))
+        );
+    }
+
+    #[test]
+    fn test_metrics_prefer_focus() {
+        let cam = Arc::new(RwLock::new(SimCamera::new(96, 96, 12)));
+        cam.write().unwrap().set_star_field(8, 7);
+        let frame = |defocus: f64| {
+            cam.write().unwrap().set_defocus(defocus);
+            match cam.read().unwrap().create_frame() {
+                CameraFrameType::Mono16(f) => f.data,
+                _ => panic!("expected a 16-bit frame"),
+            }
+        };
+        let (sharp, blurred) = (frame(0.0), frame(2.5));
+        let hfr = half_flux_radius(&sharp).unwrap();
+        // A Gaussian of sigma 1.2 has a half-flux radius of 1.41
+        assert!((hfr - 1.41).abs() < 0.2, "{}", hfr);
+        assert!(half_flux_radius(&blurred).unwrap() > 2.5);
+        for metric in [
+            FocusMetric::LaplacianVariance,
+            FocusMetric::Brenner,
+            FocusMetric::Tenengrad,
+        ] {
+            assert!(
+                metric.evaluate(&sharp).unwrap() > metric.evaluate(&blurred).unwrap(),
+                "{:?}",
+                metric
+            );
+        }
+    }
+
+    #[test]
+    fn test_autofocus_sim() {
+        let mut cam = Arc::new(RwLock::new(SimCamera::new(96, 96, 12)));
+        cam.write().unwrap().set_star_field(8, 11);
+        let mut focuser = SimFocuser::new(cam.clone(), 5230, 200.0);
+        let options = AutofocusOptions {
+            start: 4400,
+            end: 6000,
+            steps: 9,
+            timeout: Duration::from_secs(5),
+            ..Default::default()
+        };
+        let result = autofocus(&mut cam, &mut focuser, &options).unwrap();
+        assert_eq!(result.samples.len(), 9);
+        assert!(result.fitted);
+        assert!((result.best_position - 5230).abs() < 60, "{:?}", result);
+        assert_eq!(focuser.position().unwrap(), result.best_position);
+
+        let roi = AutofocusOptions {
+            roi: Some((0, 0, 200, 10)),
+            ..options
+        };
+        assert!(matches!(
+            autofocus(&mut cam, &mut focuser, &roi),
+            Err(FocusError::InvalidRoi(_))
+        ));
+    }
+}
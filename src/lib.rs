@@ -1,20 +1,51 @@
 pub mod svbony;
 
+pub mod background;
+pub mod bayer;
+pub mod calibration;
 mod camera;
 mod cameraframe;
 pub mod colormap;
+pub mod filter;
+pub mod focus;
 mod list;
 mod pixel;
+pub mod rawdump;
+pub mod registration;
 mod sim;
+pub mod sources;
+pub mod stacking;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod video;
+pub mod zarr;
 
+pub use cameraframe::load_frames_from_npz;
+pub use cameraframe::save_frames_to_npz;
+pub use cameraframe::AutoLevels;
+pub use cameraframe::BinMode;
 pub use cameraframe::CameraFrame;
 pub use cameraframe::CameraFrameRGB;
 pub use cameraframe::CameraFrameRGBA;
 pub use cameraframe::CameraFrameType;
+pub use cameraframe::FitsPixel;
+pub use cameraframe::Flip;
 pub use cameraframe::FrameData;
+pub use cameraframe::FrameDataView;
+pub use cameraframe::FrameFileError;
+pub use cameraframe::Histogram;
 pub use cameraframe::MonoCameraFrame;
 pub use cameraframe::MonoFrameData;
+pub use cameraframe::NpyPixel;
+pub use cameraframe::PngPixel;
+pub use cameraframe::PnmFormat;
+pub use cameraframe::PnmPixel;
+pub use cameraframe::ResampleError;
+pub use cameraframe::ResizeFilter;
+pub use cameraframe::Stretch;
+pub use cameraframe::TransformError;
 
+pub use pixel::FloatPixel;
 pub use pixel::MonoPixel;
 pub use pixel::Pixel;
 
@@ -23,6 +54,7 @@ pub use camera::CameraError;
 pub use camera::FrameCallback;
 
 pub use sim::SimCamera;
+pub use sim::SimFocuser;
 
 pub use list::get_available_cameras;
 pub use list::AvailableCamera;
@@ -1,20 +1,213 @@
 /// A trait for pixel types
 pub trait Pixel: Sized + Clone + Copy + std::fmt::Debug + Send + Sync + 'static + Default {}
 
-impl<T> Pixel for rgb::Gray<T> where
-    T: num_traits::PrimInt + std::fmt::Debug + std::default::Default + Send + Sync + 'static
-{
-}
+impl<T> Pixel for rgb::Gray<T> where T: GrayValue {}
 impl Pixel for rgb::RGBA8 {}
 impl Pixel for rgb::RGBA16 {}
 impl Pixel for rgb::RGB8 {}
 impl Pixel for rgb::RGB16 {}
 
-pub trait MonoPixel:
-    num_traits::PrimInt + std::fmt::Debug + std::default::Default + Send + Sync + 'static
+/// Pixel types whose components can be interpolated, as used by rotation, binning
+/// and resizing.  Implemented for the crate's own pixel types only, so `Pixel`
+/// carries no required methods for pixel types defined elsewhere.
+pub trait Interpolate: Pixel {
+    /// The components of the pixel as floating point, with unused components zero
+    fn to_components(self) -> [f64; 4];
+
+    /// A pixel from floating-point components, rounded and clamped to the range of
+    /// the component type
+    fn from_components(components: [f64; 4]) -> Self;
+
+    /// Running sum of pixels, exact for integer components of any width
+    type Sum: Copy + Default;
+
+    /// Add the pixel to a running sum
+    fn add_to(self, sum: &mut Self::Sum);
+
+    /// The pixel of the mean of a sum of `count` pixels, rounded and clamped
+    fn from_sum(sum: Self::Sum, count: u64) -> Self;
+}
+
+/// Value types of monochrome pixels: the integer types of `MonoPixel` and the
+/// floating-point types of `FloatPixel`.  Not exported, so it is implemented for
+/// the primitive types only.
+pub trait GrayValue:
+    num_traits::Num
+    + num_traits::NumCast
+    + PartialOrd
+    + Copy
+    + std::fmt::Debug
+    + std::default::Default
+    + Send
+    + Sync
+    + 'static
+{
+    /// A value from floating point, rounded and clamped for integer types
+    fn from_f64(v: f64) -> Self;
+
+    /// Running sum of values, in 128 bits for integer types
+    type Sum: Copy + Default;
+
+    /// Add the value to a running sum, saturating
+    fn add_to(self, sum: &mut Self::Sum);
+
+    /// The mean of a sum of `count` values, rounded and clamped
+    fn from_sum(sum: Self::Sum, count: u64) -> Self;
+}
+
+/// Round and clamp a floating-point value to a primitive integer type
+fn to_prim<T: num_traits::PrimInt>(v: f64) -> T {
+    let v = v.round().clamp(
+        T::min_value().to_f64().unwrap_or(0.0),
+        T::max_value().to_f64().unwrap_or(0.0),
+    );
+    T::from(v).unwrap_or(T::zero())
+}
+
+/// Integer quotient rounded half away from zero
+fn round_div<S: num_traits::PrimInt>(sum: S, count: u64) -> S {
+    let count = S::from(count.max(1)).unwrap_or(S::one());
+    let (q, r) = (sum / count, sum % count);
+    if r >= S::zero() && r + r >= count {
+        q + S::one()
+    } else if r < S::zero() && S::zero() - r - r >= count {
+        q - S::one()
+    } else {
+        q
+    }
+}
+
+impl<T> Interpolate for rgb::Gray<T>
+where
+    T: GrayValue,
 {
+    fn to_components(self) -> [f64; 4] {
+        [self.value().to_f64().unwrap_or(0.0), 0.0, 0.0, 0.0]
+    }
+
+    fn from_components(components: [f64; 4]) -> Self {
+        rgb::Gray::new(T::from_f64(components[0]))
+    }
+
+    type Sum = T::Sum;
+
+    fn add_to(self, sum: &mut T::Sum) {
+        self.value().add_to(sum)
+    }
+
+    fn from_sum(sum: T::Sum, count: u64) -> Self {
+        rgb::Gray::new(T::from_sum(sum, count))
+    }
+}
+
+macro_rules! impl_rgb_interpolate {
+    ($t:ty) => {
+        impl Interpolate for rgb::RGB<$t> {
+            fn to_components(self) -> [f64; 4] {
+                [self.r as f64, self.g as f64, self.b as f64, 0.0]
+            }
+
+            fn from_components(c: [f64; 4]) -> Self {
+                rgb::RGB::new(to_prim(c[0]), to_prim(c[1]), to_prim(c[2]))
+            }
+
+            // Sums of 8- and 16-bit components are exact in floating point
+            type Sum = [f64; 4];
+
+            fn add_to(self, sum: &mut [f64; 4]) {
+                let c = self.to_components();
+                (0..4).for_each(|i| sum[i] += c[i]);
+            }
+
+            fn from_sum(sum: [f64; 4], count: u64) -> Self {
+                Self::from_components(sum.map(|s| s / count.max(1) as f64))
+            }
+        }
+
+        impl Interpolate for rgb::RGBA<$t> {
+            fn to_components(self) -> [f64; 4] {
+                [self.r as f64, self.g as f64, self.b as f64, self.a as f64]
+            }
+
+            fn from_components(c: [f64; 4]) -> Self {
+                rgb::RGBA::new(to_prim(c[0]), to_prim(c[1]), to_prim(c[2]), to_prim(c[3]))
+            }
+
+            type Sum = [f64; 4];
+
+            fn add_to(self, sum: &mut [f64; 4]) {
+                let c = self.to_components();
+                (0..4).for_each(|i| sum[i] += c[i]);
+            }
+
+            fn from_sum(sum: [f64; 4], count: u64) -> Self {
+                Self::from_components(sum.map(|s| s / count.max(1) as f64))
+            }
+        }
+    };
 }
-impl<T> MonoPixel for T where
-    T: num_traits::PrimInt + std::fmt::Debug + std::default::Default + Send + Sync + 'static
+
+impl_rgb_interpolate!(u8);
+impl_rgb_interpolate!(u16);
+
+pub trait MonoPixel:
+    num_traits::PrimInt + GrayValue + std::fmt::Debug + std::default::Default + Send + Sync + 'static
 {
 }
+
+macro_rules! impl_mono_pixel {
+    ($sum:ty; $($t:ty),*) => {
+        $(
+            impl GrayValue for $t {
+                fn from_f64(v: f64) -> Self {
+                    to_prim(v)
+                }
+
+                type Sum = $sum;
+
+                fn add_to(self, sum: &mut $sum) {
+                    *sum = sum.saturating_add(self as $sum);
+                }
+
+                fn from_sum(sum: $sum, count: u64) -> Self {
+                    round_div(sum, count).clamp(<$t>::MIN as $sum, <$t>::MAX as $sum) as $t
+                }
+            }
+
+            impl MonoPixel for $t {}
+        )*
+    };
+}
+
+impl_mono_pixel!(u128; u8, u16, u32, u64, u128, usize);
+impl_mono_pixel!(i128; i8, i16, i32, i64, i128, isize);
+
+/// A trait for floating-point values of monochrome pixels, for frames of calibrated
+/// or intermediate values that may be fractional or negative
+pub trait FloatPixel: num_traits::Float + GrayValue {}
+
+macro_rules! impl_float_pixel {
+    ($($t:ty),*) => {
+        $(
+            impl GrayValue for $t {
+                fn from_f64(v: f64) -> Self {
+                    v as $t
+                }
+
+                type Sum = f64;
+
+                fn add_to(self, sum: &mut f64) {
+                    *sum += self as f64;
+                }
+
+                fn from_sum(sum: f64, count: u64) -> Self {
+                    (sum / count.max(1) as f64) as $t
+                }
+            }
+
+            impl FloatPixel for $t {}
+        )*
+    };
+}
+
+impl_float_pixel!(f32, f64);
@@ -0,0 +1,487 @@
+//!
+//! Raw binary frame dump for high-speed capture.
+//!
+//! Frames are appended as contiguous raw pixel data (native byte order) to a data
+//! file, with each frame starting on a 64-byte boundary.  A CSV sidecar index at
+//! `<filename>.csv` records, one line per frame, the byte offset, dimensions, pixel
+//! type, bit depth, exposure, timestamp, gain and temperature.
+//!
+//! The [`RawDumpReader`] memory-maps the data file and yields [`FrameDataView`]s
+//! that borrow directly from the mapping, without copying.
+//!
+
+use crate::CameraFrame;
+use crate::CameraFrameType;
+use crate::FrameData;
+use crate::FrameDataView;
+use crate::FrameFileError;
+use crate::Pixel;
+
+use chrono::{DateTime, Utc};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+
+/// Alignment, in bytes, of the start of each frame in the data file
+const ALIGNMENT: u64 = 64;
+
+const CSV_HEADER: &str =
+    "offset,width,height,pixel_type,bit_depth,exposure,timestamp,gain,temperature";
+
+/// Pixel type of a frame in a raw dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawPixelType {
+    Mono8,
+    Mono16,
+    RGB8,
+    RGBA8,
+}
+
+impl RawPixelType {
+    /// Number of bytes per pixel
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            RawPixelType::Mono8 => 1,
+            RawPixelType::Mono16 => 2,
+            RawPixelType::RGB8 => 3,
+            RawPixelType::RGBA8 => 4,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            RawPixelType::Mono8 => "mono8",
+            RawPixelType::Mono16 => "mono16",
+            RawPixelType::RGB8 => "rgb8",
+            RawPixelType::RGBA8 => "rgba8",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mono8" => Some(RawPixelType::Mono8),
+            "mono16" => Some(RawPixelType::Mono16),
+            "rgb8" => Some(RawPixelType::RGB8),
+            "rgba8" => Some(RawPixelType::RGBA8),
+            _ => None,
+        }
+    }
+}
+
+/// Pixel types that can be stored in a raw dump
+pub trait RawPixel: Pixel + bytemuck::Pod {
+    const PIXEL_TYPE: RawPixelType;
+}
+
+impl RawPixel for rgb::Gray<u8> {
+    const PIXEL_TYPE: RawPixelType = RawPixelType::Mono8;
+}
+
+impl RawPixel for rgb::Gray<u16> {
+    const PIXEL_TYPE: RawPixelType = RawPixelType::Mono16;
+}
+
+impl RawPixel for rgb::RGB8 {
+    const PIXEL_TYPE: RawPixelType = RawPixelType::RGB8;
+}
+
+impl RawPixel for rgb::RGBA8 {
+    const PIXEL_TYPE: RawPixelType = RawPixelType::RGBA8;
+}
+
+/// Index entry describing one frame in a raw dump
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawDumpEntry {
+    /// Byte offset of the frame in the data file
+    pub offset: u64,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_type: RawPixelType,
+    pub bit_depth: u8,
+    pub exposure: f64,
+    /// Center of integration
+    pub timestamp: DateTime<Utc>,
+    pub gain: Option<f64>,
+    pub temperature: Option<f64>,
+}
+
+impl RawDumpEntry {
+    /// Size of the frame data in bytes
+    pub fn size(&self) -> usize {
+        self.width as usize * self.height as usize * self.pixel_type.bytes_per_pixel()
+    }
+
+    fn to_csv(&self) -> String {
+        let opt = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.offset,
+            self.width,
+            self.height,
+            self.pixel_type.name(),
+            self.bit_depth,
+            self.exposure,
+            self.timestamp
+                .to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            opt(self.gain),
+            opt(self.temperature),
+        )
+    }
+
+    fn from_csv(line: &str) -> Result<Self, FrameFileError> {
+        let bad = || FrameFileError::Format(format!("invalid raw dump index line: {}", line));
+        let fields: Vec<&str> = line.trim_end().split(',').collect();
+        if fields.len() != 9 {
+            return Err(bad());
+        }
+        let opt = |s: &str| -> Result<Option<f64>, FrameFileError> {
+            match s {
+                "" => Ok(None),
+                s => s.parse().map(Some).map_err(|_| bad()),
+            }
+        };
+        Ok(RawDumpEntry {
+            offset: fields[0].parse().map_err(|_| bad())?,
+            width: fields[1].parse().map_err(|_| bad())?,
+            height: fields[2].parse().map_err(|_| bad())?,
+            pixel_type: RawPixelType::from_name(fields[3]).ok_or_else(bad)?,
+            bit_depth: fields[4].parse().map_err(|_| bad())?,
+            exposure: fields[5].parse().map_err(|_| bad())?,
+            timestamp: DateTime::parse_from_rfc3339(fields[6])
+                .map_err(|_| bad())?
+                .with_timezone(&Utc),
+            gain: opt(fields[7])?,
+            temperature: opt(fields[8])?,
+        })
+    }
+}
+
+/// Name of the CSV sidecar index for a raw dump data file
+pub fn index_filename(filename: &str) -> String {
+    format!("{}.csv", filename)
+}
+
+/// Writer appending frames to a raw dump
+pub struct RawDumpWriter {
+    data: BufWriter<File>,
+    index: BufWriter<File>,
+    offset: u64,
+}
+
+impl RawDumpWriter {
+    /// Create a new raw dump, truncating any existing data file and index.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the data file; the index is written alongside it.
+    ///
+    /// # Returns
+    /// The writer, or an error if the files could not be created.
+    ///
+    pub fn create(filename: &str) -> Result<Self, FrameFileError> {
+        let data = File::create(filename)?;
+        let mut index = BufWriter::new(File::create(index_filename(filename))?);
+        writeln!(index, "{}", CSV_HEADER)?;
+        Ok(RawDumpWriter {
+            data: BufWriter::new(data),
+            index,
+            offset: 0,
+        })
+    }
+
+    /// Open a raw dump for appending, creating it if it does not exist.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the data file; the index is written alongside it.
+    ///
+    /// # Returns
+    /// The writer, or an error if the files could not be opened.
+    ///
+    pub fn append(filename: &str) -> Result<Self, FrameFileError> {
+        let mut data = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(filename)?;
+        let offset = data.seek(SeekFrom::End(0))?;
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(index_filename(filename))?;
+        let is_new = index.metadata()?.len() == 0;
+        let mut index = BufWriter::new(index);
+        if is_new {
+            writeln!(index, "{}", CSV_HEADER)?;
+        }
+        Ok(RawDumpWriter {
+            data: BufWriter::new(data),
+            index,
+            offset,
+        })
+    }
+
+    fn write<T: RawPixel>(&mut self, frame: &CameraFrame<T>) -> Result<(), FrameFileError> {
+        let padding = self.offset.next_multiple_of(ALIGNMENT) - self.offset;
+        self.data
+            .write_all(&[0u8; ALIGNMENT as usize][..padding as usize])?;
+        self.offset += padding;
+
+        let entry = RawDumpEntry {
+            offset: self.offset,
+            width: frame.data.width,
+            height: frame.data.height,
+            pixel_type: T::PIXEL_TYPE,
+            bit_depth: frame.bit_depth,
+            exposure: frame.exposure,
+            timestamp: frame.center_of_integration,
+            gain: frame.gain,
+            temperature: frame.temperature,
+        };
+        let bytes: &[u8] = bytemuck::cast_slice(&frame.data.data);
+        self.data.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        writeln!(self.index, "{}", entry.to_csv())?;
+        Ok(())
+    }
+
+    /// Append a frame to the dump.
+    ///
+    /// # Arguments
+    /// `frame` - The frame to append.
+    ///
+    /// # Returns
+    /// An empty Result if the write was successful, or an error if the write failed.
+    ///
+    pub fn write_frame(&mut self, frame: &CameraFrameType) -> Result<(), FrameFileError> {
+        match frame {
+            CameraFrameType::Mono8(f) => self.write(f),
+            CameraFrameType::Mono16(f) => self.write(f),
+            CameraFrameType::RGB8(f) => self.write(f),
+            CameraFrameType::RGBA8(f) => self.write(f),
+        }
+    }
+
+    /// Flush buffered frame data and index entries to disk.
+    pub fn flush(&mut self) -> Result<(), FrameFileError> {
+        self.data.flush()?;
+        self.index.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for RawDumpWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Memory-mapped reader for a raw dump
+pub struct RawDumpReader {
+    mmap: Option<memmap2::Mmap>,
+    entries: Vec<RawDumpEntry>,
+}
+
+impl RawDumpReader {
+    /// Open a raw dump and its index.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the data file.
+    ///
+    /// # Returns
+    /// The reader, or an error if the files could not be read or the index does not
+    /// match the data file.
+    ///
+    pub fn open(filename: &str) -> Result<Self, FrameFileError> {
+        let index = BufReader::new(File::open(index_filename(filename))?);
+        let mut entries = Vec::new();
+        for line in index.lines().skip(1) {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(RawDumpEntry::from_csv(&line)?);
+            }
+        }
+
+        let file = File::open(filename)?;
+        let len = file.metadata()?.len();
+        let past_end = |e: &RawDumpEntry| {
+            e.offset
+                .checked_add(e.size() as u64)
+                .is_none_or(|end| end > len)
+        };
+        if let Some(e) = entries.iter().find(|e| past_end(e)) {
+            return Err(FrameFileError::Format(format!(
+                "raw dump frame at offset {} extends past end of data file",
+                e.offset
+            )));
+        }
+        // Zero-length files cannot be mapped
+        let mmap = match len {
+            0 => None,
+            // Safety: the mapping is read-only; the data file must not be truncated
+            // while the reader is alive.
+            _ => Some(unsafe { memmap2::Mmap::map(&file)? }),
+        };
+        Ok(RawDumpReader { mmap, entries })
+    }
+
+    /// Number of frames in the dump
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the dump contains no frames
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Index entries for all frames in the dump
+    pub fn entries(&self) -> &[RawDumpEntry] {
+        &self.entries
+    }
+
+    fn entry(&self, idx: usize) -> Result<&RawDumpEntry, FrameFileError> {
+        self.entries.get(idx).ok_or_else(|| {
+            FrameFileError::Format(format!(
+                "frame {} out of range for raw dump with {} frames",
+                idx,
+                self.entries.len()
+            ))
+        })
+    }
+
+    /// Borrow the data of a frame without copying.
+    ///
+    /// # Arguments
+    /// `idx` - Index of the frame in the dump.
+    ///
+    /// # Returns
+    /// A view of the frame data, or an error if the index is out of range or the
+    /// frame is not of pixel type `T`.
+    ///
+    pub fn frame<T: RawPixel>(&self, idx: usize) -> Result<FrameDataView<'_, T>, FrameFileError> {
+        let entry = self.entry(idx)?;
+        if entry.pixel_type != T::PIXEL_TYPE {
+            return Err(FrameFileError::Unsupported(format!(
+                "frame {} is {:?}, not {:?}",
+                idx,
+                entry.pixel_type,
+                T::PIXEL_TYPE
+            )));
+        }
+        let start = entry.offset as usize;
+        let bytes = match &self.mmap {
+            Some(mmap) => &mmap[start..start + entry.size()],
+            None => &[],
+        };
+        let data = bytemuck::try_cast_slice(bytes)
+            .map_err(|e| FrameFileError::Format(format!("misaligned raw dump frame: {}", e)))?;
+        Ok(FrameDataView {
+            width: entry.width,
+            height: entry.height,
+            data,
+        })
+    }
+
+    fn camera_frame<T: RawPixel>(&self, idx: usize) -> Result<CameraFrame<T>, FrameFileError> {
+        let entry = self.entry(idx)?;
+        let data: FrameData<T> = self.frame::<T>(idx)?.to_frame_data();
+        Ok(CameraFrame {
+            gain: entry.gain,
+            temperature: entry.temperature,
+            ..CameraFrame::create(entry.exposure, entry.timestamp, entry.bit_depth, data)
+        })
+    }
+
+    /// Copy a frame, with its metadata, out of the dump.
+    ///
+    /// # Arguments
+    /// `idx` - Index of the frame in the dump.
+    ///
+    /// # Returns
+    /// The frame, or an error if the index is out of range.
+    ///
+    pub fn frame_type(&self, idx: usize) -> Result<CameraFrameType, FrameFileError> {
+        Ok(match self.entry(idx)?.pixel_type {
+            RawPixelType::Mono8 => CameraFrameType::Mono8(self.camera_frame(idx)?),
+            RawPixelType::Mono16 => CameraFrameType::Mono16(self.camera_frame(idx)?),
+            RawPixelType::RGB8 => CameraFrameType::RGB8(self.camera_frame(idx)?),
+            RawPixelType::RGBA8 => CameraFrameType::RGBA8(self.camera_frame(idx)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoCameraFrame;
+    use crate::MonoFrameData;
+
+    #[test]
+    fn test_rawdump_roundtrip() {
+        let filename = std::env::temp_dir().join("camera_test_dump.raw");
+        let filename = filename.to_str().unwrap();
+
+        let mono = MonoFrameData::<u16> {
+            width: 5,
+            height: 3,
+            data: (0..15).map(|x| rgb::Gray::<u16>::new(x * 1000)).collect(),
+        };
+        let mut frame = MonoCameraFrame::<u16>::create(0.1, Utc::now(), 12, mono);
+        frame.gain = Some(20.0);
+        let rgb = FrameData::<rgb::RGB8> {
+            width: 3,
+            height: 1,
+            data: vec![rgb::RGB8::new(1, 2, 3); 3],
+        };
+        let rgbframe = CameraFrame::create(0.2, Utc::now(), 8, rgb);
+
+        let mut writer = RawDumpWriter::create(filename).unwrap();
+        writer
+            .write_frame(&CameraFrameType::RGB8(rgbframe.clone()))
+            .unwrap();
+        drop(writer);
+        let mut writer = RawDumpWriter::append(filename).unwrap();
+        writer
+            .write_frame(&CameraFrameType::Mono16(frame.clone()))
+            .unwrap();
+        drop(writer);
+
+        let reader = RawDumpReader::open(filename).unwrap();
+        assert_eq!(reader.len(), 2);
+        assert_eq!(reader.entries()[1].offset, 64);
+        let view = reader.frame::<rgb::Gray<u16>>(1).unwrap();
+        assert_eq!(view.data, &frame.data.data[..]);
+        assert_eq!(view.at(2, 1), frame.data.at(2, 1));
+        assert!(reader.frame::<rgb::Gray<u8>>(1).is_err());
+        assert!(reader.frame::<rgb::RGB8>(2).is_err());
+
+        match reader.frame_type(1).unwrap() {
+            CameraFrameType::Mono16(f) => {
+                assert_eq!(f.data.data, frame.data.data);
+                assert_eq!(f.gain, Some(20.0));
+                assert_eq!(f.bit_depth, 12);
+                assert_eq!(
+                    f.center_of_integration.timestamp_micros(),
+                    frame.center_of_integration.timestamp_micros()
+                );
+            }
+            _ => panic!("expected Mono16 frame"),
+        }
+        match reader.frame_type(0).unwrap() {
+            CameraFrameType::RGB8(f) => assert_eq!(f.data.data, rgbframe.data.data),
+            _ => panic!("expected RGB8 frame"),
+        }
+
+        drop(reader);
+
+        // A corrupt offset in the index is a format error rather than an overflow
+        let index = std::fs::read_to_string(index_filename(filename)).unwrap();
+        let corrupt = index.replacen("\n64,", &format!("\n{},", u64::MAX), 1);
+        assert_ne!(corrupt, index);
+        std::fs::write(index_filename(filename), corrupt).unwrap();
+        assert!(matches!(
+            RawDumpReader::open(filename),
+            Err(FrameFileError::Format(_))
+        ));
+
+        let _ = std::fs::remove_file(filename);
+        let _ = std::fs::remove_file(index_filename(filename));
+    }
+}
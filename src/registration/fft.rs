@@ -0,0 +1,142 @@
+//!
+//! Radix-2 fast Fourier transforms of complex data.
+//!
+
+use std::ops::{Add, Mul, Sub};
+
+/// Complex number
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub fn norm(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn scale(self, s: f64) -> Self {
+        Complex::new(self.re * s, self.im * s)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(
+            self.re * o.re - self.im * o.im,
+            self.re * o.im + self.im * o.re,
+        )
+    }
+}
+
+/// In-place transform of a power-of-two length slice; the inverse is unnormalized
+fn fft(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    debug_assert!(n.is_power_of_two());
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        let step = Complex::new(angle.cos(), angle.sin());
+        for start in (0..n).step_by(len) {
+            let mut w = Complex::new(1.0, 0.0);
+            for k in 0..len / 2 {
+                let a = data[start + k];
+                let b = data[start + k + len / 2] * w;
+                data[start + k] = a + b;
+                data[start + k + len / 2] = a - b;
+                w = w * step;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// In-place 2D transform of row-major data with power-of-two dimensions.
+///
+/// # Arguments
+/// `data` - The `width * height` values.
+/// `width` - Number of columns.
+/// `height` - Number of rows.
+/// `inverse` - Whether to compute the inverse transform, which is normalized by
+///   the number of values.
+///
+pub(crate) fn fft2d(data: &mut [Complex], width: usize, height: usize, inverse: bool) {
+    for row in data.chunks_exact_mut(width) {
+        fft(row, inverse);
+    }
+    let mut column = vec![Complex::default(); height];
+    for x in 0..width {
+        for (y, c) in column.iter_mut().enumerate() {
+            *c = data[y * width + x];
+        }
+        fft(&mut column, inverse);
+        for (y, c) in column.iter().enumerate() {
+            data[y * width + x] = *c;
+        }
+    }
+    if inverse {
+        let s = 1.0 / (width * height) as f64;
+        data.iter_mut().for_each(|c| *c = c.scale(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fft_roundtrip() {
+        let (w, h) = (8, 4);
+        let orig: Vec<Complex> = (0..w * h)
+            .map(|i| Complex::new((i * 7 % 5) as f64, (i % 3) as f64))
+            .collect();
+        let mut data = orig.clone();
+        fft2d(&mut data, w, h, false);
+        // The DC term is the sum of the values
+        let sum = orig.iter().fold(Complex::default(), |a, b| a + *b);
+        assert!((data[0] - sum).norm() < 1e-9);
+        fft2d(&mut data, w, h, true);
+        assert!(data
+            .iter()
+            .zip(orig.iter())
+            .all(|(a, b)| (*a - *b).norm() < 1e-9));
+    }
+}
@@ -0,0 +1,501 @@
+//!
+//! Translation registration of monochrome frames.
+//!
+//! [`phase_correlate`] estimates the shift between two frames from the peak of
+//! their phase correlation: the inverse transform of the normalized cross-power
+//! spectrum, computed with radix-2 FFTs after subtracting the mean, applying a Hann
+//! window and zero-padding to powers of two.  The spectrum is weighted by a
+//! Gaussian so the correlation peak is a Gaussian about one pixel wide, and the
+//! sub-pixel position of the peak is found by fitting a Gaussian through it and its
+//! neighbours along each axis.
+//!
+//! [`match_stars`] instead matches the brightest stars of the two frames, choosing
+//! the offset on which most star pairs agree and averaging the matched pairs.
+//! [`register`] uses phase correlation and falls back to star matching when the
+//! correlation peak is weak, e.g. under changing sky brightness or clouds.
+//!
+//! A shift `(dx, dy)` means the content of the frame lies `dx` columns right of
+//! and `dy` rows below the same content in the reference; [`shift_frame`] by
+//! `(-dx, -dy)`, as done by [`align_frames`], aligns the frame with the reference.
+//!
+
+mod fft;
+
+use crate::sources::{extract_sources, ExtractOptions, Source};
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+use fft::{fft2d, Complex};
+use rgb::Gray;
+
+/// Errors registering frames
+#[derive(Debug, thiserror::Error)]
+pub enum RegistrationError {
+    #[error("Frame size {found:?} does not match reference size {expected:?}")]
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    #[error("Frame of {width}x{height} is too small to register")]
+    TooSmall { width: u32, height: u32 },
+    #[error("No matching stars found")]
+    NoMatch,
+}
+
+/// How a shift was estimated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMethod {
+    PhaseCorrelation,
+    StarMatching,
+}
+
+/// Estimated translation of a frame relative to a reference
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shift {
+    /// Columns the content moved to the right
+    pub dx: f64,
+    /// Rows the content moved down
+    pub dy: f64,
+    /// Quality of the estimate from 0 to 1: the correlation peak height relative to
+    /// identical frames, or the fraction of stars matched
+    pub confidence: f64,
+    pub method: RegistrationMethod,
+}
+
+/// Options for registration
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistrationOptions {
+    /// Phase correlation confidence below which star matching is used instead
+    pub min_confidence: f64,
+    /// Number of the brightest stars of each frame used for matching
+    pub max_stars: usize,
+    /// Distance within which stars are matched, in pixels
+    pub match_tolerance: f64,
+    /// Options for extracting the stars
+    pub extract: ExtractOptions,
+}
+
+impl Default for RegistrationOptions {
+    fn default() -> Self {
+        RegistrationOptions {
+            min_confidence: 0.1,
+            max_stars: 30,
+            match_tolerance: 2.0,
+            extract: ExtractOptions::default(),
+        }
+    }
+}
+
+/// Width of the correlation peak, in pixels
+const PEAK_SIGMA: f64 = 1.0;
+
+fn check_frames<T: MonoPixel>(
+    reference: &MonoFrameData<T>,
+    frame: &MonoFrameData<T>,
+) -> Result<(), RegistrationError> {
+    let expected = (reference.width, reference.height);
+    let found = (frame.width, frame.height);
+    if expected != found {
+        return Err(RegistrationError::SizeMismatch { expected, found });
+    }
+    if found.0 < 4 || found.1 < 4 {
+        return Err(RegistrationError::TooSmall {
+            width: found.0,
+            height: found.1,
+        });
+    }
+    Ok(())
+}
+
+/// Windowed, zero-mean and zero-padded spectrum of a frame
+fn spectrum<T: MonoPixel>(frame: &MonoFrameData<T>, pw: usize, ph: usize) -> Vec<Complex> {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let values: Vec<f64> = frame.to_f64_values();
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let hann = |i: usize, n: usize| {
+        0.5 - 0.5 * (2.0 * std::f64::consts::PI * (i as f64 + 0.5) / n as f64).cos()
+    };
+    let wx: Vec<f64> = (0..w).map(|x| hann(x, w)).collect();
+    let wy: Vec<f64> = (0..h).map(|y| hann(y, h)).collect();
+    let mut data = vec![Complex::default(); pw * ph];
+    for y in 0..h {
+        for x in 0..w {
+            data[y * pw + x] = Complex::new((values[y * w + x] - mean) * wx[x] * wy[y], 0.0);
+        }
+    }
+    fft2d(&mut data, pw, ph, false);
+    data
+}
+
+/// Offset of the peak of three samples from the centre one, fitting a Gaussian if
+/// all are positive and a parabola otherwise
+fn peak_offset(left: f64, center: f64, right: f64) -> f64 {
+    let offset = if left > 0.0 && center > 0.0 && right > 0.0 {
+        let (l, c, r) = (left.ln(), center.ln(), right.ln());
+        (l - r) / (2.0 * (l - 2.0 * c + r))
+    } else {
+        (left - right) / (2.0 * (left - 2.0 * center + right))
+    };
+    if offset.is_finite() {
+        offset.clamp(-0.5, 0.5)
+    } else {
+        0.0
+    }
+}
+
+/// Estimate the shift of a frame relative to a reference by phase correlation.
+///
+/// # Arguments
+/// `reference` - The reference frame.
+/// `frame` - The frame to register, of the same size.
+///
+/// # Returns
+/// The shift, or an error if the sizes differ or the frames are smaller than 4x4.
+///
+pub fn phase_correlate<T: MonoPixel>(
+    reference: &MonoFrameData<T>,
+    frame: &MonoFrameData<T>,
+) -> Result<Shift, RegistrationError> {
+    check_frames(reference, frame)?;
+    let pw = (reference.width as usize).next_power_of_two();
+    let ph = (reference.height as usize).next_power_of_two();
+    let f = spectrum(reference, pw, ph);
+    let g = spectrum(frame, pw, ph);
+
+    // Signed frequency in cycles per pixel
+    let freq = |k: usize, n: usize| {
+        if k <= n / 2 {
+            k as f64 / n as f64
+        } else {
+            k as f64 / n as f64 - 1.0
+        }
+    };
+    let gauss = -2.0 * std::f64::consts::PI.powi(2) * PEAK_SIGMA * PEAK_SIGMA;
+    let mut total_weight = 0.0;
+    let mut cross: Vec<Complex> = f
+        .iter()
+        .zip(g.iter())
+        .enumerate()
+        .map(|(i, (f, g))| {
+            let (fx, fy) = (freq(i % pw, pw), freq(i / pw, ph));
+            let weight = (gauss * (fx * fx + fy * fy)).exp();
+            total_weight += weight;
+            let c = *g * f.conj();
+            let norm = c.norm();
+            if norm > 1e-12 {
+                c.scale(weight / norm)
+            } else {
+                Complex::default()
+            }
+        })
+        .collect();
+    fft2d(&mut cross, pw, ph, true);
+
+    let (peak, _) = cross
+        .iter()
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |best, (i, c)| {
+            if c.re > best.1 {
+                (i, c.re)
+            } else {
+                best
+            }
+        });
+    let (px, py) = (peak % pw, peak / pw);
+    let at = |x: usize, y: usize| cross[(y % ph) * pw + (x % pw)].re;
+    let center = at(px, py);
+    let sx = peak_offset(at(px + pw - 1, py), center, at(px + 1, py));
+    let sy = peak_offset(at(px, py + ph - 1), center, at(px, py + 1));
+    let signed = |p: usize, n: usize| {
+        if p > n / 2 {
+            p as f64 - n as f64
+        } else {
+            p as f64
+        }
+    };
+    // A perfect match gives a peak of the mean weight
+    let confidence = (center * (pw * ph) as f64 / total_weight).clamp(0.0, 1.0);
+    Ok(Shift {
+        dx: signed(px, pw) + sx,
+        dy: signed(py, ph) + sy,
+        confidence,
+        method: RegistrationMethod::PhaseCorrelation,
+    })
+}
+
+/// Brightest stars of a frame
+fn brightest<T: MonoPixel>(frame: &MonoFrameData<T>, options: &RegistrationOptions) -> Vec<Source> {
+    let mut stars = extract_sources(frame, &options.extract);
+    stars.truncate(options.max_stars);
+    stars
+}
+
+/// Estimate the shift of a frame relative to a reference by matching stars.
+///
+/// Every pairing of a reference star with a frame star proposes an offset; the
+/// offset that brings the most reference stars within the match tolerance of a
+/// frame star is chosen, and refined by averaging the offsets of those matches.
+///
+/// # Arguments
+/// `reference` - The reference frame.
+/// `frame` - The frame to register, of the same size.
+/// `options` - Star extraction and matching options.
+///
+/// # Returns
+/// The shift, or an error if the sizes differ or too few stars match.
+///
+pub fn match_stars<T: MonoPixel>(
+    reference: &MonoFrameData<T>,
+    frame: &MonoFrameData<T>,
+    options: &RegistrationOptions,
+) -> Result<Shift, RegistrationError> {
+    check_frames(reference, frame)?;
+    let ref_stars = brightest(reference, options);
+    let frame_stars = brightest(frame, options);
+    let available = ref_stars.len().min(frame_stars.len());
+    if available == 0 {
+        return Err(RegistrationError::NoMatch);
+    }
+    let tol2 = options.match_tolerance * options.match_tolerance;
+    let matches = |dx: f64, dy: f64| -> Vec<(f64, f64)> {
+        ref_stars
+            .iter()
+            .filter_map(|r| {
+                frame_stars
+                    .iter()
+                    .map(|f| (f.x - r.x, f.y - r.y))
+                    .map(|(ox, oy)| (ox, oy, (ox - dx).powi(2) + (oy - dy).powi(2)))
+                    .filter(|(_, _, d2)| *d2 <= tol2)
+                    .min_by(|a, b| a.2.total_cmp(&b.2))
+                    .map(|(ox, oy, _)| (ox, oy))
+            })
+            .collect()
+    };
+    let mut best: Vec<(f64, f64)> = Vec::new();
+    for r in ref_stars.iter() {
+        for f in frame_stars.iter() {
+            let m = matches(f.x - r.x, f.y - r.y);
+            if m.len() > best.len() {
+                best = m;
+            }
+        }
+    }
+    if best.len() < available.min(3) {
+        return Err(RegistrationError::NoMatch);
+    }
+    // Refine about the mean offset of the matches
+    let n = best.len() as f64;
+    let (mx, my) = best
+        .iter()
+        .fold((0.0, 0.0), |a, (x, y)| (a.0 + x / n, a.1 + y / n));
+    let refined = matches(mx, my);
+    let best = if refined.len() >= best.len() {
+        refined
+    } else {
+        best
+    };
+    let n = best.len() as f64;
+    let (dx, dy) = best
+        .iter()
+        .fold((0.0, 0.0), |a, (x, y)| (a.0 + x / n, a.1 + y / n));
+    Ok(Shift {
+        dx,
+        dy,
+        confidence: n / available as f64,
+        method: RegistrationMethod::StarMatching,
+    })
+}
+
+/// Estimate the shift of a frame relative to a reference by phase correlation,
+/// falling back to star matching if the correlation is weak.
+///
+/// # Arguments
+/// `reference` - The reference frame.
+/// `frame` - The frame to register, of the same size.
+/// `options` - Registration options.
+///
+/// # Returns
+/// The shift; the phase correlation estimate is returned if star matching fails.
+///
+pub fn register<T: MonoPixel>(
+    reference: &MonoFrameData<T>,
+    frame: &MonoFrameData<T>,
+    options: &RegistrationOptions,
+) -> Result<Shift, RegistrationError> {
+    let shift = phase_correlate(reference, frame)?;
+    if shift.confidence >= options.min_confidence {
+        return Ok(shift);
+    }
+    match match_stars(reference, frame, options) {
+        Ok(s) => Ok(s),
+        Err(RegistrationError::NoMatch) => Ok(shift),
+        Err(e) => Err(e),
+    }
+}
+
+/// Translate the content of a frame by a possibly fractional number of pixels,
+/// with bilinear interpolation.
+///
+/// Pixels that come from outside the frame take the value of the nearest edge pixel.
+///
+/// # Arguments
+/// `frame` - The frame.
+/// `dx` - Columns to move the content right.
+/// `dy` - Rows to move the content down.
+///
+/// # Returns
+/// The shifted frame, of the same size.
+///
+pub fn shift_frame<T: MonoPixel>(frame: &MonoFrameData<T>, dx: f64, dy: f64) -> MonoFrameData<T> {
+    let (w, h) = (frame.width as i64, frame.height as i64);
+    if w == 0 || h == 0 {
+        return frame.clone();
+    }
+    let at = |x: i64, y: i64| {
+        frame.data[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize]
+            .value()
+            .to_f64()
+            .unwrap_or(0.0)
+    };
+    let (ix, fx) = ((-dx).floor() as i64, -dx - (-dx).floor());
+    let (iy, fy) = ((-dy).floor() as i64, -dy - (-dy).floor());
+    let data = (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (sx, sy) = (x + ix, y + iy);
+            let top = at(sx, sy) * (1.0 - fx) + at(sx + 1, sy) * fx;
+            let bottom = at(sx, sy + 1) * (1.0 - fx) + at(sx + 1, sy + 1) * fx;
+            let v = (top * (1.0 - fy) + bottom * fy).round().clamp(
+                T::min_value().to_f64().unwrap(),
+                T::max_value().to_f64().unwrap(),
+            );
+            Gray::new(T::from(v).unwrap_or(T::zero()))
+        })
+        .collect();
+    MonoFrameData {
+        width: frame.width,
+        height: frame.height,
+        data,
+    }
+}
+
+/// Register frames against a reference and shift them into alignment with it.
+///
+/// # Arguments
+/// `reference` - The reference frame.
+/// `frames` - The frames to align.
+/// `options` - Registration options.
+///
+/// # Returns
+/// The aligned frames and their estimated shifts, in the order given.
+///
+pub fn align_frames<T: MonoPixel>(
+    reference: &MonoFrameData<T>,
+    frames: &[MonoFrameData<T>],
+    options: &RegistrationOptions,
+) -> Result<Vec<(MonoFrameData<T>, Shift)>, RegistrationError> {
+    frames
+        .iter()
+        .map(|f| {
+            let shift = register(reference, f, options)?;
+            Ok((shift_frame(f, -shift.dx, -shift.dy), shift))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Field of stars shifted by (dx, dy), with noise from the given seed
+    fn star_field(w: u32, h: u32, dx: f64, dy: f64, seed: u32) -> MonoFrameData<u16> {
+        let stars = [
+            (20.0, 30.0, 8000.0),
+            (70.3, 15.8, 5000.0),
+            (100.0, 80.0, 12000.0),
+            (45.5, 90.2, 3000.0),
+            (110.7, 40.1, 6000.0),
+            (30.2, 60.6, 9000.0),
+            (85.0, 100.0, 4000.0),
+        ]
+        .map(|(x, y, a)| (x + dx, y + dy, a, 1.5, 1.5));
+        crate::test_util::star_field(w, h, &stars, seed)
+    }
+
+    #[test]
+    fn test_phase_correlate() {
+        let reference = star_field(128, 120, 0.0, 0.0, 1);
+        for (dx, dy) in [(3.3, -5.7), (-10.5, 2.25), (0.0, 0.0)] {
+            let frame = star_field(128, 120, dx, dy, 2);
+            let shift = phase_correlate(&reference, &frame).unwrap();
+            assert_eq!(shift.method, RegistrationMethod::PhaseCorrelation);
+            assert!((shift.dx - dx).abs() < 0.15, "{:?} {}", shift, dx);
+            assert!((shift.dy - dy).abs() < 0.15, "{:?} {}", shift, dy);
+            assert!(shift.confidence > 0.3, "{:?}", shift);
+        }
+        assert!(matches!(
+            phase_correlate(&reference, &MonoFrameData::<u16>::zeros(128, 100)),
+            Err(RegistrationError::SizeMismatch { .. })
+        ));
+        let tiny = MonoFrameData::<u16>::zeros(3, 3);
+        assert!(matches!(
+            phase_correlate(&tiny, &tiny),
+            Err(RegistrationError::TooSmall { .. })
+        ));
+    }
+
+    #[test]
+    fn test_match_stars() {
+        let reference = star_field(128, 120, 0.0, 0.0, 1);
+        let frame = star_field(128, 120, -4.6, 7.3, 3);
+        let options = RegistrationOptions::default();
+        let shift = match_stars(&reference, &frame, &options).unwrap();
+        assert_eq!(shift.method, RegistrationMethod::StarMatching);
+        assert!((shift.dx + 4.6).abs() < 0.1, "{:?}", shift);
+        assert!((shift.dy - 7.3).abs() < 0.1, "{:?}", shift);
+        assert!(shift.confidence > 0.8);
+
+        // Forcing the fallback
+        let options = RegistrationOptions {
+            min_confidence: 2.0,
+            ..Default::default()
+        };
+        let shift = register(&reference, &frame, &options).unwrap();
+        assert_eq!(shift.method, RegistrationMethod::StarMatching);
+
+        // Without stars the phase correlation estimate is kept
+        let blank = MonoFrameData::<u16>::zeros(128, 120);
+        assert!(matches!(
+            match_stars(&blank, &blank, &options),
+            Err(RegistrationError::NoMatch)
+        ));
+        let shift = register(&blank, &blank, &options).unwrap();
+        assert_eq!(shift.method, RegistrationMethod::PhaseCorrelation);
+    }
+
+    #[test]
+    fn test_shift_and_align() {
+        let frame = MonoFrameData::<u16> {
+            width: 4,
+            height: 3,
+            data: (0..12).map(|v| Gray::new(v * 10)).collect(),
+        };
+        let shifted = shift_frame(&frame, 1.0, -1.0);
+        assert_eq!(shifted.at(1, 0).value(), frame.at(0, 1).value());
+        assert_eq!(shifted.at(3, 1).value(), frame.at(2, 2).value());
+        // Edges are replicated
+        assert_eq!(shifted.at(0, 2).value(), frame.at(0, 2).value());
+        let half = shift_frame(&frame, 0.5, 0.0);
+        assert_eq!(half.at(1, 0).value(), 5);
+
+        let reference = star_field(128, 120, 0.0, 0.0, 1);
+        let frames = [star_field(128, 120, 2.5, -3.5, 4)];
+        let aligned = align_frames(&reference, &frames, &RegistrationOptions::default()).unwrap();
+        let (aligned, shift) = &aligned[0];
+        assert!((shift.dx - 2.5).abs() < 0.15 && (shift.dy + 3.5).abs() < 0.15);
+        let stars = extract_sources(aligned, &ExtractOptions::default());
+        assert!(stars
+            .iter()
+            .any(|s| (s.x - 100.0).abs() < 0.2 && (s.y - 80.0).abs() < 0.2));
+    }
+}
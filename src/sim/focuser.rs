@@ -0,0 +1,54 @@
+use super::SimCamera;
+use crate::focus::Focuser;
+use crate::CameraError;
+
+use std::sync::{Arc, RwLock};
+
+/// Simulated focuser that blurs the star field of a `SimCamera` in proportion to
+/// its distance from the best focus position
+pub struct SimFocuser {
+    camera: Arc<RwLock<SimCamera>>,
+    position: i32,
+    best_position: i32,
+    steps_per_pixel: f64,
+}
+
+impl SimFocuser {
+    /// Create a focuser driving the defocus of a simulated camera.
+    ///
+    /// # Arguments
+    /// `camera` - The camera whose star field is blurred.
+    /// `best_position` - Position of perfect focus.
+    /// `steps_per_pixel` - Focuser steps per pixel of defocus blur sigma.
+    ///
+    /// # Returns
+    /// The focuser, at the best position.
+    ///
+    pub fn new(camera: Arc<RwLock<SimCamera>>, best_position: i32, steps_per_pixel: f64) -> Self {
+        let mut focuser = SimFocuser {
+            camera,
+            position: best_position,
+            best_position,
+            steps_per_pixel,
+        };
+        focuser.update();
+        focuser
+    }
+
+    fn update(&mut self) {
+        let defocus = (self.position - self.best_position) as f64 / self.steps_per_pixel;
+        self.camera.write().unwrap().set_defocus(defocus);
+    }
+}
+
+impl Focuser for SimFocuser {
+    fn move_absolute(&mut self, position: i32) -> Result<(), CameraError> {
+        self.position = position;
+        self.update();
+        Ok(())
+    }
+
+    fn position(&self) -> Result<i32, CameraError> {
+        Ok(self.position)
+    }
+}
@@ -10,6 +10,13 @@ use crate::MonoFrameData;
 use std::sync::{Arc, RwLock};
 use std::thread;
 
+mod focuser;
+
+pub use focuser::SimFocuser;
+
+/// Gaussian sigma, in pixels, of simulated stars in perfect focus
+const SEEING_SIGMA: f64 = 1.2;
+
 pub struct SimCamera {
     exposure: f64,
     gain: f64,
@@ -20,6 +27,12 @@ pub struct SimCamera {
     callback: Option<Arc<FrameCallback>>,
     running: bool,
     handle: Option<thread::JoinHandle<()>>,
+    /// Simulated stars as (x, y, peak as a fraction of full scale in focus)
+    stars: Vec<(f64, f64, f64)>,
+    /// Seed of the star field, from which the noise of each frame is derived
+    seed: u64,
+    /// Gaussian sigma, in pixels, of the defocus blur added to the stars
+    defocus: f64,
 }
 
 impl SimCamera {
@@ -34,6 +47,85 @@ impl SimCamera {
             callback: None,
             running: false,
             handle: None,
+            stars: Vec::new(),
+            seed: 0,
+            defocus: 0.0,
+        }
+    }
+
+    /// Render a field of random stars on a dark sky instead of the default moving
+    /// blob.
+    ///
+    /// # Arguments
+    /// `count` - Number of stars; zero restores the default frames.
+    /// `seed` - Seed of the random star positions and brightnesses, and of the
+    ///          noise, so frames at the same focus are identical.
+    ///
+    pub fn set_star_field(&mut self, count: usize, seed: u64) {
+        use rand::{Rng, SeedableRng};
+        self.seed = seed;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        // Keep stars away from the edges so defocused images stay in the frame
+        let margin = |len: usize| (len as f64 * 0.1).max(1.0);
+        let (mx, my) = (margin(self.width), margin(self.height));
+        self.stars = (0..count)
+            .map(|_| {
+                (
+                    rng.random_range(mx..(self.width as f64 - mx).max(mx + 1.0)),
+                    rng.random_range(my..(self.height as f64 - my).max(my + 1.0)),
+                    rng.random_range(0.2..0.7),
+                )
+            })
+            .collect();
+    }
+
+    /// Set the defocus blur of the star field.
+    ///
+    /// # Arguments
+    /// `sigma` - Gaussian sigma of the blur in pixels, added in quadrature to the
+    ///           in-focus star size; zero is perfect focus.
+    ///
+    pub fn set_defocus(&mut self, sigma: f64) {
+        self.defocus = sigma.abs();
+    }
+
+    /// Frame of the star field: stars blurred by the defocus, conserving flux, on a
+    /// faint background with little noise
+    fn create_star_data<T>(&self) -> MonoFrameData<T>
+    where
+        T: crate::MonoPixel,
+    {
+        use rand::SeedableRng;
+        use rand_distr::Normal;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(self.seed ^ self.defocus.to_bits());
+        let maxval = ((1u64 << self.bit_depth as u32) - 1) as f64;
+        let normal = Normal::new(0.0, maxval / 1024.0).unwrap();
+        let mut values = vec![maxval / 32.0; self.width * self.height];
+        let sigma = (SEEING_SIGMA * SEEING_SIGMA + self.defocus * self.defocus).sqrt();
+        let scale = (SEEING_SIGMA / sigma).powi(2);
+        let radius = (5.0 * sigma).ceil() as i64;
+        for (sx, sy, peak) in self.stars.iter() {
+            let amplitude = peak * maxval * scale;
+            let (cx, cy) = (sx.round() as i64, sy.round() as i64);
+            for y in (cy - radius).max(0)..(cy + radius + 1).min(self.height as i64) {
+                for x in (cx - radius).max(0)..(cx + radius + 1).min(self.width as i64) {
+                    let (dx, dy) = (x as f64 - sx, y as f64 - sy);
+                    values[y as usize * self.width + x as usize] +=
+                        amplitude * (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp();
+                }
+            }
+        }
+        MonoFrameData::<T> {
+            width: self.width as u32,
+            height: self.height as u32,
+            data: values
+                .into_iter()
+                .map(|v| {
+                    let v = (v + normal.sample(&mut rng)).clamp(0.0, maxval).round();
+                    rgb::Gray::<T>::new(T::from(v).unwrap_or(T::zero()))
+                })
+                .collect(),
         }
     }
 
@@ -41,6 +133,9 @@ impl SimCamera {
     where
         T: crate::MonoPixel,
     {
+        if !self.stars.is_empty() {
+            return self.create_star_data();
+        }
         MonoFrameData::<T> {
             width: self.width as u32,
             height: self.height as u32,
@@ -75,20 +170,28 @@ impl SimCamera {
         }
     }
 
-    fn create_frame(&self) -> CameraFrameType {
+    pub(crate) fn create_frame(&self) -> CameraFrameType {
         match self.bit_depth <= 8 {
-            true => crate::CameraFrameType::Mono8(CameraFrame::<rgb::Gray<u8>>::create(
-                self.exposure,
-                chrono::Utc::now(),
-                self.bit_depth,
-                self.create_frame_data::<u8>(),
-            )),
-            false => crate::CameraFrameType::Mono16(CameraFrame::<rgb::Gray<u16>>::create(
-                self.exposure,
-                chrono::Utc::now(),
-                self.bit_depth,
-                self.create_frame_data::<u16>(),
-            )),
+            true => crate::CameraFrameType::Mono8(CameraFrame::<rgb::Gray<u8>> {
+                gain: Some(self.gain),
+                camera_name: Some("Simulated Camera".to_string()),
+                ..CameraFrame::<rgb::Gray<u8>>::create(
+                    self.exposure,
+                    chrono::Utc::now(),
+                    self.bit_depth,
+                    self.create_frame_data::<u8>(),
+                )
+            }),
+            false => crate::CameraFrameType::Mono16(CameraFrame::<rgb::Gray<u16>> {
+                gain: Some(self.gain),
+                camera_name: Some("Simulated Camera".to_string()),
+                ..CameraFrame::<rgb::Gray<u16>>::create(
+                    self.exposure,
+                    chrono::Utc::now(),
+                    self.bit_depth,
+                    self.create_frame_data::<u16>(),
+                )
+            }),
         }
     }
 
@@ -0,0 +1,327 @@
+//!
+//! Extraction of point sources (stars) from monochrome frames.
+//!
+//! The sky background and its noise are estimated by iterative sigma clipping of the
+//! frame, pixels more than a threshold number of noise standard deviations above the
+//! background are grouped into 8-connected components, and each component large
+//! enough to be a source is measured from its background-subtracted pixels.
+//!
+//! Shapes are measured from the intensity-weighted second moments in a window around
+//! the centroid, sized from the moments of the detected pixels so that the wings of
+//! the profile below the threshold are included.  The FWHM is that of a Gaussian
+//! with the same moments.
+//!
+
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+/// Conversion from Gaussian sigma to full width at half maximum
+const SIGMA_TO_FWHM: f64 = 2.354_820_045;
+
+/// Options for source extraction
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractOptions {
+    /// Detection threshold, in standard deviations of the background noise
+    pub threshold: f64,
+    /// Minimum number of connected pixels above the threshold
+    pub min_pixels: usize,
+    /// Pixel value at or above which a source is flagged saturated; defaults to the
+    /// maximum value of the pixel type
+    pub saturation: Option<f64>,
+    /// Background level and noise standard deviation, if known; otherwise estimated
+    /// from the frame
+    pub background: Option<(f64, f64)>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            threshold: 5.0,
+            min_pixels: 5,
+            saturation: None,
+            background: None,
+        }
+    }
+}
+
+/// A source measured in a frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    /// Centroid column, in pixels from the centre of the first pixel
+    pub x: f64,
+    /// Centroid row
+    pub y: f64,
+    /// Background-subtracted sum over the window around the source
+    pub flux: f64,
+    /// Highest background-subtracted pixel value
+    pub peak: f64,
+    /// Number of connected pixels above the threshold
+    pub npix: usize,
+    /// Full width at half maximum of a Gaussian with the same second moments
+    pub fwhm: f64,
+    /// `1 - b / a`, where `a` and `b` are the major and minor axes
+    pub ellipticity: f64,
+    /// Angle of the major axis from the x axis towards the y axis, radians
+    pub theta: f64,
+    /// Whether any pixel of the source reaches the saturation level
+    pub saturated: bool,
+}
+
+/// Background level and noise standard deviation of a frame by iterative
+/// 3-sigma clipping about the median.
+///
+/// # Arguments
+/// `frame` - The frame.
+///
+/// # Returns
+/// The clipped median and standard deviation.
+///
+pub fn estimate_background<T: MonoPixel>(frame: &MonoFrameData<T>) -> (f64, f64) {
+    let mut values: Vec<f64> = frame.to_f64_values();
+    sigma_clip(&mut values, 3.0, 5)
+}
+
+/// Median and standard deviation of values after iterative clipping at `k` standard
+/// deviations about the median; reorders the values
+pub(crate) fn sigma_clip(values: &mut [f64], k: f64, iterations: u32) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    values.sort_unstable_by(f64::total_cmp);
+    let mut kept = &values[..];
+    let median = |v: &[f64]| {
+        let n = v.len();
+        if n % 2 == 1 {
+            v[n / 2]
+        } else {
+            (v[n / 2 - 1] + v[n / 2]) / 2.0
+        }
+    };
+    let std = |v: &[f64]| {
+        let m = v.iter().sum::<f64>() / v.len() as f64;
+        (v.iter().map(|x| (x - m) * (x - m)).sum::<f64>() / v.len() as f64).sqrt()
+    };
+    for _ in 0..iterations {
+        let (center, sigma) = (median(kept), std(kept));
+        let start = kept.partition_point(|v| *v < center - k * sigma);
+        let end = kept.partition_point(|v| *v <= center + k * sigma);
+        if (start, end) == (0, kept.len()) || end <= start {
+            break;
+        }
+        kept = &kept[start..end];
+    }
+    (median(kept), std(kept))
+}
+
+/// Intensity-weighted moments of the background-subtracted values in a region
+struct Moments {
+    sum: f64,
+    x: f64,
+    y: f64,
+    xx: f64,
+    yy: f64,
+    xy: f64,
+}
+
+impl Moments {
+    fn new(pixels: impl Iterator<Item = (f64, f64, f64)>) -> Option<Self> {
+        let (mut s, mut sx, mut sy) = (0.0, 0.0, 0.0);
+        let points: Vec<(f64, f64, f64)> = pixels.filter(|(_, _, w)| *w > 0.0).collect();
+        for (x, y, w) in points.iter() {
+            s += w;
+            sx += w * x;
+            sy += w * y;
+        }
+        if s <= 0.0 {
+            return None;
+        }
+        let (cx, cy) = (sx / s, sy / s);
+        let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+        for (x, y, w) in points.iter() {
+            xx += w * (x - cx) * (x - cx);
+            yy += w * (y - cy) * (y - cy);
+            xy += w * (x - cx) * (y - cy);
+        }
+        Some(Moments {
+            sum: s,
+            x: cx,
+            y: cy,
+            xx: xx / s,
+            yy: yy / s,
+            xy: xy / s,
+        })
+    }
+
+    /// Variances along the major and minor axes, and the major axis angle
+    fn axes(&self) -> (f64, f64, f64) {
+        let mean = (self.xx + self.yy) / 2.0;
+        let diff = (((self.xx - self.yy) / 2.0).powi(2) + self.xy * self.xy).sqrt();
+        let theta = 0.5 * (2.0 * self.xy).atan2(self.xx - self.yy);
+        (mean + diff, (mean - diff).max(0.0), theta)
+    }
+}
+
+/// Detect and measure sources in a frame.
+///
+/// # Arguments
+/// `frame` - The frame to search.
+/// `options` - Detection threshold and related options.
+///
+/// # Returns
+/// The sources found, brightest (highest flux) first.
+///
+pub fn extract_sources<T: MonoPixel>(
+    frame: &MonoFrameData<T>,
+    options: &ExtractOptions,
+) -> Vec<Source> {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let values: Vec<f64> = frame.to_f64_values();
+    let (bg, rms) = options
+        .background
+        .unwrap_or_else(|| sigma_clip(&mut values.clone(), 3.0, 5));
+    let threshold = bg + options.threshold * rms;
+    let saturation = options
+        .saturation
+        .unwrap_or_else(|| T::max_value().to_f64().unwrap_or(f64::INFINITY));
+
+    // Label 8-connected components of pixels above the threshold
+    let mut visited: Vec<bool> = values.iter().map(|v| *v <= threshold).collect();
+    let mut sources = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..values.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        stack.push(start);
+        let mut component = Vec::new();
+        while let Some(idx) = stack.pop() {
+            component.push(idx);
+            let (x, y) = (idx % w, idx / w);
+            for ny in y.saturating_sub(1)..(y + 2).min(h) {
+                for nx in x.saturating_sub(1)..(x + 2).min(w) {
+                    let n = ny * w + nx;
+                    if !visited[n] {
+                        visited[n] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+        if component.len() < options.min_pixels {
+            continue;
+        }
+        if let Some(source) = measure(&values, w, h, &component, bg, saturation) {
+            sources.push(source);
+        }
+    }
+    sources.sort_by(|a, b| b.flux.total_cmp(&a.flux));
+    sources
+}
+
+/// Measure a source from the pixel indices of its detected component
+fn measure(
+    values: &[f64],
+    w: usize,
+    h: usize,
+    component: &[usize],
+    bg: f64,
+    saturation: f64,
+) -> Option<Source> {
+    let pixel = |idx: usize| ((idx % w) as f64, (idx / w) as f64, values[idx] - bg);
+    let detected = Moments::new(component.iter().map(|i| pixel(*i)))?;
+    let (peak, saturated) = component.iter().fold((f64::MIN, false), |(p, s), i| {
+        (p.max(values[*i] - bg), s || values[*i] >= saturation)
+    });
+
+    // Window of four times the detected major-axis sigma, which the thresholded
+    // moments underestimate, and never smaller than the detected component
+    let (major, _, _) = detected.axes();
+    let (mut x0, mut x1, mut y0, mut y1) = (w, 0, h, 0);
+    for i in component {
+        let (x, y) = (i % w, i / w);
+        (x0, x1, y0, y1) = (x0.min(x), x1.max(x), y0.min(y), y1.max(y));
+    }
+    let r = (4.0 * major.sqrt()).max(2.0);
+    let x0 = (x0 as f64).min(detected.x - r).max(0.0) as usize;
+    let y0 = (y0 as f64).min(detected.y - r).max(0.0) as usize;
+    let x1 = ((x1 as f64).max(detected.x + r).ceil() as usize).min(w - 1);
+    let y1 = ((y1 as f64).max(detected.y + r).ceil() as usize).min(h - 1);
+    let window = Moments::new(
+        (y0..=y1)
+            .flat_map(|y| (x0..=x1).map(move |x| y * w + x))
+            .map(pixel),
+    )
+    .unwrap_or(detected);
+
+    let (a2, b2, theta) = window.axes();
+    Some(Source {
+        x: window.x,
+        y: window.y,
+        flux: window.sum,
+        peak,
+        npix: component.len(),
+        fwhm: SIGMA_TO_FWHM * ((a2 + b2) / 2.0).sqrt(),
+        ellipticity: if a2 > 0.0 {
+            1.0 - (b2 / a2).sqrt()
+        } else {
+            0.0
+        },
+        theta,
+        saturated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::star_field;
+
+    #[test]
+    fn test_extract_sources() {
+        let frame = star_field(
+            96,
+            64,
+            &[
+                (20.3, 15.7, 2000.0, 2.0, 2.0),
+                (70.0, 40.0, 90000.0, 2.0, 2.0),
+                (40.6, 50.2, 1000.0, 3.0, 1.5),
+            ],
+            1,
+        );
+        let (bg, rms) = estimate_background(&frame);
+        assert!((bg - 1000.0).abs() < 1.0 && rms > 2.0 && rms < 4.0);
+
+        let sources = extract_sources(&frame, &ExtractOptions::default());
+        assert_eq!(sources.len(), 3);
+        // Sorted by flux: the saturated star first
+        assert!(sources[0].saturated && !sources[1].saturated);
+        assert!((sources[0].x - 70.0).abs() < 0.1);
+
+        let round = &sources[1];
+        assert!((round.x - 20.3).abs() < 0.05 && (round.y - 15.7).abs() < 0.05);
+        assert!(
+            (round.fwhm - 2.0 * SIGMA_TO_FWHM).abs() < 0.3,
+            "{:?}",
+            round
+        );
+        assert!(round.ellipticity < 0.05);
+        assert!((round.peak - 2000.0).abs() < 200.0);
+        let expected_flux = 2000.0 * 2.0 * std::f64::consts::PI * 4.0;
+        assert!(
+            (round.flux / expected_flux - 1.0).abs() < 0.05,
+            "{:?}",
+            round
+        );
+
+        let elongated = &sources[2];
+        assert!((elongated.ellipticity - 0.5).abs() < 0.1, "{:?}", elongated);
+        assert!(elongated.theta.abs() < 0.1);
+
+        let strict = ExtractOptions {
+            threshold: 400.0,
+            ..Default::default()
+        };
+        assert_eq!(extract_sources(&frame, &strict).len(), 2);
+    }
+}
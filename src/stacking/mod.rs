@@ -0,0 +1,340 @@
+//!
+//! Stacking of monochrome frames.
+//!
+//! [`stack`] combines a set of frames of the same size pixel-by-pixel with one of
+//! the [`StackMethod`]s, computing in floating point and rounding the result back to
+//! the pixel type.  [`StreamingStack`] accumulates frames one at a time for the mean,
+//! minimum and maximum, holding sums in 128-bit integers so long stacks cannot
+//! overflow, even of 64-bit frames.
+//!
+
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+use rgb::Gray;
+
+/// Errors stacking frames
+#[derive(Debug, thiserror::Error)]
+pub enum StackError {
+    #[error("No frames to stack")]
+    NoFrames,
+    #[error("Frame size {found:?} does not match stack size {expected:?}")]
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+}
+
+/// How the values of a pixel are combined across frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackMethod {
+    Mean,
+    Median,
+    /// Mean after iteratively rejecting values more than `low` standard deviations
+    /// below or `high` standard deviations above the median
+    SigmaClip {
+        low: f64,
+        high: f64,
+        iterations: u32,
+    },
+    Min,
+    Max,
+    /// Mean of the values between the `low` and `high` percentiles (0-100)
+    Percentile {
+        low: f64,
+        high: f64,
+    },
+}
+
+/// Median of sorted values
+fn sorted_median(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+impl StackMethod {
+    /// Combine the values of one pixel, reordering them
+    fn combine(&self, values: &mut [f64]) -> f64 {
+        match *self {
+            StackMethod::Mean => mean(values),
+            StackMethod::Min => values.iter().fold(f64::INFINITY, |a, b| a.min(*b)),
+            StackMethod::Max => values.iter().fold(f64::NEG_INFINITY, |a, b| a.max(*b)),
+            StackMethod::Median => {
+                values.sort_unstable_by(f64::total_cmp);
+                sorted_median(values)
+            }
+            StackMethod::SigmaClip {
+                low,
+                high,
+                iterations,
+            } => {
+                values.sort_unstable_by(f64::total_cmp);
+                let mut kept = &values[..];
+                for _ in 0..iterations {
+                    if kept.len() < 3 {
+                        break;
+                    }
+                    let center = sorted_median(kept);
+                    let m = mean(kept);
+                    let std = (kept.iter().map(|v| (v - m) * (v - m)).sum::<f64>()
+                        / (kept.len() - 1) as f64)
+                        .sqrt();
+                    // Values are sorted, so the kept values are a contiguous range
+                    let start = kept.partition_point(|v| *v < center - low * std);
+                    let end = kept.partition_point(|v| *v <= center + high * std);
+                    if (start, end) == (0, kept.len()) {
+                        break;
+                    }
+                    kept = &kept[start..end];
+                }
+                mean(kept)
+            }
+            StackMethod::Percentile { low, high } => {
+                values.sort_unstable_by(f64::total_cmp);
+                let n = values.len() as f64;
+                let start = (n * low / 100.0).floor().max(0.0) as usize;
+                let end = ((n * high / 100.0).ceil() as usize).min(values.len());
+                if end <= start {
+                    sorted_median(values)
+                } else {
+                    mean(&values[start..end])
+                }
+            }
+        }
+    }
+}
+
+fn check_size(expected: (u32, u32), found: (u32, u32)) -> Result<(), StackError> {
+    if expected != found {
+        return Err(StackError::SizeMismatch { expected, found });
+    }
+    Ok(())
+}
+
+/// Round a floating-point value to the nearest value of the pixel type
+fn to_pixel<T: MonoPixel>(v: f64) -> Gray<T> {
+    let v = v.round().clamp(
+        T::min_value().to_f64().unwrap(),
+        T::max_value().to_f64().unwrap(),
+    );
+    Gray::new(T::from(v).unwrap_or(T::zero()))
+}
+
+/// Combine frames of the same size pixel-by-pixel.
+///
+/// # Arguments
+/// `frames` - The frames to stack.
+/// `method` - How the values of each pixel are combined.
+///
+/// # Returns
+/// The stacked frame, or an error if no frames were given or their sizes differ.
+///
+pub fn stack<T: MonoPixel>(
+    frames: &[MonoFrameData<T>],
+    method: StackMethod,
+) -> Result<MonoFrameData<T>, StackError> {
+    let first = frames.first().ok_or(StackError::NoFrames)?;
+    let size = (first.width, first.height);
+    for f in frames.iter() {
+        check_size(size, (f.width, f.height))?;
+    }
+    let mut values = vec![0f64; frames.len()];
+    let data = (0..first.data.len())
+        .map(|i| {
+            values
+                .iter_mut()
+                .zip(frames.iter())
+                .for_each(|(v, f)| *v = f.data[i].value().to_f64().unwrap_or(0.0));
+            to_pixel(method.combine(&mut values))
+        })
+        .collect();
+    Ok(MonoFrameData {
+        width: size.0,
+        height: size.1,
+        data,
+    })
+}
+
+/// Incremental stack of frames, tracking the sum, minimum and maximum of each pixel
+#[derive(Debug, Clone)]
+pub struct StreamingStack<T>
+where
+    T: MonoPixel,
+{
+    width: u32,
+    height: u32,
+    count: usize,
+    sum: Vec<i128>,
+    min: Vec<T>,
+    max: Vec<T>,
+}
+
+impl<T> StreamingStack<T>
+where
+    T: MonoPixel,
+{
+    /// Create an empty stack for frames of the given size
+    pub fn new(width: u32, height: u32) -> Self {
+        let npix = width as usize * height as usize;
+        StreamingStack {
+            width,
+            height,
+            count: 0,
+            sum: vec![0; npix],
+            min: vec![T::max_value(); npix],
+            max: vec![T::min_value(); npix],
+        }
+    }
+
+    /// Add a frame to the stack.
+    ///
+    /// # Arguments
+    /// `frame` - The frame to add; it must match the size of the stack.
+    ///
+    /// # Returns
+    /// An empty Result if the frame was added, or an error if its size differs.
+    ///
+    pub fn add(&mut self, frame: &MonoFrameData<T>) -> Result<(), StackError> {
+        check_size((self.width, self.height), (frame.width, frame.height))?;
+        for (i, p) in frame.data.iter().enumerate() {
+            let v = p.value();
+            // Only u128 values beyond i128 saturate
+            let v128 = v.to_i128().unwrap_or(i128::MAX);
+            self.sum[i] = self.sum[i].saturating_add(v128);
+            self.min[i] = self.min[i].min(v);
+            self.max[i] = self.max[i].max(v);
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Number of frames added
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Per-pixel sums of the frames added, saturating at the range of i128
+    pub fn sum(&self) -> &[i128] {
+        &self.sum
+    }
+
+    fn frame(&self, data: Vec<Gray<T>>) -> Result<MonoFrameData<T>, StackError> {
+        if self.count == 0 {
+            return Err(StackError::NoFrames);
+        }
+        Ok(MonoFrameData {
+            width: self.width,
+            height: self.height,
+            data,
+        })
+    }
+
+    /// Mean of the frames added, rounded to the pixel type
+    pub fn mean(&self) -> Result<MonoFrameData<T>, StackError> {
+        let n = self.count.max(1) as i128;
+        let mean = |s: i128| {
+            // Rounded half away from zero
+            let m = s.saturating_add(s.signum() * (n / 2)) / n;
+            let clamped = if m < 0 {
+                T::min_value()
+            } else {
+                T::max_value()
+            };
+            Gray::new(T::from(m).unwrap_or(clamped))
+        };
+        self.frame(self.sum.iter().map(|s| mean(*s)).collect())
+    }
+
+    /// Per-pixel minimum of the frames added
+    pub fn min(&self) -> Result<MonoFrameData<T>, StackError> {
+        self.frame(self.min.iter().map(|v| Gray::new(*v)).collect())
+    }
+
+    /// Per-pixel maximum of the frames added
+    pub fn max(&self) -> Result<MonoFrameData<T>, StackError> {
+        self.frame(self.max.iter().map(|v| Gray::new(*v)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(values: &[u16]) -> Vec<MonoFrameData<u16>> {
+        values
+            .iter()
+            .map(|v| MonoFrameData {
+                width: 2,
+                height: 1,
+                data: vec![Gray::new(*v), Gray::new(v / 2)],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stack_methods() {
+        let f = frames(&[100, 102, 98, 101, 99, 60000]);
+        let px = |method| stack(&f, method).unwrap().data[0].value();
+        assert_eq!(px(StackMethod::Mean), 10083);
+        assert_eq!(px(StackMethod::Median), 101);
+        assert_eq!(px(StackMethod::Min), 98);
+        assert_eq!(px(StackMethod::Max), 60000);
+        assert_eq!(
+            px(StackMethod::SigmaClip {
+                low: 2.0,
+                high: 2.0,
+                iterations: 5
+            }),
+            100
+        );
+        assert_eq!(
+            px(StackMethod::Percentile {
+                low: 20.0,
+                high: 80.0
+            }),
+            101
+        );
+        assert!(matches!(
+            stack::<u16>(&[], StackMethod::Mean),
+            Err(StackError::NoFrames)
+        ));
+    }
+
+    #[test]
+    fn test_streaming_stack() {
+        let mut s = StreamingStack::<u16>::new(2, 1);
+        assert!(s.mean().is_err());
+        // Sum well beyond the u16 range
+        for f in frames(&[65535; 100]) {
+            s.add(&f).unwrap();
+        }
+        s.add(&frames(&[0])[0]).unwrap();
+        assert_eq!(s.count(), 101);
+        assert_eq!(s.sum()[0], 65535 * 100);
+        assert_eq!(s.mean().unwrap().data[0].value(), 64886);
+        assert_eq!(s.min().unwrap().data[0].value(), 0);
+        assert_eq!(s.max().unwrap().data[1].value(), 32767);
+        assert!(s.add(&MonoFrameData::zeros(3, 3)).is_err());
+
+        // Values beyond the range of i64 are summed exactly
+        let mut wide = StreamingStack::<u64>::new(1, 1);
+        for v in [u64::MAX, u64::MAX - 2] {
+            wide.add(&MonoFrameData {
+                width: 1,
+                height: 1,
+                data: vec![Gray::new(v)],
+            })
+            .unwrap();
+        }
+        assert_eq!(wide.sum()[0], 2 * u64::MAX as i128 - 2);
+        assert_eq!(wide.mean().unwrap().data[0].value(), u64::MAX - 1);
+    }
+}
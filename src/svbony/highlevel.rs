@@ -11,6 +11,11 @@ pub use ll::{SVBBayerPattern, SVBCameraProperty, SVBErrorCode, SVBPixelType};
 pub use ll::{SVBControlCaps, SVBControlType};
 
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a sensor temperature reading is reused for frame metadata before the
+/// camera is queried again
+const TEMPERATURE_REFRESH: Duration = Duration::from_secs(5);
 
 #[derive(Clone)]
 pub struct SVBonyCamera {
@@ -99,13 +104,22 @@ impl SVBonyCamera {
             SVBPixelType::Raw16 => 12 * bin * bin,
             _ => 8 * bin * bin,
         };
+        let gain = self.gain().ok().map(|g| g as f64);
+        let camera_name = Some(self.info.friendly_name.clone());
         println!("bit_depth = {}", bit_depth);
         println!("exposure = {}", exposure);
         println!("npixels = {}", npixels);
         println!("pixeltype = {:?}", pixeltype);
 
+        let mut temperature = self.temperature();
+        let mut temperature_time = Instant::now();
+
         ll::start_capture(&self.id)?;
         while *self.running.lock().unwrap() {
+            if temperature_time.elapsed() >= TEMPERATURE_REFRESH {
+                temperature = self.temperature();
+                temperature_time = Instant::now();
+            }
             match bit_depth {
                 8 => {
                     let ts = self.get_frame(&mut buf8, wait_ms)?;
@@ -117,9 +131,13 @@ impl SVBonyCamera {
                         width: width as u32,
                         height: height as u32,
                     };
-                    let frame = CameraFrameType::Mono8(MonoCameraFrame::<u8>::create(
-                        exposure, ts, 8, framedata,
-                    ));
+                    let frame = CameraFrameType::Mono8(MonoCameraFrame::<u8> {
+                        gain,
+                        binning: Some(bin as u32),
+                        temperature,
+                        camera_name: camera_name.clone(),
+                        ..MonoCameraFrame::<u8>::create(exposure, ts, 8, framedata)
+                    });
                     if let Some(cb) = &self.callback {
                         cb(frame)?;
                     }
@@ -135,12 +153,13 @@ impl SVBonyCamera {
                         width: width as u32,
                         height: height as u32,
                     };
-                    let frame = CameraFrameType::Mono16(MonoCameraFrame::<u16>::create(
-                        exposure,
-                        ts,
-                        bit_depth as u8,
-                        framedata,
-                    ));
+                    let frame = CameraFrameType::Mono16(MonoCameraFrame::<u16> {
+                        gain,
+                        binning: Some(bin as u32),
+                        temperature,
+                        camera_name: camera_name.clone(),
+                        ..MonoCameraFrame::<u16>::create(exposure, ts, bit_depth as u8, framedata)
+                    });
                     if let Some(cb) = &self.callback {
                         cb(frame)?;
                     }
@@ -230,6 +249,20 @@ impl SVBonyCamera {
         self.set_control_value(SVBControlType::SVBGain, value)
     }
 
+    /// Get the current sensor temperature
+    ///
+    /// # Notes
+    ///     The SDK reports temperature in units of 0.1 degrees Celsius
+    ///
+    /// # Returns
+    ///     The sensor temperature in degrees Celsius, or None if the
+    ///     camera does not report temperature
+    pub fn temperature(&self) -> Option<f64> {
+        self.get_control_value(SVBControlType::SVBCurrentTemperature)
+            .ok()
+            .map(|t| t as f64 / 10.0)
+    }
+
     /// Get the camera properties
     ///
     /// # Returns
@@ -0,0 +1,48 @@
+//!
+//! Fixtures shared by the unit tests: reproducible noise and synthetic star fields.
+//!
+
+use crate::MonoFrameData;
+use rgb::Gray;
+
+/// Linear congruential generator for reproducible test data
+pub(crate) fn lcg(seed: u32) -> impl FnMut() -> u32 {
+    let mut seed = seed;
+    move || {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        seed
+    }
+}
+
+/// Uniform noise between -amplitude and amplitude
+pub(crate) fn uniform_noise(seed: u32, amplitude: f64) -> impl FnMut() -> f64 {
+    let mut next = lcg(seed);
+    move || ((next() >> 24) as f64 / 128.0 - 1.0) * amplitude
+}
+
+/// Frame with Gaussian stars (x, y, amplitude, sigma_x, sigma_y) on a background
+/// of 1000 with uniform noise of +/-5 from the given seed
+pub(crate) fn star_field(
+    width: u32,
+    height: u32,
+    stars: &[(f64, f64, f64, f64, f64)],
+    seed: u32,
+) -> MonoFrameData<u16> {
+    let mut noise = uniform_noise(seed, 5.0);
+    MonoFrameData {
+        width,
+        height,
+        data: (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f64, (i / width) as f64);
+                let v = stars
+                    .iter()
+                    .fold(1000.0 + noise(), |v, (sx, sy, a, wx, wy)| {
+                        let (dx, dy) = ((x - sx) / wx, (y - sy) / wy);
+                        v + a * (-(dx * dx + dy * dy) / 2.0).exp()
+                    });
+                Gray::new(v.round().clamp(0.0, 65535.0) as u16)
+            })
+            .collect(),
+    }
+}
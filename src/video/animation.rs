@@ -0,0 +1,250 @@
+//!
+//! Animated GIF and APNG export of monochrome frame sequences.
+//!
+//! Frames are rendered through `to_rgba` with the colormap of a [`ColorScale`], and
+//! stored as 8-bit indexed images whose palette is the 256 colormap entries.  Unset
+//! scale limits are taken from the minimum and maximum over all frames, so the
+//! scaling does not flicker between frames.  The delay of each frame is the time
+//! to the next frame's center of integration.
+//!
+
+use super::{scaled_rgba, ColorScale};
+use crate::FrameFileError;
+use crate::MonoCameraFrame;
+use crate::MonoPixel;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+
+/// Delay used when it cannot be derived from the timestamps
+const DEFAULT_DELAY_MS: u64 = 100;
+
+/// Frames rendered to colormap indices, with their delays
+struct IndexedFrames {
+    width: u32,
+    height: u32,
+    frames: Vec<Vec<u8>>,
+    delays_ms: Vec<u64>,
+}
+
+impl IndexedFrames {
+    fn render<T>(frames: &[MonoCameraFrame<T>], scale: &ColorScale) -> Result<Self, FrameFileError>
+    where
+        T: MonoPixel,
+    {
+        let first = frames
+            .first()
+            .ok_or_else(|| FrameFileError::Format("no frames to write".to_string()))?;
+        let (width, height) = (first.data.width, first.data.height);
+        if let Some(f) = frames
+            .iter()
+            .find(|f| (f.data.width, f.data.height) != (width, height))
+        {
+            return Err(FrameFileError::Format(format!(
+                "frame size {}x{} does not match animation size {}x{}",
+                f.data.width, f.data.height, width, height
+            )));
+        }
+
+        let limits = frames
+            .iter()
+            .map(|f| f.data.minmax())
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+            .unwrap();
+
+        // First occurrence of each colour, in case the colormap repeats entries
+        let mut lookup = HashMap::new();
+        for (i, c) in scale.cmap.iter().enumerate().rev() {
+            lookup.insert(*c, i as u8);
+        }
+        let indexed = frames
+            .iter()
+            .map(|f| {
+                scaled_rgba(&f.data, scale, limits)
+                    .data
+                    .iter()
+                    .map(|c| lookup.get(c).copied().unwrap_or(0))
+                    .collect()
+            })
+            .collect();
+
+        let mut delays_ms: Vec<u64> = frames
+            .windows(2)
+            .map(|w| {
+                let dt =
+                    (w[1].center_of_integration - w[0].center_of_integration).num_milliseconds();
+                if dt > 0 {
+                    dt as u64
+                } else {
+                    DEFAULT_DELAY_MS
+                }
+            })
+            .collect();
+        delays_ms.push(delays_ms.last().copied().unwrap_or(DEFAULT_DELAY_MS));
+
+        Ok(IndexedFrames {
+            width,
+            height,
+            frames: indexed,
+            delays_ms,
+        })
+    }
+}
+
+/// Save a sequence of mono frames to an animated GIF file.
+///
+/// Delays are rounded to the 10 ms resolution of GIF, with a minimum of 20 ms
+/// since shorter delays are not honored by most viewers.
+///
+/// # Arguments
+/// `filename` - The name of the file to save the animation to.
+/// `frames` - The frames, all of the same size.
+/// `scale` - Scaling, gamma and colormap used to render the frames.
+///
+/// # Returns
+/// An empty Result if the save was successful, or an error if the save failed.
+///
+pub fn save_frames_to_gif<T>(
+    filename: &str,
+    frames: &[MonoCameraFrame<T>],
+    scale: &ColorScale,
+) -> Result<(), FrameFileError>
+where
+    T: MonoPixel,
+{
+    let indexed = IndexedFrames::render(frames, scale)?;
+    let (width, height) = match (u16::try_from(indexed.width), u16::try_from(indexed.height)) {
+        (Ok(w), Ok(h)) => (w, h),
+        _ => {
+            return Err(FrameFileError::Unsupported(format!(
+                "{}x{} frames exceed the GIF size limit",
+                indexed.width, indexed.height
+            )))
+        }
+    };
+
+    let palette: Vec<u8> = scale.cmap.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+    let file = BufWriter::new(File::create(filename)?);
+    let mut encoder = gif::Encoder::new(file, width, height, &palette)?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for (pixels, delay_ms) in indexed.frames.into_iter().zip(indexed.delays_ms) {
+        let mut frame = gif::Frame::from_indexed_pixels(width, height, pixels, None);
+        frame.delay = (delay_ms.div_ceil(10)).clamp(2, u16::MAX as u64) as u16;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+/// Save a sequence of mono frames to an animated PNG (APNG) file.
+///
+/// # Arguments
+/// `filename` - The name of the file to save the animation to.
+/// `frames` - The frames, all of the same size.
+/// `scale` - Scaling, gamma and colormap used to render the frames.
+///
+/// # Returns
+/// An empty Result if the save was successful, or an error if the save failed.
+///
+pub fn save_frames_to_apng<T>(
+    filename: &str,
+    frames: &[MonoCameraFrame<T>],
+    scale: &ColorScale,
+) -> Result<(), FrameFileError>
+where
+    T: MonoPixel,
+{
+    let indexed = IndexedFrames::render(frames, scale)?;
+
+    let file = BufWriter::new(File::create(filename)?);
+    let mut encoder = png::Encoder::new(file, indexed.width, indexed.height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        scale
+            .cmap
+            .iter()
+            .flat_map(|c| [c.r, c.g, c.b])
+            .collect::<Vec<u8>>(),
+    );
+    encoder.set_animated(indexed.frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for (pixels, delay_ms) in indexed.frames.iter().zip(indexed.delays_ms) {
+        writer.set_frame_delay(delay_ms.min(u16::MAX as u64) as u16, 1000)?;
+        writer.write_image_data(pixels)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colormap;
+    use crate::MonoFrameData;
+
+    fn test_frames() -> Vec<MonoCameraFrame<u16>> {
+        let t0 = chrono::Utc::now();
+        (0..4)
+            .map(|i| {
+                let data = MonoFrameData::<u16> {
+                    width: 8,
+                    height: 4,
+                    data: (0..32).map(|x| rgb::Gray::new(x * 10 + i * 100)).collect(),
+                };
+                let t = t0 + chrono::Duration::milliseconds(i as i64 * 250);
+                MonoCameraFrame::create(0.01, t, 12, data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_gif_export() {
+        let filename = std::env::temp_dir().join("camera_test_anim.gif");
+        let filename = filename.to_str().unwrap();
+        let scale = ColorScale {
+            minscale: None,
+            maxscale: None,
+            gamma: 1.0,
+            cmap: colormap::hot(),
+        };
+        save_frames_to_gif(filename, &test_frames(), &scale).unwrap();
+        let bytes = std::fs::read(filename).unwrap();
+        assert_eq!(&bytes[0..6], b"GIF89a");
+        assert_eq!(u16::from_le_bytes([bytes[6], bytes[7]]), 8);
+        // Graphic control extensions carry the 250 ms delay
+        let gce = bytes
+            .windows(3)
+            .position(|w| w == [0x21, 0xf9, 0x04])
+            .unwrap();
+        assert_eq!(u16::from_le_bytes([bytes[gce + 4], bytes[gce + 5]]), 25);
+        assert_eq!(*bytes.last().unwrap(), 0x3b);
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_apng_export() {
+        let filename = std::env::temp_dir().join("camera_test_anim.png");
+        let filename = filename.to_str().unwrap();
+        let scale = ColorScale {
+            minscale: Some(0),
+            maxscale: Some(1000),
+            gamma: 1.0,
+            cmap: colormap::grayscale(),
+        };
+        let frames = test_frames();
+        save_frames_to_apng(filename, &frames, &scale).unwrap();
+
+        let decoder = png::Decoder::new(File::open(filename).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let control = reader.info().animation_control().unwrap();
+        assert_eq!(control.num_frames, 4);
+        let mut buf = vec![0u8; reader.output_buffer_size()];
+        reader.next_frame(&mut buf).unwrap();
+        let fc = reader.info().frame_control().unwrap();
+        assert_eq!((fc.delay_num, fc.delay_den), (250, 1000));
+        // Index 255 * 310 / 1000 for the last pixel of the first frame
+        assert_eq!(buf[31], 79);
+        let _ = std::fs::remove_file(filename);
+    }
+}
@@ -0,0 +1,265 @@
+//!
+//! Uncompressed AVI (RIFF) writer.
+//!
+//! Grayscale video is stored as 8-bit DIB frames with a grayscale palette, colour
+//! video as 24-bit BGR DIB frames; both are bottom-up with rows padded to 4 bytes.
+//! Files are limited to the 4 GiB size of a RIFF (AVI 1.0) file.
+//!
+
+use super::check_size;
+use super::{ColorScale, VideoColor, VideoFrame};
+use crate::CameraFrameType;
+use crate::FrameFileError;
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+/// AVIF_HASINDEX in the main header, AVIIF_KEYFRAME in the index
+const AVI_FLAG_INDEX: u32 = 0x10;
+
+/// Writer for uncompressed AVI files
+pub struct AviWriter {
+    file: BufWriter<File>,
+    width: u32,
+    height: u32,
+    color: VideoColor,
+    /// Offsets of the chunks in the "movi" list, relative to the "movi" fourcc
+    index: Vec<u32>,
+    /// Byte offset of the "movi" list size
+    movi_size_pos: u64,
+    /// Byte offsets of the frame counts in the main and stream headers
+    count_pos: [u64; 2],
+    /// Bytes written so far
+    len: u64,
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+/// Patch a little-endian u32 previously written into `buf` at `pos`
+fn patch_u32(buf: &mut [u8], pos: usize, v: u32) {
+    buf[pos..pos + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+impl AviWriter {
+    /// Create a new AVI file and write its headers.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the video to.
+    /// `width` - Width of the frames in pixels.
+    /// `height` - Height of the frames in pixels.
+    /// `fps` - Playback rate in frames per second.
+    /// `color` - Pixel format of the video.
+    ///
+    /// # Returns
+    /// The writer, or an error if the file could not be created.
+    ///
+    pub fn create(
+        filename: &str,
+        width: u32,
+        height: u32,
+        fps: f64,
+        color: VideoColor,
+    ) -> Result<Self, FrameFileError> {
+        if fps <= 0.0 || !fps.is_finite() {
+            return Err(FrameFileError::Format(format!(
+                "invalid frame rate {}",
+                fps
+            )));
+        }
+        let (bits, palette) = match color {
+            VideoColor::Gray => (8, 256),
+            VideoColor::RGB => (24, 0),
+        };
+        let frame_size = Self::row_size(width, color) * height;
+        let rate = (fps * 1000.0).round() as u32;
+
+        let mut h = Vec::new();
+        h.extend_from_slice(b"RIFF");
+        put_u32(&mut h, 0);
+        h.extend_from_slice(b"AVI LIST");
+        let hdrl_size_pos = h.len();
+        put_u32(&mut h, 0);
+        h.extend_from_slice(b"hdrl");
+
+        // Main AVI header
+        h.extend_from_slice(b"avih");
+        put_u32(&mut h, 56);
+        put_u32(&mut h, (1.0e6 / fps).round() as u32);
+        put_u32(&mut h, (frame_size as f64 * fps) as u32);
+        put_u32(&mut h, 0);
+        put_u32(&mut h, AVI_FLAG_INDEX);
+        let total_frames_pos = h.len();
+        put_u32(&mut h, 0);
+        put_u32(&mut h, 0);
+        put_u32(&mut h, 1);
+        put_u32(&mut h, frame_size);
+        put_u32(&mut h, width);
+        put_u32(&mut h, height);
+        h.extend_from_slice(&[0u8; 16]);
+
+        // Stream list: video stream header and bitmap format
+        h.extend_from_slice(b"LIST");
+        let strl_size_pos = h.len();
+        put_u32(&mut h, 0);
+        h.extend_from_slice(b"strlstrh");
+        put_u32(&mut h, 56);
+        h.extend_from_slice(b"vidsDIB ");
+        put_u32(&mut h, 0);
+        put_u16(&mut h, 0);
+        put_u16(&mut h, 0);
+        put_u32(&mut h, 0);
+        put_u32(&mut h, 1000);
+        put_u32(&mut h, rate);
+        put_u32(&mut h, 0);
+        let length_pos = h.len();
+        put_u32(&mut h, 0);
+        put_u32(&mut h, frame_size);
+        put_u32(&mut h, u32::MAX);
+        put_u32(&mut h, 0);
+        put_u16(&mut h, 0);
+        put_u16(&mut h, 0);
+        put_u16(&mut h, width as u16);
+        put_u16(&mut h, height as u16);
+
+        h.extend_from_slice(b"strf");
+        put_u32(&mut h, 40 + 4 * palette);
+        put_u32(&mut h, 40);
+        put_u32(&mut h, width);
+        put_u32(&mut h, height);
+        put_u16(&mut h, 1);
+        put_u16(&mut h, bits);
+        put_u32(&mut h, 0);
+        put_u32(&mut h, frame_size);
+        put_u32(&mut h, 0);
+        put_u32(&mut h, 0);
+        put_u32(&mut h, palette);
+        put_u32(&mut h, 0);
+        for i in 0..palette {
+            h.extend_from_slice(&[i as u8, i as u8, i as u8, 0]);
+        }
+
+        let strl_size = (h.len() - strl_size_pos - 4) as u32;
+        patch_u32(&mut h, strl_size_pos, strl_size);
+        let hdrl_size = (h.len() - hdrl_size_pos - 4) as u32;
+        patch_u32(&mut h, hdrl_size_pos, hdrl_size);
+
+        h.extend_from_slice(b"LIST");
+        let movi_size_pos = h.len() as u64;
+        put_u32(&mut h, 0);
+        h.extend_from_slice(b"movi");
+
+        let mut file = BufWriter::new(File::create(filename)?);
+        file.write_all(&h)?;
+        Ok(AviWriter {
+            file,
+            width,
+            height,
+            color,
+            index: Vec::new(),
+            movi_size_pos,
+            count_pos: [total_frames_pos as u64, length_pos as u64],
+            len: h.len() as u64,
+        })
+    }
+
+    /// Size in bytes of a padded DIB row
+    fn row_size(width: u32, color: VideoColor) -> u32 {
+        let bytes = match color {
+            VideoColor::Gray => width,
+            VideoColor::RGB => width * 3,
+        };
+        bytes.next_multiple_of(4)
+    }
+
+    /// Append a frame to the video.
+    ///
+    /// # Arguments
+    /// `frame` - The frame to append; it must match the video dimensions.
+    /// `scale` - Optional colour mapping applied to monochrome frames.
+    ///
+    /// # Returns
+    /// An empty Result if the write was successful, or an error if the write failed.
+    ///
+    pub fn write_frame(
+        &mut self,
+        frame: &CameraFrameType,
+        scale: Option<&ColorScale>,
+    ) -> Result<(), FrameFileError> {
+        let frame = VideoFrame::new(frame, self.color, scale);
+        check_size(&frame, self.width, self.height)?;
+
+        let row_size = Self::row_size(self.width, self.color) as usize;
+        let mut bytes = vec![0u8; row_size * self.height as usize];
+        // DIB rows are stored bottom-up
+        for (row, out) in bytes.chunks_exact_mut(row_size).rev().enumerate() {
+            let start = row * self.width as usize;
+            let end = start + self.width as usize;
+            match &frame {
+                VideoFrame::Gray(f) => f.data[start..end]
+                    .iter()
+                    .zip(out.iter_mut())
+                    .for_each(|(p, o)| *o = p.value()),
+                VideoFrame::Rgb(f) => f.data[start..end]
+                    .iter()
+                    .zip(out.chunks_exact_mut(3))
+                    .for_each(|(p, o)| o.copy_from_slice(&[p.b, p.g, p.r])),
+            }
+        }
+
+        // Frame chunk, index entry and end-of-file index must fit in the RIFF size
+        let total = self.len + 8 + bytes.len() as u64 + 8 + 16 * (self.index.len() as u64 + 1);
+        if total > u32::MAX as u64 {
+            return Err(FrameFileError::Unsupported(
+                "AVI file would exceed the 4 GiB RIFF size limit".to_string(),
+            ));
+        }
+        self.index.push((self.len - self.movi_size_pos - 4) as u32);
+        self.file.write_all(b"00db")?;
+        self.file.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.file.write_all(&bytes)?;
+        self.len += 8 + bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Write the frame index and finalize the headers.
+    ///
+    /// # Returns
+    /// An empty Result if the write was successful, or an error if the write failed.
+    ///
+    pub fn finish(mut self) -> Result<(), FrameFileError> {
+        let movi_size = (self.len - self.movi_size_pos - 4) as u32;
+        let frame_size = Self::row_size(self.width, self.color) * self.height;
+
+        let mut idx = Vec::with_capacity(8 + 16 * self.index.len());
+        idx.extend_from_slice(b"idx1");
+        put_u32(&mut idx, 16 * self.index.len() as u32);
+        for offset in self.index.iter() {
+            idx.extend_from_slice(b"00db");
+            put_u32(&mut idx, AVI_FLAG_INDEX);
+            put_u32(&mut idx, *offset);
+            put_u32(&mut idx, frame_size);
+        }
+        self.file.write_all(&idx)?;
+        self.len += idx.len() as u64;
+
+        let nframes = self.index.len() as u32;
+        let patches = [
+            (4, (self.len - 8) as u32),
+            (self.movi_size_pos, movi_size),
+            (self.count_pos[0], nframes),
+            (self.count_pos[1], nframes),
+        ];
+        for (pos, value) in patches {
+            self.file.seek(SeekFrom::Start(pos))?;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+//!
+//! Quick-look video export of frame sequences.
+//!
+//! Frames are written as uncompressed AVI (8-bit paletted grayscale or 24-bit RGB)
+//! or as YUV4MPEG2 (Y4M, monochrome or 4:4:4 YCbCr), neither of which needs an
+//! external encoder.  Monochrome frames are optionally passed through `to_rgba`
+//! with a [`ColorScale`] first; otherwise 16-bit frames are scaled down to 8 bits
+//! according to the frame bit depth.
+//!
+//! Monochrome frame sequences may also be rendered through a colormap into an
+//! animated GIF or APNG, with frame delays taken from the frame timestamps.
+//!
+
+mod animation;
+mod avi;
+mod y4m;
+
+pub use animation::save_frames_to_apng;
+pub use animation::save_frames_to_gif;
+pub use avi::AviWriter;
+pub use y4m::Y4mWriter;
+
+use crate::colormap::ColorMap;
+use crate::CameraFrameType;
+use crate::FrameData;
+use crate::FrameFileError;
+use crate::MonoCameraFrame;
+use crate::MonoFrameData;
+use crate::MonoPixel;
+
+use rgb::{Gray, RGB8, RGBA8};
+
+/// Pixel format of the encoded video
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoColor {
+    /// 8-bit grayscale; colour frames are converted to luminance
+    Gray,
+    /// 8-bit-per-channel RGB
+    RGB,
+}
+
+/// Colour mapping applied to monochrome frames before encoding
+#[derive(Debug, Clone, Copy)]
+pub struct ColorScale<'a> {
+    /// Data value at the bottom of the colormap, or the frame minimum if `None`
+    pub minscale: Option<u16>,
+    /// Data value at the top of the colormap, or the frame maximum if `None`
+    pub maxscale: Option<u16>,
+    pub gamma: f64,
+    pub cmap: &'a ColorMap,
+}
+
+/// A frame converted to the encoded pixel format
+pub(crate) enum VideoFrame {
+    Gray(MonoFrameData<u8>),
+    Rgb(FrameData<RGB8>),
+}
+
+impl VideoFrame {
+    /// Convert a frame to the given video pixel format
+    pub(crate) fn new(
+        frame: &CameraFrameType,
+        color: VideoColor,
+        scale: Option<&ColorScale>,
+    ) -> VideoFrame {
+        let rgb = match (frame, scale) {
+            (CameraFrameType::Mono8(f), Some(s)) => mono_to_rgb(f, s),
+            (CameraFrameType::Mono16(f), Some(s)) => mono_to_rgb(f, s),
+            (CameraFrameType::Mono8(f), None) => return VideoFrame::from_gray(&f.data, color),
+            (CameraFrameType::Mono16(f), None) => {
+                let shift = match f.bit_depth {
+                    1..=16 => f.bit_depth.saturating_sub(8),
+                    _ => 8,
+                };
+                let gray = FrameData {
+                    width: f.data.width,
+                    height: f.data.height,
+                    data: f
+                        .data
+                        .data
+                        .iter()
+                        .map(|x| Gray::new((x.value() >> shift).min(255) as u8))
+                        .collect(),
+                };
+                return VideoFrame::from_gray(&gray, color);
+            }
+            (CameraFrameType::RGB8(f), _) => f.data.clone(),
+            (CameraFrameType::RGBA8(f), _) => FrameData {
+                width: f.data.width,
+                height: f.data.height,
+                data: f.data.data.iter().map(|x| x.rgb()).collect(),
+            },
+        };
+        match color {
+            VideoColor::RGB => VideoFrame::Rgb(rgb),
+            VideoColor::Gray => VideoFrame::Gray(FrameData {
+                width: rgb.width,
+                height: rgb.height,
+                data: rgb
+                    .data
+                    .iter()
+                    .map(|p| {
+                        let y = 77 * p.r as u32 + 150 * p.g as u32 + 29 * p.b as u32;
+                        Gray::new((y >> 8) as u8)
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    fn from_gray(gray: &MonoFrameData<u8>, color: VideoColor) -> VideoFrame {
+        match color {
+            VideoColor::Gray => VideoFrame::Gray(gray.clone()),
+            VideoColor::RGB => VideoFrame::Rgb(FrameData {
+                width: gray.width,
+                height: gray.height,
+                data: gray
+                    .data
+                    .iter()
+                    .map(|x| RGB8::new(x.value(), x.value(), x.value()))
+                    .collect(),
+            }),
+        }
+    }
+
+    pub(crate) fn size(&self) -> (u32, u32) {
+        match self {
+            VideoFrame::Gray(f) => (f.width, f.height),
+            VideoFrame::Rgb(f) => (f.width, f.height),
+        }
+    }
+}
+
+/// Render a mono frame through `to_rgba`, with unset scale limits taken from
+/// `(fmin, fmax)`
+pub(crate) fn scaled_rgba<T>(
+    frame: &MonoFrameData<T>,
+    scale: &ColorScale,
+    (fmin, fmax): (T, T),
+) -> FrameData<RGBA8>
+where
+    T: MonoPixel,
+{
+    let minscale = scale.minscale.and_then(T::from).unwrap_or(fmin);
+    let maxscale = scale
+        .maxscale
+        .and_then(T::from)
+        .unwrap_or(fmax)
+        .max(minscale.saturating_add(T::one()));
+    // A single-valued range is widened so it can be scaled
+    let minscale = minscale.min(maxscale.saturating_sub(T::one()));
+    frame.to_rgba(minscale, maxscale, scale.gamma, scale.cmap)
+}
+
+fn mono_to_rgb<T>(frame: &MonoCameraFrame<T>, scale: &ColorScale) -> FrameData<RGB8>
+where
+    T: MonoPixel,
+{
+    let rgba = scaled_rgba(&frame.data, scale, frame.data.minmax());
+    FrameData {
+        width: rgba.width,
+        height: rgba.height,
+        data: rgba.data.iter().map(|x| x.rgb()).collect(),
+    }
+}
+
+/// Check that a frame matches the video dimensions
+pub(crate) fn check_size(
+    frame: &VideoFrame,
+    width: u32,
+    height: u32,
+) -> Result<(), FrameFileError> {
+    let (w, h) = frame.size();
+    if (w, h) != (width, height) {
+        return Err(FrameFileError::Format(format!(
+            "frame size {}x{} does not match video size {}x{}",
+            w, h, width, height
+        )));
+    }
+    Ok(())
+}
+
+/// Dimensions of the first frame in a sequence
+fn first_size(frames: &[CameraFrameType]) -> Result<(u32, u32), FrameFileError> {
+    let frame = frames
+        .first()
+        .ok_or_else(|| FrameFileError::Format("no frames to write".to_string()))?;
+    Ok(match frame {
+        CameraFrameType::Mono8(f) => (f.data.width, f.data.height),
+        CameraFrameType::Mono16(f) => (f.data.width, f.data.height),
+        CameraFrameType::RGB8(f) => (f.data.width, f.data.height),
+        CameraFrameType::RGBA8(f) => (f.data.width, f.data.height),
+    })
+}
+
+/// Save a sequence of frames to an uncompressed AVI file.
+///
+/// # Arguments
+/// `filename` - The name of the file to save the video to.
+/// `frames` - The frames, all of the same size.
+/// `fps` - Playback rate in frames per second.
+/// `color` - Pixel format of the video.
+/// `scale` - Optional colour mapping applied to monochrome frames.
+///
+/// # Returns
+/// An empty Result if the save was successful, or an error if the save failed.
+///
+pub fn save_frames_to_avi(
+    filename: &str,
+    frames: &[CameraFrameType],
+    fps: f64,
+    color: VideoColor,
+    scale: Option<&ColorScale>,
+) -> Result<(), FrameFileError> {
+    let (width, height) = first_size(frames)?;
+    let mut writer = AviWriter::create(filename, width, height, fps, color)?;
+    for frame in frames {
+        writer.write_frame(frame, scale)?;
+    }
+    writer.finish()
+}
+
+/// Save a sequence of frames to a YUV4MPEG2 (Y4M) file.
+///
+/// # Arguments
+/// `filename` - The name of the file to save the video to.
+/// `frames` - The frames, all of the same size.
+/// `fps` - Playback rate in frames per second.
+/// `color` - Pixel format of the video.
+/// `scale` - Optional colour mapping applied to monochrome frames.
+///
+/// # Returns
+/// An empty Result if the save was successful, or an error if the save failed.
+///
+pub fn save_frames_to_y4m(
+    filename: &str,
+    frames: &[CameraFrameType],
+    fps: f64,
+    color: VideoColor,
+    scale: Option<&ColorScale>,
+) -> Result<(), FrameFileError> {
+    let (width, height) = first_size(frames)?;
+    let mut writer = Y4mWriter::create(filename, width, height, fps, color)?;
+    for frame in frames {
+        writer.write_frame(frame, scale)?;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colormap;
+
+    fn test_frames() -> Vec<CameraFrameType> {
+        (0..3)
+            .map(|i| {
+                let data = MonoFrameData::<u16> {
+                    width: 6,
+                    height: 5,
+                    data: (0..30).map(|x| Gray::new(x * 100 + i)).collect(),
+                };
+                CameraFrameType::Mono16(MonoCameraFrame::create(0.1, chrono::Utc::now(), 12, data))
+            })
+            .collect()
+    }
+
+    fn le_u32(bytes: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_avi_export() {
+        let filename = std::env::temp_dir().join("camera_test_video.avi");
+        let filename = filename.to_str().unwrap();
+        let scale = ColorScale {
+            minscale: None,
+            maxscale: None,
+            gamma: 1.0,
+            cmap: colormap::parula(),
+        };
+        save_frames_to_avi(
+            filename,
+            &test_frames(),
+            10.0,
+            VideoColor::RGB,
+            Some(&scale),
+        )
+        .unwrap();
+        let bytes = std::fs::read(filename).unwrap();
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(le_u32(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[8..12], b"AVI ");
+        // Total frames in the main header
+        assert_eq!(le_u32(&bytes, 48), 3);
+        // Index of three 24-bit frames with 20-byte padded rows
+        let idx = bytes.len() - 8 - 48;
+        assert_eq!(&bytes[idx..idx + 4], b"idx1");
+        assert_eq!(le_u32(&bytes, idx + 4), 48);
+        assert_eq!(le_u32(&bytes, idx + 20), 100);
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_y4m_export() {
+        let filename = std::env::temp_dir().join("camera_test_video.y4m");
+        let filename = filename.to_str().unwrap();
+        let mut frames = test_frames();
+        save_frames_to_y4m(filename, &frames, 25.0, VideoColor::Gray, None).unwrap();
+        let bytes = std::fs::read(filename).unwrap();
+        let header = b"YUV4MPEG2 W6 H5 F25000:1000 Ip A1:1 Cmono\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 3 * (6 + 30));
+        // 12-bit data is scaled down to 8 bits
+        assert_eq!(bytes[header.len() + 6 + 29], (2900 >> 4) as u8);
+
+        frames.push(CameraFrameType::Mono8(MonoCameraFrame::create(
+            0.1,
+            chrono::Utc::now(),
+            8,
+            MonoFrameData::zeros(4, 4),
+        )));
+        assert!(save_frames_to_y4m(filename, &frames, 25.0, VideoColor::Gray, None).is_err());
+        let _ = std::fs::remove_file(filename);
+    }
+}
@@ -0,0 +1,126 @@
+//!
+//! YUV4MPEG2 (Y4M) writer.
+//!
+//! Grayscale video is written with the `mono` colour space, colour video as full
+//! resolution 4:4:4 YCbCr using the BT.601 studio-range matrix.
+//!
+
+use super::check_size;
+use super::{ColorScale, VideoColor, VideoFrame};
+use crate::CameraFrameType;
+use crate::FrameFileError;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Writer for YUV4MPEG2 files
+pub struct Y4mWriter {
+    file: BufWriter<File>,
+    width: u32,
+    height: u32,
+    color: VideoColor,
+}
+
+/// Convert an 8-bit RGB pixel to BT.601 studio-range YCbCr
+fn ycbcr(p: &rgb::RGB8) -> [u8; 3] {
+    let (r, g, b) = (p.r as f64, p.g as f64, p.b as f64);
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+    [y.round() as u8, cb.round() as u8, cr.round() as u8]
+}
+
+impl Y4mWriter {
+    /// Create a new Y4M file and write its header.
+    ///
+    /// # Arguments
+    /// `filename` - The name of the file to save the video to.
+    /// `width` - Width of the frames in pixels.
+    /// `height` - Height of the frames in pixels.
+    /// `fps` - Playback rate in frames per second.
+    /// `color` - Pixel format of the video.
+    ///
+    /// # Returns
+    /// The writer, or an error if the file could not be created.
+    ///
+    pub fn create(
+        filename: &str,
+        width: u32,
+        height: u32,
+        fps: f64,
+        color: VideoColor,
+    ) -> Result<Self, FrameFileError> {
+        if fps <= 0.0 || !fps.is_finite() {
+            return Err(FrameFileError::Format(format!(
+                "invalid frame rate {}",
+                fps
+            )));
+        }
+        let colorspace = match color {
+            VideoColor::Gray => "mono",
+            VideoColor::RGB => "444",
+        };
+        let mut file = BufWriter::new(File::create(filename)?);
+        writeln!(
+            file,
+            "YUV4MPEG2 W{} H{} F{}:1000 Ip A1:1 C{}",
+            width,
+            height,
+            (fps * 1000.0).round() as u64,
+            colorspace
+        )?;
+        Ok(Y4mWriter {
+            file,
+            width,
+            height,
+            color,
+        })
+    }
+
+    /// Append a frame to the video.
+    ///
+    /// # Arguments
+    /// `frame` - The frame to append; it must match the video dimensions.
+    /// `scale` - Optional colour mapping applied to monochrome frames.
+    ///
+    /// # Returns
+    /// An empty Result if the write was successful, or an error if the write failed.
+    ///
+    pub fn write_frame(
+        &mut self,
+        frame: &CameraFrameType,
+        scale: Option<&ColorScale>,
+    ) -> Result<(), FrameFileError> {
+        let frame = VideoFrame::new(frame, self.color, scale);
+        check_size(&frame, self.width, self.height)?;
+        self.file.write_all(b"FRAME\n")?;
+        match frame {
+            VideoFrame::Gray(f) => {
+                let bytes: Vec<u8> = f.data.iter().map(|x| x.value()).collect();
+                self.file.write_all(&bytes)?;
+            }
+            VideoFrame::Rgb(f) => {
+                let npix = f.data.len();
+                let mut planes = vec![0u8; npix * 3];
+                for (i, p) in f.data.iter().enumerate() {
+                    let [y, cb, cr] = ycbcr(p);
+                    planes[i] = y;
+                    planes[npix + i] = cb;
+                    planes[2 * npix + i] = cr;
+                }
+                self.file.write_all(&planes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush the video to disk.
+    ///
+    /// # Returns
+    /// An empty Result if the write was successful, or an error if the write failed.
+    ///
+    pub fn finish(mut self) -> Result<(), FrameFileError> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,415 @@
+//!
+//! Zarr v2 store writer for long image sequences.
+//!
+//! Frames are appended to a directory store containing:
+//! * `frames` - the pixel data, of shape `(n, height, width)` or
+//!   `(n, height, width, channels)`, chunked by frame and spatial tile
+//! * `timestamp` - centers of integration, `int64` microseconds since the Unix epoch,
+//!   with CF `units` so xarray decodes them as datetimes
+//! * `exposure` - exposure times in seconds, `float64`
+//! * `gain`, `temperature` - `float64`, NaN where unknown
+//!
+//! Chunks may be compressed with the numcodecs `zlib` codec.  Array metadata is
+//! rewritten after each frame, so the store is readable (e.g. by zarr-python,
+//! xarray or dask) while a capture is still running.  Bit depth, binning and camera
+//! name of the first frame are stored as attributes of the group.
+//!
+
+use crate::CameraFrame;
+use crate::FrameFileError;
+use crate::NpyPixel;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Layout and compression of a Zarr store
+#[derive(Debug, Clone, Copy)]
+pub struct ZarrOptions {
+    /// Width of a spatial chunk in pixels
+    pub tile_width: u32,
+    /// Height of a spatial chunk in pixels
+    pub tile_height: u32,
+    /// Number of frames per chunk of the timestamp and exposure arrays
+    pub time_chunk: usize,
+    /// zlib compression level (0-9), or `None` for uncompressed chunks
+    pub compression: Option<u32>,
+}
+
+impl Default for ZarrOptions {
+    fn default() -> Self {
+        ZarrOptions {
+            tile_width: 512,
+            tile_height: 512,
+            time_chunk: 1024,
+            compression: None,
+        }
+    }
+}
+
+/// Quote a string as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Format a list of values as a JSON array
+fn json_list<T: ToString>(values: &[T]) -> String {
+    let items: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Writer appending CameraFrames to a Zarr v2 store
+pub struct ZarrWriter<T>
+where
+    T: NpyPixel,
+{
+    path: PathBuf,
+    options: ZarrOptions,
+    /// Frame size, fixed by the first frame
+    size: Option<(u32, u32)>,
+    nframes: usize,
+    /// Values of the per-frame arrays in the current time chunk
+    timestamp: Vec<i64>,
+    exposure: Vec<f64>,
+    gain: Vec<f64>,
+    temperature: Vec<f64>,
+    _pixel: std::marker::PhantomData<T>,
+}
+
+impl<T> ZarrWriter<T>
+where
+    T: NpyPixel,
+{
+    /// Create a new Zarr store, replacing the arrays of any existing store at `path`.
+    ///
+    /// # Arguments
+    /// `path` - Directory of the store.
+    /// `options` - Chunk layout and compression.
+    ///
+    /// # Returns
+    /// The writer, or an error if the store could not be created.
+    ///
+    pub fn create(path: &str, options: ZarrOptions) -> Result<Self, FrameFileError> {
+        if options.tile_width == 0 || options.tile_height == 0 || options.time_chunk == 0 {
+            return Err(FrameFileError::Format(
+                "Zarr chunk sizes must be non-zero".to_string(),
+            ));
+        }
+        if options.compression.is_some_and(|level| level > 9) {
+            return Err(FrameFileError::Format(
+                "zlib compression level must be 0-9".to_string(),
+            ));
+        }
+        let path = PathBuf::from(path);
+        std::fs::create_dir_all(&path)?;
+        for name in ["frames", "timestamp", "exposure", "gain", "temperature"] {
+            let dir = path.join(name);
+            if dir.exists() {
+                std::fs::remove_dir_all(&dir)?;
+            }
+            std::fs::create_dir(&dir)?;
+        }
+        std::fs::write(path.join(".zgroup"), "{\n    \"zarr_format\": 2\n}\n")?;
+        Ok(ZarrWriter {
+            path,
+            options,
+            size: None,
+            nframes: 0,
+            timestamp: Vec::new(),
+            exposure: Vec::new(),
+            gain: Vec::new(),
+            temperature: Vec::new(),
+            _pixel: std::marker::PhantomData,
+        })
+    }
+
+    /// Number of frames written to the store
+    pub fn len(&self) -> usize {
+        self.nframes
+    }
+
+    /// Whether no frames have been written to the store
+    pub fn is_empty(&self) -> bool {
+        self.nframes == 0
+    }
+
+    fn compressor(&self) -> String {
+        match self.options.compression {
+            Some(level) => format!("{{\"id\": \"zlib\", \"level\": {}}}", level),
+            None => "null".to_string(),
+        }
+    }
+
+    /// Write a chunk file, compressing it if requested
+    fn write_chunk(&self, path: &Path, bytes: &[u8]) -> Result<(), FrameFileError> {
+        match self.options.compression {
+            Some(level) => {
+                let file = std::fs::File::create(path)?;
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(file, flate2::Compression::new(level));
+                encoder.write_all(bytes)?;
+                encoder.finish()?;
+            }
+            None => std::fs::write(path, bytes)?,
+        }
+        Ok(())
+    }
+
+    /// Write the `.zarray` and `.zattrs` metadata of an array
+    fn write_metadata(
+        &self,
+        name: &str,
+        shape: &[usize],
+        chunks: &[usize],
+        dtype: &str,
+        fill_value: &str,
+        attrs: &str,
+    ) -> Result<(), FrameFileError> {
+        let dir = self.path.join(name);
+        let zarray = format!(
+            "{{\n    \"zarr_format\": 2,\n    \"shape\": {},\n    \"chunks\": {},\n    \
+             \"dtype\": {},\n    \"compressor\": {},\n    \"fill_value\": {},\n    \
+             \"order\": \"C\",\n    \"filters\": null,\n    \"dimension_separator\": \".\"\n}}\n",
+            json_list(shape),
+            json_list(chunks),
+            json_string(dtype),
+            self.compressor(),
+            fill_value
+        );
+        std::fs::write(dir.join(".zarray"), zarray)?;
+        std::fs::write(dir.join(".zattrs"), format!("{{\n{}\n}}\n", attrs))?;
+        Ok(())
+    }
+
+    /// Write the group attributes from the first frame
+    fn write_group_attrs(&self, frame: &CameraFrame<T>) -> Result<(), FrameFileError> {
+        let mut attrs = vec![format!("    \"bit_depth\": {}", frame.bit_depth)];
+        if let Some(binning) = frame.binning {
+            attrs.push(format!("    \"binning\": {}", binning));
+        }
+        if let Some(name) = &frame.camera_name {
+            attrs.push(format!("    \"camera_name\": {}", json_string(name)));
+        }
+        std::fs::write(
+            self.path.join(".zattrs"),
+            format!("{{\n{}\n}}\n", attrs.join(",\n")),
+        )?;
+        Ok(())
+    }
+
+    /// Write the spatial chunks of frame `idx`
+    fn write_tiles(&self, idx: usize, frame: &CameraFrame<T>) -> Result<(), FrameFileError> {
+        let (width, height) = (frame.data.width as usize, frame.data.height as usize);
+        let (tw, th) = (
+            self.options.tile_width as usize,
+            self.options.tile_height as usize,
+        );
+        let mut samples = Vec::new();
+        T::write_samples(&frame.data.data, &mut samples);
+        let pixel_size = samples.len() / frame.data.data.len().max(1);
+
+        let suffix = if T::CHANNELS > 1 { ".0" } else { "" };
+        for ty in 0..height.div_ceil(th) {
+            for tx in 0..width.div_ceil(tw) {
+                // Edge chunks are padded to the full chunk size with the fill value
+                let mut chunk = vec![0u8; tw * th * pixel_size];
+                let x0 = tx * tw;
+                let ncols = tw.min(width - x0);
+                for row in 0..th.min(height - ty * th) {
+                    let src = ((ty * th + row) * width + x0) * pixel_size;
+                    let dst = row * tw * pixel_size;
+                    chunk[dst..dst + ncols * pixel_size]
+                        .copy_from_slice(&samples[src..src + ncols * pixel_size]);
+                }
+                let key = format!("{}.{}.{}{}", idx, ty, tx, suffix);
+                self.write_chunk(&self.path.join("frames").join(key), &chunk)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrite the current chunk and metadata of the per-frame arrays
+    fn write_series(&self) -> Result<(), FrameFileError> {
+        let chunk = self.options.time_chunk;
+        let key = ((self.nframes - 1) / chunk).to_string();
+        let shape = [self.nframes];
+
+        let mut bytes: Vec<u8> = self
+            .timestamp
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        bytes.resize(chunk * 8, 0);
+        self.write_chunk(&self.path.join("timestamp").join(&key), &bytes)?;
+        self.write_metadata(
+            "timestamp",
+            &shape,
+            &[chunk],
+            "<i8",
+            "0",
+            "    \"_ARRAY_DIMENSIONS\": [\"frame\"],\n    \
+             \"units\": \"microseconds since 1970-01-01T00:00:00\",\n    \
+             \"calendar\": \"proleptic_gregorian\"",
+        )?;
+
+        for (name, values) in [
+            ("exposure", &self.exposure),
+            ("gain", &self.gain),
+            ("temperature", &self.temperature),
+        ] {
+            let mut values = values.clone();
+            values.resize(chunk, f64::NAN);
+            let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+            self.write_chunk(&self.path.join(name).join(&key), &bytes)?;
+            self.write_metadata(
+                name,
+                &shape,
+                &[chunk],
+                "<f8",
+                "\"NaN\"",
+                "    \"_ARRAY_DIMENSIONS\": [\"frame\"]",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Append a frame to the store.
+    ///
+    /// # Arguments
+    /// `frame` - The frame to append; it must be the same size as the first frame.
+    ///
+    /// # Returns
+    /// An empty Result if the write was successful, or an error if the write failed.
+    ///
+    pub fn write_frame(&mut self, frame: &CameraFrame<T>) -> Result<(), FrameFileError> {
+        let (width, height) = (frame.data.width, frame.data.height);
+        match self.size {
+            None => {
+                self.size = Some((width, height));
+                self.write_group_attrs(frame)?;
+            }
+            Some((w, h)) if (w, h) != (width, height) => {
+                return Err(FrameFileError::Format(format!(
+                    "frame size {}x{} does not match store size {}x{}",
+                    width, height, w, h
+                )));
+            }
+            Some(_) => {}
+        }
+
+        self.write_tiles(self.nframes, frame)?;
+
+        if self.nframes.is_multiple_of(self.options.time_chunk) {
+            self.timestamp.clear();
+            self.exposure.clear();
+            self.gain.clear();
+            self.temperature.clear();
+        }
+        self.timestamp
+            .push(frame.center_of_integration.timestamp_micros());
+        self.exposure.push(frame.exposure);
+        self.gain.push(frame.gain.unwrap_or(f64::NAN));
+        self.temperature.push(frame.temperature.unwrap_or(f64::NAN));
+        self.nframes += 1;
+        self.write_series()?;
+
+        let mut shape = vec![self.nframes, height as usize, width as usize];
+        let mut chunks = vec![
+            1,
+            self.options.tile_height as usize,
+            self.options.tile_width as usize,
+        ];
+        let mut dims = "\"frame\", \"y\", \"x\"".to_string();
+        if T::CHANNELS > 1 {
+            shape.push(T::CHANNELS);
+            chunks.push(T::CHANNELS);
+            dims.push_str(", \"channel\"");
+        }
+        self.write_metadata(
+            "frames",
+            &shape,
+            &chunks,
+            T::DESCR,
+            "0",
+            &format!("    \"_ARRAY_DIMENSIONS\": [{}]", dims),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MonoCameraFrame;
+    use crate::MonoFrameData;
+    use std::io::Read;
+
+    #[test]
+    fn test_zarr_store() {
+        let path = std::env::temp_dir().join("camera_test_store.zarr");
+        let options = ZarrOptions {
+            tile_width: 4,
+            tile_height: 4,
+            time_chunk: 2,
+            compression: Some(6),
+        };
+        let mut writer =
+            ZarrWriter::<rgb::Gray<u16>>::create(path.to_str().unwrap(), options).unwrap();
+        for i in 0..3u16 {
+            let data = MonoFrameData::<u16> {
+                width: 6,
+                height: 5,
+                data: (0..30).map(|x| rgb::Gray::new(x + i * 100)).collect(),
+            };
+            let mut frame = MonoCameraFrame::create(0.5, chrono::Utc::now(), 12, data);
+            frame.camera_name = Some("Cam \"1\"".to_string());
+            writer.write_frame(&frame).unwrap();
+        }
+        assert!(writer
+            .write_frame(&MonoCameraFrame::create(
+                0.5,
+                chrono::Utc::now(),
+                12,
+                MonoFrameData::zeros(2, 2)
+            ))
+            .is_err());
+        assert_eq!(writer.len(), 3);
+
+        let zarray = std::fs::read_to_string(path.join("frames/.zarray")).unwrap();
+        assert!(zarray.contains("\"shape\": [3, 5, 6]"));
+        assert!(zarray.contains("\"chunks\": [1, 4, 4]"));
+        let attrs = std::fs::read_to_string(path.join(".zattrs")).unwrap();
+        assert!(attrs.contains("\"camera_name\": \"Cam \\\"1\\\"\""));
+
+        // Bottom-right tile of the last frame: one row of two pixels, then padding
+        let file = std::fs::File::open(path.join("frames/2.1.1")).unwrap();
+        let mut bytes = Vec::new();
+        flate2::read::ZlibDecoder::new(file)
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(u16::from_le_bytes([bytes[0], bytes[1]]), 228);
+        assert_eq!(u16::from_le_bytes([bytes[2], bytes[3]]), 229);
+        assert_eq!(u16::from_le_bytes([bytes[4], bytes[5]]), 0);
+
+        // Third exposure starts the second time chunk
+        let file = std::fs::File::open(path.join("exposure/1")).unwrap();
+        let mut bytes = Vec::new();
+        flate2::read::ZlibDecoder::new(file)
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(f64::from_le_bytes(bytes[0..8].try_into().unwrap()), 0.5);
+        assert!(f64::from_le_bytes(bytes[8..16].try_into().unwrap()).is_nan());
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}